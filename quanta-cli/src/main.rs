@@ -0,0 +1,323 @@
+use std::{fs, future::Future, io::{self, BufRead, Write}, path::{Path, PathBuf}, pin::Pin, process::ExitCode, sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex}, task::{Context, Poll, Waker}, time::{Duration, Instant}};
+
+use clap::{Parser, Subcommand};
+use futures::executor::block_on;
+use quanta_lang::{render::Rasterizer, repl::Repl, timer::{run_pending, Timer, YieldTimer}, Compiler};
+use quanta_parser::{error::{Error, ErrorType}, format::format_source, parse_ast};
+
+#[derive(Parser)]
+#[command(name = "quanta", about = "Check, run and format Kvanta programs")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    #[command(about = "Parse and type check programs without running them")]
+    Check {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+    #[command(about = "Run a program, optionally saving every frame it shows as a PNG")]
+    Run {
+        file: PathBuf,
+        #[arg(long, help = "Directory for frame_0000.png, frame_0001.png, ...")]
+        out: Option<PathBuf>,
+        #[arg(long, default_value_t = 300, help = "Frames to run animations for")]
+        frames: usize,
+        #[arg(long, default_value_t = 10, help = "Seconds before a program that keeps running is stopped")]
+        timeout: u64,
+    },
+    #[command(about = "Print the syntax tree of a program")]
+    Ast {
+        file: PathBuf,
+    },
+    #[command(about = "Evaluate statements and expressions interactively")]
+    Repl,
+    #[command(about = "Print programs in the canonical layout")]
+    Fmt {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        #[arg(long, help = "Overwrite the files instead of printing them")]
+        write: bool,
+    },
+}
+
+// A problem in a source file, positions are 1-based like in `Error`
+struct Diagnostic {
+    kind: &'static str,
+    message: String,
+    start: (usize, usize),
+    finish: (usize, usize),
+}
+
+impl Diagnostic {
+    fn from_error(error: &Error) -> Diagnostic {
+        let kind = match error.error_type {
+            ErrorType::ParseError => "parse error",
+            ErrorType::TypeError => "type error",
+            ErrorType::LogicError => "error",
+            ErrorType::RuntimeError => "runtime error",
+            ErrorType::LimitError => "stopped",
+        };
+        Diagnostic { kind, message: error.message.clone(), start: error.start, finish: error.finish }
+    }
+
+    // Codes as reported by `RuntimeError`
+    fn from_code(code: u32, message: String, start: (usize, usize), finish: (usize, usize)) -> Diagnostic {
+        let kind = match code {
+            1 => "parse error",
+            2 => "error",
+            3 => "type error",
+            5 => "stopped",
+            _ => "runtime error",
+        };
+        Diagnostic { kind, message, start, finish }
+    }
+
+    // `lesson.qn:3:5-3:9: type error: ...` followed by the line and a marker under the range
+    fn render(&self, path: &Path, source: &str) -> String {
+        let (row, column) = self.start;
+        if row == 0 {
+            return format!("{}: {}: {}", path.display(), self.kind, self.message);
+        }
+        let mut result = format!("{}:{}:{}-{}:{}: {}: {}", path.display(), row, column, self.finish.0, self.finish.1, self.kind, self.message);
+        if let Some(line) = source.lines().nth(row - 1) {
+            let width = if self.finish.0 == row && self.finish.1 > column { self.finish.1 - column } else { 1 };
+            result.push_str(&format!("\n{:>5} | {}\n      | {}{}", row, line, " ".repeat(column.saturating_sub(1)), "^".repeat(width)));
+        }
+        result
+    }
+}
+
+// Once armed, every tick hands control back to `run` until it releases the program again,
+// so endless animations can be stopped after enough frames.
+#[derive(Debug, Default)]
+struct Gate {
+    armed: AtomicBool,
+    waiting: Arc<Mutex<Option<Waker>>>,
+}
+
+impl Gate {
+    fn release(&self) -> bool {
+        match self.waiting.lock().unwrap().take() {
+            Some(waker) => {
+                waker.wake();
+                true
+            },
+            None => false,
+        }
+    }
+}
+
+impl Timer for Gate {
+    fn tick(&self) -> Pin<Box<dyn Future<Output = ()>>> {
+        if !self.armed.load(Ordering::Relaxed) {
+            return YieldTimer.tick();
+        }
+        Box::pin(Held { waiting: Arc::clone(&self.waiting), parked: false })
+    }
+}
+
+struct Held {
+    waiting: Arc<Mutex<Option<Waker>>>,
+    parked: bool,
+}
+
+impl Future for Held {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.parked {
+            return Poll::Ready(());
+        }
+        self.parked = true;
+        *self.waiting.lock().unwrap() = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+fn read(path: &Path) -> Result<String, String> {
+    fs::read_to_string(path).map_err(|e| format!("{}: couldn't read the file: {}", path.display(), e))
+}
+
+fn check(files: &[PathBuf]) -> Result<(), String> {
+    let mut failed = 0;
+    for path in files {
+        let source = match read(path) {
+            Ok(source) => source,
+            Err(e) => {
+                eprintln!("{}", e);
+                failed += 1;
+                continue;
+            }
+        };
+        if let Err(error) = Compiler::new().check(&source) {
+            eprintln!("{}", Diagnostic::from_error(&error).render(path, &source));
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        return Err(format!("{} of {} files have errors", failed, files.len()));
+    }
+    println!("{} files ok", files.len());
+    Ok(())
+}
+
+fn run(path: &Path, out: Option<&Path>, max_frames: usize, timeout: u64) -> Result<(), String> {
+    let source = read(path)?;
+    let gate = Arc::new(Gate::default());
+    let message = block_on(Compiler::new().compile_with_timer(&source, Arc::clone(&gate) as Arc<dyn Timer>));
+    if message.error_code != 0 {
+        let error = message.get_error();
+        let diagnostic = Diagnostic::from_code(error.error_code, error.get_error_message(),
+            (error.start_row, error.start_column), (error.end_row, error.end_column));
+        return Err(diagnostic.render(path, &source));
+    }
+
+    let mut runtime = message.get_runtime();
+    let deadline = Instant::now() + Duration::from_secs(timeout);
+    gate.armed.store(true, Ordering::Relaxed);
+    runtime.execute();
+    let mut blocks = vec![];
+    let (mut frames, mut finished) = (0, false);
+    while frames < max_frames && !finished {
+        for block in runtime.get_commands() {
+            match block.get_status() {
+                0 => frames += 1,
+                2 | 3 => finished = true,
+                _ => {},
+            }
+            print!("{}", block.output());
+            blocks.push(block);
+            if frames == max_frames {
+                break;
+            }
+        }
+        if finished || Instant::now() > deadline || !gate.release() {
+            break;
+        }
+        run_pending();
+    }
+    let error = runtime.get_runtime_error();
+
+    if let Some(out) = out {
+        fs::create_dir_all(out).map_err(|e| format!("{}: couldn't create the directory: {}", out.display(), e))?;
+        let mut rasterizer = Rasterizer::new();
+        let mut pictures = rasterizer.render_blocks(&blocks).map_err(|e| e.message)?;
+        // Stopped by the timeout, keep what was drawn since the last frame
+        if !finished && frames < max_frames && error.error_code == 0 {
+            pictures.push(rasterizer.to_png().map_err(|e| e.message)?);
+        }
+        for (i, picture) in pictures.iter().enumerate() {
+            let file = out.join(format!("frame_{:04}.png", i));
+            fs::write(&file, picture).map_err(|e| format!("{}: couldn't write the frame: {}", file.display(), e))?;
+        }
+        println!("{}: wrote {} frames to {}", path.display(), pictures.len(), out.display());
+    }
+
+    if error.error_code != 0 {
+        let diagnostic = Diagnostic::from_code(error.error_code, error.get_error_message(),
+            (error.start_row, error.start_column), (error.end_row, error.end_column));
+        return Err(diagnostic.render(path, &source));
+    }
+    if !finished {
+        println!("{}: stopped after {} frames, the program was still running", path.display(), frames);
+    }
+    Ok(())
+}
+
+fn ast(path: &Path) -> Result<(), String> {
+    let source = read(path)?;
+    let ast = parse_ast(&source).map_err(|e| Diagnostic::from_error(&e).render(path, &source))?;
+    println!("{:#?}", ast);
+    Ok(())
+}
+
+fn fmt(files: &[PathBuf], write: bool) -> Result<(), String> {
+    for path in files {
+        let source = read(path)?;
+        let formatted = format_source(&source).map_err(|e| Diagnostic::from_error(&e).render(path, &source))?;
+        if !write {
+            print!("{}", formatted);
+        } else if formatted != source {
+            fs::write(path, formatted).map_err(|e| format!("{}: couldn't write the file: {}", path.display(), e))?;
+            println!("formatted {}", path.display());
+        }
+    }
+    Ok(())
+}
+
+// Reads until braces are balanced, so loops and ifs can span several lines
+fn repl() -> Result<(), String> {
+    let mut repl = Repl::new();
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        let mut input = String::new();
+        let mut prompt = "> ";
+        loop {
+            print!("{}", prompt);
+            io::stdout().flush().map_err(|e| e.to_string())?;
+            match lines.next() {
+                Some(line) => input.push_str(&line.map_err(|e| e.to_string())?),
+                None => return Ok(()),
+            }
+            input.push('\n');
+            if input.matches('{').count() <= input.matches('}').count() {
+                break;
+            }
+            prompt = "... ";
+        }
+        if input.trim().is_empty() {
+            continue;
+        }
+        match repl.eval(&input) {
+            Ok(evaluation) => {
+                for command in &evaluation.commands {
+                    println!("{}", command);
+                }
+                print!("{}", evaluation.output);
+                if !evaluation.output.is_empty() && !evaluation.output.ends_with('\n') {
+                    println!();
+                }
+                if let Some(value) = evaluation.value {
+                    println!("= {}", value);
+                }
+            },
+            Err(error) => eprintln!("{}", Diagnostic::from_error(&error).render(Path::new("input"), &input)),
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let result = match &cli.command {
+        Command::Check { files } => check(files),
+        Command::Run { file, out, frames, timeout } => run(file, out.as_deref(), *frames, *timeout),
+        Command::Ast { file } => ast(file),
+        Command::Repl => repl(),
+        Command::Fmt { files, write } => fmt(files, *write),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("{}", message);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diagnostics_point_at_the_range() {
+        let source = "func main() {\n    int x = true;\n}\n";
+        let error = Compiler::new().check(source).unwrap_err();
+        let rendered = Diagnostic::from_error(&error).render(Path::new("bad.qn"), source);
+        assert_eq!(rendered, format!("bad.qn:2:5-2:18: error: {}\n    2 |     int x = true;\n      |     ^^^^^^^^^^^^^", error.message));
+    }
+}
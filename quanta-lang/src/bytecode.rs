@@ -0,0 +1,607 @@
+use std::{collections::HashMap, ops::Range};
+
+use quanta_parser::{ast::{AstBlock, AstProgram, AstStatement, BaseType, BaseValue, BaseValueType, Coords, Expression, ExpressionType, Operator, TypeName, UnaryOperator, VariableCall, Accessor}, error::Error};
+
+use crate::{execution::LIST_MUTATORS, program::Program};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variable {
+    // Slot in the current call frame
+    Local(usize),
+    // Index into `Bytecode::names`, looked up in the shared global map
+    Global(usize),
+}
+
+// Locals of a `for` loop. `count` holds the upper bound until `ForPrep` replaces it
+// with the number of iterations, `step` is only set beforehand for stepped ranges.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ForSlots {
+    pub from: usize,
+    pub count: usize,
+    pub step: usize,
+    pub index: usize,
+    pub var: usize,
+}
+
+// Locals of a `for ... in` loop, `list` holds the array being walked and `position`
+// the optional index variable
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EachSlots {
+    pub list: usize,
+    pub index: usize,
+    pub var: usize,
+    pub position: Option<usize>,
+}
+
+// A step of a field path, indices are popped from the stack
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathOp {
+    Index,
+    // Index into `Bytecode::names`
+    Field(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instr {
+    Const(usize),
+    Load(Variable),
+    Store(Variable),
+    // Pops `count` indices
+    LoadIndexed { var: Variable, name: usize, count: usize },
+    // Pops `count` indices, then the value
+    StoreIndexed { var: Variable, name: usize, count: usize },
+    // Pop the indices of the path, `StorePath` then pops the value
+    LoadPath { var: Variable, name: usize, path: Vec<PathOp> },
+    StorePath { var: Variable, name: usize, path: Vec<PathOp> },
+    // Pops a value for every field, in the order of `fields`
+    MakeStruct { name: usize, fields: Vec<usize> },
+    // Pops the elements of an array literal
+    MakeArray(usize),
+    Unary(UnaryOperator),
+    Binary(Operator),
+    Jump(usize),
+    JumpIfFalse(usize),
+    // Works out the range of a `for` loop and sets the loop variable, skips the loop if the range is empty
+    ForPrep { slots: ForSlots, stepped: bool, exclusive: bool, exit: usize },
+    ForNext { slots: ForSlots, body: usize },
+    // Same for the elements of an array, skips the loop if it is empty
+    ForEachPrep { slots: EachSlots, exit: usize },
+    ForEachNext { slots: EachSlots, body: usize },
+    // Turns an int argument into a float for a float parameter
+    ToFloat,
+    Call { function: usize, argc: usize, wants_value: bool },
+    CallBuiltin { name: usize, argc: usize, wants_value: bool },
+    // Pops the arguments and the list, pushes the taken value if wanted and then the changed list
+    ChangeList { name: usize, list: usize, argc: usize, wants_value: bool },
+    Return,
+    ReturnNone,
+    // Starts every statement, charged to the budget and a place for the debugger to stop
+    Statement,
+    // Starts every pass through a loop body, charges a step and checks the budget
+    Iteration,
+}
+
+// A named slot for the debugger. It holds the variable while the next instruction
+// to run is in `live`, `depth` counts the scopes around its declaration.
+#[derive(Debug, Clone)]
+pub struct Local {
+    pub name: String,
+    pub slot: usize,
+    pub depth: usize,
+    pub live: Range<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Chunk {
+    pub name: String,
+    pub code: Vec<Instr>,
+    pub coords: Vec<Coords>,
+    pub params: usize,
+    pub slots: usize,
+    pub locals: Vec<Local>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Bytecode {
+    pub chunks: Vec<Chunk>,
+    pub constants: Vec<BaseValue>,
+    pub names: Vec<String>,
+    pub entry: usize,
+    // Where `main` is declared, (0, 0, 0, 0) for a plain list of commands
+    pub header: Coords,
+}
+
+fn unsupported(message: String, coords: Coords) -> Error {
+    Error::logic(message, coords)
+}
+
+// Jumps out of a loop body, patched once the end of the loop is known
+#[derive(Default)]
+struct Loop {
+    // Known up front for `while`, a `for` continues at its `ForNext`
+    start: Option<usize>,
+    breaks: Vec<usize>,
+    continues: Vec<usize>,
+}
+
+struct ChunkBuilder {
+    chunk: Chunk,
+    scopes: Vec<HashMap<String, usize>>,
+    next_slot: usize,
+    loops: Vec<Loop>,
+}
+
+impl ChunkBuilder {
+    fn new(name: &str, params: &[String]) -> ChunkBuilder {
+        let mut builder = ChunkBuilder {
+            chunk: Chunk { name: name.to_string(), code: vec![], coords: vec![], params: params.len(), slots: 0, locals: vec![] },
+            scopes: vec![HashMap::new()],
+            next_slot: 0,
+            loops: vec![],
+        };
+        for param in params {
+            builder.declare(param);
+        }
+        builder
+    }
+
+    fn emit(&mut self, instr: Instr, coords: Coords) -> usize {
+        self.chunk.code.push(instr);
+        self.chunk.coords.push(coords);
+        self.chunk.code.len() - 1
+    }
+
+    fn here(&self) -> usize {
+        self.chunk.code.len()
+    }
+
+    fn patch(&mut self, at: usize, target: usize) {
+        match &mut self.chunk.code[at] {
+            Instr::Jump(t) | Instr::JumpIfFalse(t) => *t = target,
+            Instr::ForPrep { exit, .. } | Instr::ForEachPrep { exit, .. } => *exit = target,
+            _ => unreachable!("Only jumps can be patched"),
+        }
+    }
+
+    fn local(&self, name: &str) -> Option<usize> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name).copied())
+    }
+
+    fn declare(&mut self, name: &str) -> usize {
+        let slot = self.temp();
+        self.scopes.last_mut().unwrap().insert(name.to_string(), slot);
+        let (depth, start) = (self.scopes.len() - 1, self.here());
+        self.chunk.locals.push(Local { name: name.to_string(), slot, depth, live: start + 1..usize::MAX });
+        slot
+    }
+
+    fn temp(&mut self) -> usize {
+        let slot = self.next_slot;
+        self.next_slot += 1;
+        self.chunk.slots = self.chunk.slots.max(self.next_slot);
+        slot
+    }
+
+    fn push_scope(&mut self) -> usize {
+        self.scopes.push(HashMap::new());
+        self.next_slot
+    }
+
+    // Slots live for the whole call, so leaving a loop early is a plain jump
+    fn jump_out(&mut self, is_break: bool, coords: Coords) -> Result<(), Error> {
+        let start = match self.loops.last() {
+            Some(innermost) => innermost.start,
+            None => return Err(unsupported(String::from("'break' and 'continue' can only be used inside a loop"), coords)),
+        };
+        match (is_break, start) {
+            (false, Some(start)) => {
+                self.emit(Instr::Jump(start), coords);
+            },
+            _ => {
+                let at = self.emit(Instr::Jump(0), coords);
+                let innermost = self.loops.last_mut().unwrap();
+                if is_break { innermost.breaks.push(at) } else { innermost.continues.push(at) }
+            },
+        }
+        Ok(())
+    }
+
+    fn close_loop(&mut self, next: usize, end: usize) {
+        let innermost = self.loops.pop().unwrap();
+        for at in innermost.continues {
+            self.patch(at, next);
+        }
+        for at in innermost.breaks {
+            self.patch(at, end);
+        }
+    }
+
+    // Slots of a finished block are reused by its siblings
+    fn pop_scope(&mut self, mark: usize) {
+        self.close_locals();
+        self.scopes.pop();
+        self.next_slot = mark;
+    }
+
+    fn close_locals(&mut self) {
+        let (depth, end) = (self.scopes.len() - 1, self.here() + 1);
+        for local in self.chunk.locals.iter_mut().filter(|local| local.depth == depth && local.live.end == usize::MAX) {
+            local.live.end = end;
+        }
+    }
+}
+
+// Lowers a type-checked program to bytecode. Anything the VM can't run the same way
+// as the tree-walking interpreter is reported as an error, and the runtime falls back.
+pub fn lower(program: &Program) -> Result<Bytecode, Error> {
+    let mut lowering = Lowering {
+        program,
+        constants: vec![],
+        names: vec![],
+        name_ids: HashMap::new(),
+        function_ids: HashMap::new(),
+        globals: program.global_vars.keys().cloned().collect(),
+    };
+    let mut function_names: Vec<&String> = program.functions.keys().collect();
+    function_names.sort();
+    for (id, name) in function_names.iter().enumerate() {
+        lowering.function_ids.insert((*name).clone(), id);
+    }
+
+    let mut chunks = vec![];
+    for name in &function_names {
+        let (params, _, body) = &program.functions[*name];
+        let params: Vec<String> = params.iter().map(|(p, _)| p.clone()).collect();
+        chunks.push(lowering.lower_chunk(name, &params, body)?);
+    }
+
+    let (entry_block, header) = match &program.lines {
+        AstProgram::Block(block) => (block.clone(), (0, 0, 0, 0)),
+        AstProgram::Forest((funcs, _, _)) => match funcs.iter().find(|f| f.name == "main") {
+            Some(main) => (main.block.clone(), main.header),
+            None => return Err(unsupported(String::from("No main function found"), (0, 0, 0, 0))),
+        },
+    };
+    chunks.push(lowering.lower_chunk("main", &[], &entry_block)?);
+
+    Ok(Bytecode { entry: chunks.len() - 1, chunks, constants: lowering.constants, names: lowering.names, header })
+}
+
+struct Lowering<'a> {
+    program: &'a Program,
+    constants: Vec<BaseValue>,
+    names: Vec<String>,
+    name_ids: HashMap<String, usize>,
+    function_ids: HashMap<String, usize>,
+    globals: Vec<String>,
+}
+
+impl Lowering<'_> {
+    fn name_id(&mut self, name: &str) -> usize {
+        if let Some(id) = self.name_ids.get(name) {
+            return *id;
+        }
+        self.names.push(name.to_string());
+        self.name_ids.insert(name.to_string(), self.names.len() - 1);
+        self.names.len() - 1
+    }
+
+    fn resolve(&mut self, builder: &ChunkBuilder, name: &str, coords: Coords) -> Result<Variable, Error> {
+        if let Some(slot) = builder.local(name) {
+            return Ok(Variable::Local(slot));
+        }
+        if self.globals.iter().any(|g| g == name) {
+            return Ok(Variable::Global(self.name_id(name)));
+        }
+        Err(unsupported(format!("Unknown variable: {}", name), coords))
+    }
+
+    fn lower_chunk(&mut self, name: &str, params: &[String], block: &AstBlock) -> Result<Chunk, Error> {
+        let mut builder = ChunkBuilder::new(name, params);
+        self.lower_block(&mut builder, block)?;
+        builder.emit(Instr::ReturnNone, block.coords);
+        builder.close_locals();
+        Ok(builder.chunk)
+    }
+
+    fn lower_scoped_block(&mut self, builder: &mut ChunkBuilder, block: &AstBlock) -> Result<(), Error> {
+        let mark = builder.push_scope();
+        self.lower_block(builder, block)?;
+        builder.pop_scope(mark);
+        Ok(())
+    }
+
+    fn lower_block(&mut self, builder: &mut ChunkBuilder, block: &AstBlock) -> Result<(), Error> {
+        for node in &block.nodes {
+            let coords = node.coords;
+            builder.emit(Instr::Statement, coords);
+            match &node.statement {
+                AstStatement::Command { name, args } => {
+                    self.lower_call(builder, name, args, false, coords)?;
+                },
+                AstStatement::Init { typ: _, val, expr } => {
+                    self.lower_expr(builder, expr)?;
+                    if builder.local(val).is_some() || self.globals.contains(val) {
+                        return Err(unsupported(format!("Variable {} is already defined!", val), coords));
+                    }
+                    let slot = builder.declare(val);
+                    builder.emit(Instr::Store(Variable::Local(slot)), coords);
+                },
+                AstStatement::SetVal { val, expr } => {
+                    self.lower_expr(builder, expr)?;
+                    self.lower_store(builder, val, coords)?;
+                },
+                AstStatement::Update { val, expr } => {
+                    let ExpressionType::Binary(op, _, rhs) = &expr.expr_type else {
+                        return Err(unsupported(format!("Expected a compound assignment to {}", val), coords));
+                    };
+                    let slots = self.lower_target(builder, val)?;
+                    Self::push_slots(builder, &slots, coords);
+                    self.emit_load(builder, val, coords)?;
+                    self.lower_expr(builder, rhs)?;
+                    builder.emit(Instr::Binary(*op), expr.coords);
+                    Self::push_slots(builder, &slots, coords);
+                    self.emit_store(builder, val, coords)?;
+                },
+                AstStatement::If { clause, block, else_block } => {
+                    self.lower_expr(builder, clause)?;
+                    let to_else = builder.emit(Instr::JumpIfFalse(0), coords);
+                    self.lower_scoped_block(builder, block)?;
+                    match else_block {
+                        Some(else_block) => {
+                            let to_end = builder.emit(Instr::Jump(0), coords);
+                            builder.patch(to_else, builder.here());
+                            self.lower_scoped_block(builder, else_block)?;
+                            builder.patch(to_end, builder.here());
+                        },
+                        None => builder.patch(to_else, builder.here()),
+                    }
+                },
+                AstStatement::While { clause, block } => {
+                    let start = builder.here();
+                    self.lower_expr(builder, clause)?;
+                    let to_end = builder.emit(Instr::JumpIfFalse(0), coords);
+                    builder.emit(Instr::Iteration, coords);
+                    builder.loops.push(Loop { start: Some(start), ..Loop::default() });
+                    self.lower_scoped_block(builder, block)?;
+                    builder.emit(Instr::Jump(start), coords);
+                    builder.patch(to_end, builder.here());
+                    builder.close_loop(start, builder.here());
+                },
+                AstStatement::For { val, from, to, step, exclusive, block } => {
+                    let mark = builder.push_scope();
+                    let (from_slot, count, step_slot, index) = (builder.temp(), builder.temp(), builder.temp(), builder.temp());
+                    self.lower_expr(builder, from)?;
+                    builder.emit(Instr::Store(Variable::Local(from_slot)), coords);
+                    self.lower_expr(builder, to)?;
+                    builder.emit(Instr::Store(Variable::Local(count)), coords);
+                    if let Some(step) = step {
+                        self.lower_expr(builder, step)?;
+                        builder.emit(Instr::Store(Variable::Local(step_slot)), coords);
+                    }
+
+                    let body_mark = builder.push_scope();
+                    if builder.local(val).is_some() || self.globals.contains(val) {
+                        return Err(unsupported(format!("Variable {} is already defined!", val), coords));
+                    }
+                    let slots = ForSlots { from: from_slot, count, step: step_slot, index, var: builder.declare(val) };
+                    let prep = builder.emit(Instr::ForPrep { slots, stepped: step.is_some(), exclusive: *exclusive, exit: 0 }, coords);
+                    let body = builder.here();
+                    builder.emit(Instr::Iteration, coords);
+                    builder.loops.push(Loop::default());
+                    self.lower_block(builder, block)?;
+                    builder.pop_scope(body_mark);
+                    let next = builder.emit(Instr::ForNext { slots, body }, coords);
+                    builder.patch(prep, builder.here());
+                    builder.close_loop(next, builder.here());
+                    builder.pop_scope(mark);
+                },
+                AstStatement::ForEach { index, val, iterable, block } => {
+                    let mark = builder.push_scope();
+                    let (list, counter) = (builder.temp(), builder.temp());
+                    self.lower_expr(builder, iterable)?;
+                    builder.emit(Instr::Store(Variable::Local(list)), coords);
+
+                    let body_mark = builder.push_scope();
+                    for name in index.iter().chain(std::iter::once(val)) {
+                        if builder.local(name).is_some() || self.globals.contains(name) {
+                            return Err(unsupported(format!("Variable {} is already defined!", name), coords));
+                        }
+                    }
+                    let position = index.as_ref().map(|index| builder.declare(index));
+                    let slots = EachSlots { list, index: counter, var: builder.declare(val), position };
+                    let prep = builder.emit(Instr::ForEachPrep { slots, exit: 0 }, coords);
+                    let body = builder.here();
+                    builder.emit(Instr::Iteration, coords);
+                    builder.loops.push(Loop::default());
+                    self.lower_block(builder, block)?;
+                    builder.pop_scope(body_mark);
+                    let next = builder.emit(Instr::ForEachNext { slots, body }, coords);
+                    builder.patch(prep, builder.here());
+                    builder.close_loop(next, builder.here());
+                    builder.pop_scope(mark);
+                },
+                AstStatement::Return { expr } => {
+                    self.lower_expr(builder, expr)?;
+                    builder.emit(Instr::Return, coords);
+                },
+                AstStatement::Break => builder.jump_out(true, coords)?,
+                AstStatement::Continue => builder.jump_out(false, coords)?,
+            }
+        }
+        Ok(())
+    }
+
+    // Stores the value on top of the stack
+    fn lower_store(&mut self, builder: &mut ChunkBuilder, val: &VariableCall, coords: Coords) -> Result<(), Error> {
+        for index in val.indices() {
+            self.lower_expr(builder, index)?;
+        }
+        self.emit_store(builder, val, coords)
+    }
+
+    // Works out the indices of a target that is read and then written into temporaries,
+    // so they are evaluated only once
+    fn lower_target(&mut self, builder: &mut ChunkBuilder, val: &VariableCall) -> Result<Vec<usize>, Error> {
+        let mut slots = vec![];
+        for index in val.indices() {
+            self.lower_expr(builder, index)?;
+            let slot = builder.temp();
+            builder.emit(Instr::Store(Variable::Local(slot)), index.coords);
+            slots.push(slot);
+        }
+        Ok(slots)
+    }
+
+    fn push_slots(builder: &mut ChunkBuilder, slots: &[usize], coords: Coords) {
+        for slot in slots {
+            builder.emit(Instr::Load(Variable::Local(*slot)), coords);
+        }
+    }
+
+    // The indices of `val` are already on the stack
+    fn emit_load(&mut self, builder: &mut ChunkBuilder, val: &VariableCall, coords: Coords) -> Result<(), Error> {
+        let instr = match val {
+            VariableCall::Name(name) => Instr::Load(self.resolve(builder, name, coords)?),
+            VariableCall::ArrayCall(name, indices) => {
+                let var = self.resolve(builder, name, coords)?;
+                Instr::LoadIndexed { var, name: self.name_id(name), count: indices.len() }
+            },
+            VariableCall::FieldCall(name, path) => {
+                let var = self.resolve(builder, name, coords)?;
+                Instr::LoadPath { var, name: self.name_id(name), path: self.path_ops(path) }
+            },
+        };
+        builder.emit(instr, coords);
+        Ok(())
+    }
+
+    // The value and then the indices of `val` are already on the stack
+    fn emit_store(&mut self, builder: &mut ChunkBuilder, val: &VariableCall, coords: Coords) -> Result<(), Error> {
+        let instr = match val {
+            VariableCall::Name(name) => Instr::Store(self.resolve(builder, name, coords)?),
+            VariableCall::ArrayCall(name, indices) => {
+                let var = self.resolve(builder, name, coords)?;
+                Instr::StoreIndexed { var, name: self.name_id(name), count: indices.len() }
+            },
+            VariableCall::FieldCall(name, path) => {
+                let var = self.resolve(builder, name, coords)?;
+                Instr::StorePath { var, name: self.name_id(name), path: self.path_ops(path) }
+            },
+        };
+        builder.emit(instr, coords);
+        Ok(())
+    }
+
+    fn lower_call(&mut self, builder: &mut ChunkBuilder, name: &str, args: &[Expression], wants_value: bool, coords: Coords) -> Result<(), Error> {
+        if LIST_MUTATORS.contains(&name) {
+            let Some(ExpressionType::Value(BaseValue { val: BaseValueType::Id(target), .. })) = args.first().map(|arg| &arg.expr_type) else {
+                return Err(unsupported(format!("{}: expected a list variable", name), coords));
+            };
+            let slots = self.lower_target(builder, target)?;
+            Self::push_slots(builder, &slots, coords);
+            self.emit_load(builder, target, coords)?;
+            for arg in &args[1..] {
+                self.lower_expr(builder, arg)?;
+            }
+            let (name, list) = (self.name_id(name), self.name_id(&target.to_string()));
+            builder.emit(Instr::ChangeList { name, list, argc: args.len() - 1, wants_value }, coords);
+            Self::push_slots(builder, &slots, coords);
+            return self.emit_store(builder, target, coords);
+        }
+        let params = self.program.functions.get(name).map(|(params, _, _)| params.clone()).unwrap_or_default();
+        for (i, arg) in args.iter().enumerate() {
+            self.lower_expr(builder, arg)?;
+            if params.get(i).is_some_and(|(_, typ)| typ.type_name == TypeName::Primitive(BaseType::Float)) {
+                builder.emit(Instr::ToFloat, arg.coords);
+            }
+        }
+        let argc = args.len();
+        if let Some(function) = self.function_ids.get(name).copied() {
+            let (params, _, _) = &self.program.functions[name];
+            if params.len() != argc {
+                return Err(unsupported(format!("Function {} expects {} arguments, but got {}", name, params.len(), argc), coords));
+            }
+            builder.emit(Instr::Call { function, argc, wants_value }, coords);
+        } else if self.program.function_defs.contains_key(name) {
+            let name = self.name_id(name);
+            builder.emit(Instr::CallBuiltin { name, argc, wants_value }, coords);
+        } else {
+            return Err(unsupported(format!("Unknown function: {}", name), coords));
+        }
+        Ok(())
+    }
+
+    fn path_ops(&mut self, path: &[Accessor]) -> Vec<PathOp> {
+        path.iter().map(|step| match step {
+            Accessor::Index(_) => PathOp::Index,
+            Accessor::Field(field) => PathOp::Field(self.name_id(field)),
+        }).collect()
+    }
+
+    // Either the known result of a short-circuit or the right-hand side
+    fn lower_branch(&mut self, builder: &mut ChunkBuilder, known: Option<bool>, rhs: &Expression, coords: Coords) -> Result<(), Error> {
+        match known {
+            Some(value) => {
+                self.constants.push(BaseValue { val: BaseValueType::Bool(value), coords });
+                builder.emit(Instr::Const(self.constants.len() - 1), coords);
+                Ok(())
+            },
+            None => self.lower_expr(builder, rhs),
+        }
+    }
+
+    fn lower_expr(&mut self, builder: &mut ChunkBuilder, expr: &Expression) -> Result<(), Error> {
+        match &expr.expr_type {
+            ExpressionType::Value(BaseValue { val: BaseValueType::Id(var), .. }) => {
+                for index in var.indices() {
+                    self.lower_expr(builder, index)?;
+                }
+                self.emit_load(builder, var, expr.coords)?;
+            },
+            ExpressionType::Value(BaseValue { val: BaseValueType::Array(elems), .. }) => {
+                for elem in elems {
+                    self.lower_expr(builder, &Expression { expr_type: ExpressionType::Value(elem.clone()), coords: elem.coords })?;
+                }
+                builder.emit(Instr::MakeArray(elems.len()), expr.coords);
+            },
+            ExpressionType::Value(BaseValue { val: BaseValueType::StructLiteral(name, fields), .. }) => {
+                for (_, field) in fields {
+                    self.lower_expr(builder, field)?;
+                }
+                let fields = fields.iter().map(|(field, _)| self.name_id(field)).collect();
+                let name = self.name_id(name);
+                builder.emit(Instr::MakeStruct { name, fields }, expr.coords);
+            },
+            ExpressionType::Value(BaseValue { val: BaseValueType::FunctionCall(name, args), .. }) => {
+                self.lower_call(builder, name, args, true, expr.coords)?;
+            },
+            ExpressionType::Value(value) => {
+                self.constants.push(value.clone());
+                builder.emit(Instr::Const(self.constants.len() - 1), expr.coords);
+            },
+            ExpressionType::Unary(UnaryOperator::Parentheses, inner) => self.lower_expr(builder, inner)?,
+            ExpressionType::Unary(op, inner) => {
+                self.lower_expr(builder, inner)?;
+                builder.emit(Instr::Unary(*op), expr.coords);
+            },
+            // `a && b` is `a ? b : false` and `a || b` is `a ? true : b`
+            ExpressionType::Binary(op @ (Operator::AND | Operator::OR), lhs, rhs) => {
+                self.lower_expr(builder, lhs)?;
+                let to_else = builder.emit(Instr::JumpIfFalse(0), expr.coords);
+                let (then, otherwise) = if *op == Operator::AND { (None, Some(false)) } else { (Some(true), None) };
+                self.lower_branch(builder, then, rhs, expr.coords)?;
+                let to_end = builder.emit(Instr::Jump(0), expr.coords);
+                builder.patch(to_else, builder.here());
+                self.lower_branch(builder, otherwise, rhs, expr.coords)?;
+                builder.patch(to_end, builder.here());
+            },
+            ExpressionType::Binary(op, lhs, rhs) => {
+                self.lower_expr(builder, lhs)?;
+                self.lower_expr(builder, rhs)?;
+                builder.emit(Instr::Binary(*op), expr.coords);
+            },
+        }
+        Ok(())
+    }
+}
@@ -1,26 +1,31 @@
 
-use quanta_parser::{parse_ast};
+use std::sync::Arc;
+
+use quanta_parser::{error::Error, parse_ast};
 //use crate::linear_runtime;
-use crate::program::create_program;
+use crate::program::{create_program, Program};
 use crate::utils::canvas::construct_canvas;
 use crate::utils::message::{CompilationMessage};
+use crate::utils::timer::{default_timer, Timer};
 //use crate::utils::message::{LinearCompilationMessage};
 use crate::{Compiler, runtime::Runtime};
 
+fn build_program(source: &str) -> Result<Program, Error> {
+    let mut program = create_program(parse_ast(source)?);
+    program.type_check()?;
+    Ok(program)
+}
+
 impl Compiler {
     pub async fn compile(&mut self, source : &str) -> CompilationMessage {
-        match parse_ast(source) {
-            Ok(ast) => {
-                let mut program = create_program(ast);
-                match program.type_check() {
-                    Err(error) =>  {
-                        CompilationMessage::create_error_message(error)
-                    },
-                    Ok(_) => {
-                        let (c, r) = construct_canvas();
-                        CompilationMessage::ok(Runtime::new(program, c, r).await)
-                    }
-                }
+        self.compile_with_timer(source, default_timer()).await
+    }
+
+    pub async fn compile_with_timer(&mut self, source : &str, timer: Arc<dyn Timer>) -> CompilationMessage {
+        match build_program(source) {
+            Ok(program) => {
+                let (c, r) = construct_canvas();
+                CompilationMessage::ok(Runtime::with_timer(program, c, r, timer).await)
             },
             Err(err) => {
                 CompilationMessage::create_error_message(err)
@@ -28,6 +33,11 @@ impl Compiler {
         }
     }
 
+    // Parses and type checks without running anything, not even global initializers
+    pub fn check(&self, source : &str) -> Result<(), Error> {
+        build_program(source).map(|_| ())
+    }
+
     // pub fn linear_compile(&mut self, source : &str) -> LinearCompilationMessage {
     //     match parse_ast(source) {
     //         Ok(ast) => {
@@ -1,9 +1,8 @@
 use std::{collections::HashMap, sync::{Arc, Mutex}};
 
-use gloo_timers::future::TimeoutFuture;
-use quanta_parser::{ast::{AstBlock, AstNode, AstProgram, AstStatement, BaseValue, BaseValueType, Coords, Expression, ExpressionType, Operator, Type, UnaryOperator, VariableCall}, error::Error};
+use quanta_parser::{ast::{AstBlock, AstNode, FunctionBody, AstProgram, AstStatement, BaseValue, BaseValueType, Coords, Expression, ExpressionType, Operator, TypeName, UnaryOperator, VariableCall, Accessor}, error::Error};
 use quanta_parser::ast::BaseType;
-use crate::utils::canvas::Canvas;
+use crate::utils::{budget::Budget, canvas::Canvas, command::{DrawCommand, LineStyle, Rgba, ShapeStyle, TextStyle}, debugger::Debugger, timer::Timer};
 //use js_sys::Math;
 use std::pin::Pin;
 use std::future::Future;
@@ -30,14 +29,14 @@ impl Scope {
     }
 
     pub fn set(&mut self, name: String, val: BaseValue) -> bool {
-        if self.variables.contains_key(&name) {
-            self.variables.insert(name, val);// = val;
+        if let Some(slot) = self.variables.get_mut(&name) {
+            *slot = val;
             return true;
         }
         if let Some(outer) = &mut self.outer_scope {
             return outer.lock().unwrap().set(name, val);
         }
-        return false;
+        false
     }
 
     pub fn get(&self, name: &str) -> Option<BaseValue> {
@@ -56,22 +55,41 @@ impl Scope {
     }
 }
 
+// A user function being executed. `scope` is the innermost scope of its last statement,
+// for callers that's the statement making the call.
+#[derive(Debug, Clone)]
+pub struct CallFrame {
+    pub function: String,
+    pub coords: Coords,
+    pub scope: Arc<Mutex<Scope>>,
+}
+
+// How a block of statements finished
+#[derive(Debug, Clone, PartialEq)]
+pub enum Flow {
+    Next,
+    Break,
+    Continue,
+    Return(BaseValue),
+}
+
 #[derive(Debug, Clone)]
 pub struct Execution {
     pub lines: AstProgram, 
     pub scope : Arc<Mutex<Scope>>,
     pub global_vars : Arc<Mutex<HashMap<String, BaseValue>>>,
-    pub functions : HashMap<String, (Vec<(String, Type)>, Option<Type>, AstBlock)>,
+    pub functions : HashMap<String, FunctionBody>,
     pub canvas    : Canvas,
-    pub figure_color : Arc<Mutex<String>>,
-    pub line_color : Arc<Mutex<String>>,
+    pub figure_color : Arc<Mutex<Rgba>>,
+    pub line_color : Arc<Mutex<Rgba>>,
     pub line_width : Arc<Mutex<i32>>,
+    pub font_size : Arc<Mutex<i32>>,
+    pub font : Arc<Mutex<String>>,
     pub random_color: Arc<Mutex<i32>>,
-}
-
-fn color_to_str(r: &u8, g : &u8, b: &u8, a: &u8) -> String {
-    let s = format!("#{:02x}{:02x}{:02x}{:02x}", r, g, b, a).to_lowercase();
-    s
+    pub timer: Arc<dyn Timer>,
+    pub debugger: Debugger,
+    pub calls: Arc<Mutex<Vec<CallFrame>>>,
+    pub budget: Budget,
 }
 
 macro_rules! expect_arg {
@@ -105,22 +123,166 @@ macro_rules! expect_arg {
     }};
 }
 
-fn update_array(name: String, array: &mut BaseValue, mut integer_indices: Vec<i32>, val: BaseValue) -> Result<(), Error> {
+// Declared after `expect_arg!`, which the builtins use
+pub(crate) mod builtins;
+
+pub(crate) fn update_array(name: String, array: &mut BaseValue, mut integer_indices: Vec<i32>, val: BaseValue) -> Result<(), Error> {
         if let BaseValueType::Array(elems) = &mut array.val {
             let index = integer_indices.remove(0);
             if index < 0 || index as usize >= elems.len() {
                 return Err(Error::runtime(format!("Index out of bounds for array {}: {}", name, index), array.coords));
             }
-            if integer_indices.len() == 0 {
+            if integer_indices.is_empty() {
                 elems[index as usize] = val;
                 return Ok(());
             }
-            return update_array(format!("{}[{}]", name, index), elems.get_mut(index as usize).unwrap(), integer_indices, val);
+            update_array(format!("{}[{}]", name, index), elems.get_mut(index as usize).unwrap(), integer_indices, val)
         } else {
-            return Err(Error::runtime(format!("Variable {} is not an array", name), array.coords));
+            Err(Error::runtime(format!("Variable {} is not an array", name), array.coords))
         }
     }
 
+// A step of a field path with its index worked out
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum PathStep {
+    Index(i32),
+    Field(String),
+}
+
+fn step_into(name: &str, value: &BaseValue, step: &PathStep) -> Result<(String, usize), Error> {
+    match (step, &value.val) {
+        (PathStep::Index(index), BaseValueType::Array(elems)) => {
+            if *index < 0 || *index as usize >= elems.len() {
+                return Err(Error::runtime(format!("Index out of bounds for array {}: {}", name, index), value.coords));
+            }
+            Ok((format!("{}[{}]", name, index), *index as usize))
+        },
+        (PathStep::Field(field), BaseValueType::Struct(_, fields)) => {
+            match fields.iter().position(|(f, _)| f == field) {
+                Some(position) => Ok((format!("{}.{}", name, field), position)),
+                None => Err(Error::runtime(format!("Variable {} has no field {}", name, field), value.coords)),
+            }
+        },
+        (PathStep::Index(_), _) => Err(Error::runtime(format!("Variable {} is not an array", name), value.coords)),
+        (PathStep::Field(_), _) => Err(Error::runtime(format!("Variable {} is not a struct", name), value.coords)),
+    }
+}
+
+fn child(value: &mut BaseValue, position: usize) -> &mut BaseValue {
+    match &mut value.val {
+        BaseValueType::Array(elems) => &mut elems[position],
+        BaseValueType::Struct(_, fields) => &mut fields[position].1,
+        _ => unreachable!("step_into only steps into arrays and structs"),
+    }
+}
+
+pub(crate) fn read_path(name: &str, mut value: &BaseValue, path: &[PathStep]) -> Result<BaseValue, Error> {
+    let mut name = name.to_string();
+    for step in path {
+        let (inner_name, position) = step_into(&name, value, step)?;
+        value = match &value.val {
+            BaseValueType::Array(elems) => &elems[position],
+            BaseValueType::Struct(_, fields) => &fields[position].1,
+            _ => unreachable!("step_into only steps into arrays and structs"),
+        };
+        name = inner_name;
+    }
+    Ok(value.clone())
+}
+
+pub(crate) fn update_path(name: String, value: &mut BaseValue, path: &[PathStep], val: BaseValue) -> Result<(), Error> {
+    match path.split_first() {
+        None => {
+            *value = val;
+            Ok(())
+        },
+        Some((step, rest)) => {
+            let (inner_name, position) = step_into(&name, value, step)?;
+            update_path(inner_name, child(value, position), rest, val)
+        },
+    }
+}
+
+// Builtins that change the list variable given as their first argument
+pub(crate) const LIST_MUTATORS: [&str; 4] = ["push", "pop", "insert", "remove"];
+
+// Returns the changed list and the value `pop` or `remove` took out of it
+pub(crate) fn change_list(function_name: &str, list_name: &str, list: BaseValue, mut args: Vec<BaseValue>, coords: Coords) -> Result<(BaseValue, Option<BaseValue>), Error> {
+    let BaseValueType::Array(mut elems) = list.val else {
+        return Err(Error::runtime(format!("Variable {} is not a list", list_name), coords));
+    };
+    let index = |args: &[BaseValue], allowed: usize| match args.first().map(|v| &v.val) {
+        Some(BaseValueType::Int(i)) if *i >= 0 && (*i as usize) < allowed => Ok(*i as usize),
+        Some(BaseValueType::Int(i)) => Err(Error::runtime(format!("Index out of bounds for list {}: {}", list_name, i), coords)),
+        _ => Err(Error::runtime(format!("{}: expected an int index", function_name), coords)),
+    };
+    let taken = match function_name {
+        "push" => {
+            elems.push(args.remove(0));
+            None
+        },
+        "pop" => Some(elems.pop().ok_or_else(|| Error::runtime(format!("Cannot pop from the empty list {}", list_name), coords))?),
+        "insert" => {
+            let at = index(&args, elems.len() + 1)?;
+            elems.insert(at, args.remove(1));
+            None
+        },
+        "remove" => {
+            let at = index(&args, elems.len())?;
+            Some(elems.remove(at))
+        },
+        _ => return Err(Error::runtime(format!("Unknown list function: {}", function_name), coords)),
+    };
+    Ok((BaseValue { val: BaseValueType::Array(elems), coords: list.coords }, taken))
+}
+
+// Tolerance for float ranges, in steps, so `(0.0..1.0 step 0.1)` still reaches 1.0
+const RANGE_EPSILON: f64 = 1e-4;
+
+// The values of a `for` loop are `from + i * step` for `i` in `0..count`, computed
+// from the index rather than added up so float loops don't drift
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ForRange {
+    pub from: BaseValueType,
+    pub step: BaseValueType,
+    pub count: i32,
+}
+
+impl ForRange {
+    pub(crate) fn new(from: &BaseValueType, to: &BaseValueType, step: Option<&BaseValueType>, exclusive: bool, coords: Coords) -> Result<ForRange, Error> {
+        let (distance, step) = match (from, to, step) {
+            (BaseValueType::Int(f), BaseValueType::Int(t), None) => (*t as f64 - *f as f64, BaseValueType::Int(if f <= t { 1 } else { -1 })),
+            (BaseValueType::Int(f), BaseValueType::Int(t), Some(BaseValueType::Int(s))) => (*t as f64 - *f as f64, BaseValueType::Int(*s)),
+            (BaseValueType::Float(f), BaseValueType::Float(t), None) => (*t as f64 - *f as f64, BaseValueType::Float(if f <= t { 1.0 } else { -1.0 })),
+            (BaseValueType::Float(f), BaseValueType::Float(t), Some(BaseValueType::Float(s))) => (*t as f64 - *f as f64, BaseValueType::Float(*s)),
+            _ => return Err(Error::runtime(String::from("For loop bounds and step must be all int or all float"), coords)),
+        };
+        let size = match step {
+            BaseValueType::Int(s) => s as f64,
+            BaseValueType::Float(s) => s as f64,
+            _ => unreachable!(),
+        };
+        if size == 0.0 {
+            return Err(Error::runtime(String::from("The step of a for loop can't be 0"), coords));
+        }
+        let steps = distance / size;
+        if !steps.is_finite() || steps > i32::MAX as f64 - 1.0 {
+            return Err(Error::runtime(format!("This for loop would run more than {} times", i32::MAX), coords));
+        }
+        // A step going away from `to` runs no iterations at all
+        let count = if exclusive { (steps - RANGE_EPSILON).ceil() } else { (steps + RANGE_EPSILON).floor() + 1.0 };
+        Ok(ForRange { from: from.clone(), step, count: count.max(0.0) as i32 })
+    }
+
+    pub(crate) fn value(&self, index: i32) -> BaseValueType {
+        match (&self.from, &self.step) {
+            (BaseValueType::Int(from), BaseValueType::Int(step)) => BaseValueType::Int((*from as i64 + index as i64 * *step as i64) as i32),
+            (BaseValueType::Float(from), BaseValueType::Float(step)) => BaseValueType::Float((*from as f64 + index as f64 * *step as f64) as f32),
+            _ => unreachable!("Ranges are either int or float"),
+        }
+    }
+}
+
 fn int(i: i32, coords:Coords) -> BaseValue {
     BaseValue{ val:BaseValueType::Int(i), coords} 
 }
@@ -133,6 +295,17 @@ fn bol(i: bool, coords:Coords) -> BaseValue {
     BaseValue{ val:BaseValueType::Bool(i), coords} 
 }
 
+// An int given for a float parameter, the type checker lets it through
+pub(crate) fn widen(value: BaseValue) -> BaseValue {
+    match value.val {
+        BaseValueType::Int(i) => flt(i as f32, value.coords),
+        _ => value,
+    }
+}
+
+pub const DEFAULT_FONT_SIZE: i32 = 20;
+pub const DEFAULT_FONT: &str = "sans-serif";
+
 fn get_random() -> f64 {
     let mut rng = rand::thread_rng();
     rng.gen()
@@ -140,6 +313,26 @@ fn get_random() -> f64 {
 
 impl Execution {
 
+    pub fn new(lines: AstProgram, functions: HashMap<String, FunctionBody>, canvas: Canvas, timer: Arc<dyn Timer>) -> Execution {
+        Execution {
+            lines,
+            scope: Arc::new(Mutex::new(Scope { variables: HashMap::new(), outer_scope: None })),
+            global_vars: Arc::new(Mutex::new(HashMap::new())),
+            canvas,
+            functions,
+            figure_color: Arc::new(Mutex::new(Rgba::WHITE)),
+            line_color: Arc::new(Mutex::new(Rgba::BLACK)),
+            line_width: Arc::new(Mutex::new(1)),
+            font_size: Arc::new(Mutex::new(DEFAULT_FONT_SIZE)),
+            font: Arc::new(Mutex::new(String::from(DEFAULT_FONT))),
+            random_color: Arc::new(Mutex::new(0)),
+            timer,
+            debugger: Debugger::new(),
+            calls: Arc::new(Mutex::new(vec![])),
+            budget: Budget::new(),
+        }
+    }
+
     pub fn create_subscope(&self) -> Execution {
         Execution {
             lines: self.lines.clone(),
@@ -150,10 +343,28 @@ impl Execution {
             figure_color: Arc::clone(&self.figure_color),
             line_color: self.line_color.clone(),
             line_width: self.line_width.clone(),
+            font_size: Arc::clone(&self.font_size),
+            font: Arc::clone(&self.font),
             random_color: Arc::clone(&self.random_color),
+            timer: Arc::clone(&self.timer),
+            debugger: self.debugger.clone(),
+            calls: Arc::clone(&self.calls),
+            budget: self.budget.clone(),
         }
     }
 
+    fn shape_style(&self) -> ShapeStyle {
+        ShapeStyle { fill: *self.figure_color.lock().unwrap(), stroke: *self.line_color.lock().unwrap(), width: *self.line_width.lock().unwrap() }
+    }
+
+    fn line_style(&self) -> LineStyle {
+        LineStyle { stroke: *self.line_color.lock().unwrap(), width: *self.line_width.lock().unwrap() }
+    }
+
+    fn text_style(&self) -> TextStyle {
+        TextStyle { fill: *self.figure_color.lock().unwrap(), size: *self.font_size.lock().unwrap(), font: self.font.lock().unwrap().clone() }
+    }
+
     fn create_subfunction(&self) -> Execution {
         let e = self.create_subscope();
         e.scope.lock().unwrap().clear();
@@ -171,24 +382,62 @@ impl Execution {
         if self.scope.lock().unwrap().set(name.clone(), val.clone()) {
             return true;
         }
-        let mut globs = self.global_vars.lock().unwrap();
-        if globs.contains_key(&name) {
-            globs.insert(name, val);// = val;
+        if let Some(slot) = self.global_vars.lock().unwrap().get_mut(&name) {
+            *slot = val;
             return true;
         }
-        return false;
+        false
     }
 
     pub fn get(&self, name: &str) -> Option<BaseValue> {
         if let Some(var) = self.scope.lock().unwrap().get(name) {
             return Some(var.clone());
         }
-        self.global_vars.lock().unwrap().get(name).map(|x| x.clone())
+        self.global_vars.lock().unwrap().get(name).cloned()
+    }
+
+    async fn resolve_path(&self, name: &str, path: &[Accessor], coords: Coords) -> Result<Vec<PathStep>, Error> {
+        let mut steps = vec![];
+        for step in path {
+            steps.push(match step {
+                Accessor::Field(field) => PathStep::Field(field.clone()),
+                Accessor::Index(index) => match self.calculate_expression(index.clone()).await?.val {
+                    BaseValueType::Int(i) if i < 0 => return Err(Error::runtime(format!("Negative index for array {}: {}", name, i), coords)),
+                    BaseValueType::Int(i) => PathStep::Index(i),
+                    _ => return Err(Error::runtime(String::from("Array indices must be integers"), coords)),
+                },
+            });
+        }
+        Ok(steps)
+    }
+
+    // The same target with every index replaced by its value, so reading and then writing it
+    // works out the indices only once
+    async fn resolve_indices(&self, var: &VariableCall) -> Result<VariableCall, Error> {
+        let mut values = vec![];
+        for index in var.indices() {
+            let value = self.calculate_expression(index.clone()).await?;
+            values.push(Expression { coords: value.coords, expr_type: ExpressionType::Value(value) });
+        }
+        let mut values = values.into_iter();
+        Ok(match var {
+            VariableCall::Name(_) => var.clone(),
+            VariableCall::ArrayCall(name, indices) => VariableCall::ArrayCall(name.clone(), values.by_ref().take(indices.len()).collect()),
+            VariableCall::FieldCall(name, path) => VariableCall::FieldCall(name.clone(), path.iter().map(|step| match step {
+                Accessor::Index(_) => Accessor::Index(values.next().unwrap()),
+                Accessor::Field(field) => Accessor::Field(field.clone()),
+            }).collect()),
+        })
     }
 
     async fn get_variable(&self, var: &VariableCall, coords: Coords) -> Result<BaseValue, Error> {
         match var {
             VariableCall::Name(name) => self.get(name).ok_or(Error::runtime(format!("Unknown variable: {}", name), coords)),
+            VariableCall::FieldCall(name, path) => {
+                let steps = self.resolve_path(name, path, coords).await?;
+                let value = self.get(name).ok_or(Error::runtime(format!("Unknown variable: {}", name), coords))?;
+                read_path(name, &value, &steps)
+            },
             VariableCall::ArrayCall(name, indices) => {
                 if !self.contains_key(name) {
                     return Err(Error::runtime(format!("Unknown array 1: {}, variables: {:?}", name, self.scope), coords));
@@ -198,7 +447,7 @@ impl Execution {
                 }
                 let mut integer_indices: Vec<i32> = vec![];
                 for index in indices {
-                    match self.calculate_expression(index.clone().to_expr()).await?.val {
+                    match self.calculate_expression(index.clone()).await?.val {
                         BaseValueType::Int(i) => {
                             if i < 0 {
                                 return Err(Error::runtime(format!("Negative index for array {}: {}", name, i), coords));
@@ -213,7 +462,7 @@ impl Execution {
                     return Err(Error::runtime(format!("Unknown array: {} ", name), coords));
                 }
                 let mut array = maybe_array.unwrap();
-                while integer_indices.len() > 0 {
+                while !integer_indices.is_empty() {
                     if let BaseValueType::Array(elems) = array.val {
                         let index = integer_indices.remove(0);
                         if index < 0 || index as usize >= elems.len() {
@@ -233,7 +482,14 @@ impl Execution {
 
     async fn set_variable(&mut self, var: &VariableCall, val: BaseValue, coords: Coords) -> Result<(), Error> {
         match var {
-            VariableCall::Name(name) => if self.set(name.clone(), val) { return Ok(()); } else { return Err(Error::runtime(format!("Unknown variable: {}", name), coords));},
+            VariableCall::Name(name) => if self.set(name.clone(), val) { Ok(())} else { Err(Error::runtime(format!("Unknown variable: {}", name), coords))},
+            VariableCall::FieldCall(name, path) => {
+                let steps = self.resolve_path(name, path, coords).await?;
+                let mut value = self.get(name).ok_or(Error::runtime(format!("Unknown variable: {}", name), coords))?;
+                update_path(name.clone(), &mut value, &steps, val)?;
+                self.set(name.clone(), value);
+                Ok(())
+            },
             VariableCall::ArrayCall(name, indices) => {
                 if !self.contains_key(name) {
                     return Err(Error::runtime(format!("Unknown array 1: {}, variables: {:?}", name, self.scope), coords));
@@ -243,7 +499,7 @@ impl Execution {
                 }
                 let mut integer_indices: Vec<i32> = vec![];
                 for index in indices {
-                    match self.calculate_expression(index.clone().to_expr()).await?.val {
+                    match self.calculate_expression(index.clone()).await?.val {
                         BaseValueType::Int(i) => {
                             if i < 0 {
                                 return Err(Error::runtime(format!("Negative index for array {}: {}", name, i), coords));
@@ -268,194 +524,65 @@ impl Execution {
         }
     }
 
+    // The list is read and written back through the scope, like any other assignment
+    async fn execute_list_call(&mut self, function_name: &str, args: Vec<Expression>, coords: Coords) -> Result<Option<BaseValue>, Error> {
+        let Some(ExpressionType::Value(BaseValue { val: BaseValueType::Id(target), .. })) = args.first().map(|arg| &arg.expr_type) else {
+            return Err(Error::runtime(format!("{}: expected a list variable", function_name), coords));
+        };
+        let target = &self.resolve_indices(target).await?;
+        let list = self.get_variable(target, coords).await?;
+        let mut vals = vec![];
+        for arg in &args[1..] {
+            vals.push(self.calculate_expression(arg.clone()).await?);
+        }
+        let (list, taken) = change_list(function_name, &target.to_string(), list, vals, coords)?;
+        self.set_variable(target, list, coords).await?;
+        Ok(taken)
+    }
+
     async fn execute_function(&mut self, function_name: &str, args: Vec<Expression>, coords: Coords) -> Result<Option<BaseValue>, Error>{
+        if LIST_MUTATORS.contains(&function_name) {
+            return self.execute_list_call(function_name, args, coords).await;
+        }
         let mut vals : Vec<BaseValue> = vec![];
         for arg in args {
             let val = self.calculate_expression(arg).await?;
             vals.push(val);
         }
-        match function_name {
-            "circle" => {
-                let x1 = expect_arg!("circle", vals, 0, Int(v) => *v);
-                let y1 = expect_arg!("circle", vals, 1, Int(v) => *v);
-                let r = expect_arg!("circle", vals, 2, Int(v) => *v);
-
-                self.canvas.add_command(format!("circle {} {} {} fill={} stroke={} width={}", x1, y1, r, self.figure_color.lock().unwrap(), self.line_color.lock().unwrap(), self.line_width.lock().unwrap()));
-                Ok(None)
-            },
-            "line" => {
-                let x1 = expect_arg!("line", vals, 0, Int(v) => *v);
-                let y1 = expect_arg!("line", vals, 1, Int(v) => *v);
-                let x2 = expect_arg!("line", vals, 2, Int(v) => *v);
-                let y2 = expect_arg!("line", vals, 3, Int(v) => *v);
-
-                self.canvas.add_command(format!("line {} {} {} {} stroke={} width={}", x1, y1, x2, y2, self.line_color.lock().unwrap(), self.line_width.lock().unwrap()));
-                Ok(None)
-            },
-            "rectangle" => {
-                let x1 = expect_arg!("rectangle", vals, 0, Int(v) => *v);
-                let y1 = expect_arg!("rectangle", vals, 1, Int(v) => *v);
-                let x2 = expect_arg!("rectangle", vals, 2, Int(v) => *v);
-                let y2 = expect_arg!("rectangle", vals, 3, Int(v) => *v);
-                
-                self.canvas.add_command(format!("rectangle {} {} {} {} fill={} stroke={} width={}", x1, y1, x2, y2, self.figure_color.lock().unwrap(), self.line_color.lock().unwrap(), self.line_width.lock().unwrap()));
-                Ok(None)
-            },
-            "polygon" => {
-                let mut nums = String::new();
-                for val in &vals {
-                    if let BaseValueType::Int(num) = val.val {
-                        nums.push_str(&format!("{} ", num));
-                    } else {
-                        return Err(Error::runtime(String::from("Incorrect arguments for polygon function!"), val.coords));
-                    }
-                }
-                self.canvas.add_command(format!("polygon {} fill={} stroke={} width={}", nums.trim(), self.figure_color.lock().unwrap(), self.line_color.lock().unwrap(), self.line_width.lock().unwrap()));
-                Ok(None)
-            },
-            "arc" => {
-                let x = expect_arg!("arc", vals, 0, Int(v) => *v);
-                let y = expect_arg!("arc", vals, 1, Int(v) => *v);
-                let r = expect_arg!("arc", vals, 2, Int(v) => *v);
-                let start = expect_arg!("arc", vals, 3, Int(v) => *v);
-                let end = expect_arg!("arc", vals, 4, Int(v) => *v);
-
-                self.canvas.add_command(format!("arc {} {} {} {} {} fill={} stroke={} width={}", x, y, r, start, end, self.figure_color.lock().unwrap(), self.line_color.lock().unwrap(), self.line_width.lock().unwrap()));
-                Ok(None)
-            },
-            "setLineColor" => {
-                if let BaseValueType::Color(r,g,b, a) = &vals[0].val {
-                    let mut inner  = self.line_color.lock().unwrap();
-                    *inner = color_to_str(r, g, b, a);
-                    Ok(None)
-                }
-                else {
-                    Err(Error::runtime(format!("Incorrect arguments for setLineColor function: expected a color, got {:?}!", &vals[0]), coords))
-                }
-            },
-            "setFigureColor" => {
-                if let BaseValueType::Color(r,g,b, a) = &vals[0].val {
-                    let mut inner  = self.figure_color.lock().unwrap();
-                    *inner = color_to_str(r, g, b, a);
-                    Ok(None)
-                }
-                else {
-                    Err(Error::runtime(format!("Incorrect arguments for setFigureColor function: expected a color, got {:?}!", &vals[0]), coords))
-                }
-            },
-            "setLineWidth" => {
-                let width = expect_arg!("setLineWidth", vals, 0, Int(width) => *width);
-                if width >= 0 {
-                    let mut inner  = self.line_width.lock().unwrap();
-                    *inner = width;
-                    Ok(None)
-                } else {
-                    Err(Error::runtime(String::from("Line width can't be negative!"), coords))
-                }
-            },
-            "sleep" => {
-                let sleep_time = expect_arg!("sleep", vals, 0, Int(time) => *time);
-                if sleep_time >= 0 {
-                    //thread::sleep(Duration::from_millis(1000));
-                    self.canvas.add_command(format!("sleep {}", sleep_time));
-
-                    Ok(None)
-                } else {
-                    Err(Error::runtime(String::from("Sleep time can't be negative!"), coords))
-                }
-            },
-            "animate" => {
-                self.canvas.add_command(format!("animate"));
-                Ok(None)
-            },
-            "frame" => {
-                self.canvas.add_command(format!("frame"));
-                Ok(None)
-            },
-            "clear" => {
-                self.canvas.add_command(format!("clear"));
-                Ok(None)
-            },
-            "rgb" => {
-                let r = expect_arg!("rgb", vals, 0, Int(v) => *v);
-                let g = expect_arg!("rgb", vals, 1, Int(v) => *v);
-                let b = expect_arg!("rgb", vals, 2, Int(v) => *v);
-                if r < 0 || r > 255 || g < 0 || g > 255 || b < 0 || b > 255 {
-                    return Err(Error::runtime(String::from("RGB values must be between 0 and 255"), coords));
-                }
-                Ok(Some(BaseValue{val: BaseValueType::Color(r as u8, g as u8, b as u8, 255), coords}))
-            },
-            "Color::Random" => {
-                let r = (get_random() * 255.0) as u8;
-                let g = (get_random() * 255.0) as u8;
-                let b = (get_random() * 255.0) as u8;
-                Ok(Some(BaseValue{val: BaseValueType::Color(r,g,b, 255), coords}))
-            },
-            "round" => {
-                let num = expect_arg!("round", vals, 0, Float(v) => *v);
-                Ok(Some(int(num.round() as i32, coords)))
-            },
-            "floor" => {
-                let num = expect_arg!("floor", vals, 0, Float(v) => *v);
-                Ok(Some(int(num.floor() as i32, coords)))
-            },
-            "ceil" => {
-                let num = expect_arg!("ceil", vals, 0, Float(v) => *v);
-                Ok(Some(int(num.ceil() as i32, coords)))
-            },
-            "sqrt" => {
-                let num = expect_arg!("sqrt", vals, 0, Float(v) => *v);
-                if num < 0.0 {
-                    return Err(Error::runtime(String::from("Cannot calculate square root of a negative number"), coords));
-                }
-                Ok(Some(flt(num.sqrt(), coords)))
-            },
-            "abs" => {
-                let num = expect_arg!("abs", vals, 0, Float(v) => *v);
-                Ok(Some(flt(num.abs(), coords)))
-            },
-            "decimal" => {
-                let num = expect_arg!("decimal", vals, 0, Int(v) => *v);
-                Ok(Some(flt(num as f32, coords)))
-            },
-            "random" => {
-                let mut lower_bound = expect_arg!("random", vals, 0, Int(v) => *v);
-                let mut upper_bound = expect_arg!("random", vals, 1, Int(v) => *v);
-                if lower_bound >= upper_bound {
-                    std::mem::swap(&mut lower_bound, &mut upper_bound);
-                }
-                let random_value = (get_random() * ((upper_bound - lower_bound + 1) as f64) + (lower_bound as f64)) as i32;
-                if random_value > upper_bound {
-                    return Ok(Some(int(upper_bound, coords)));
-                }
-                Ok(Some(int(random_value, coords)))
+        if let Some((params, _, body)) = self.functions.get(function_name) {
+            if params.len() != vals.len() {
+                return Err(Error::runtime(format!("Function {} expects {} arguments, but got {}", function_name, params.len(), vals.len()), coords));
             }
-            name => {
-                if self.functions.contains_key(name) {
-                    let (params, _, body) = self.functions.get(name).unwrap();
-                    if params.len() != vals.len() {
-                        return Err(Error::runtime(format!("Function {} expects {} arguments, but got {}", name, params.len(), vals.len()), coords));
-                    }
-                    let mut new_exec = self.create_subfunction();
-                    for (i, param) in params.iter().enumerate() {
-                        new_exec.scope.lock().unwrap().variables.insert(param.0.clone(), vals[i].clone());
-                    }
-                    let ret_val_wrap = new_exec.execute_commands(body.nodes.clone()).await?;
+            let mut new_exec = self.create_subfunction();
+            for ((name, typ), val) in params.iter().zip(vals) {
+                let val = if typ.type_name == TypeName::Primitive(BaseType::Float) { widen(val) } else { val };
+                new_exec.scope.lock().unwrap().variables.insert(name.clone(), val);
+            }
+            let body = body.nodes.clone();
+            return new_exec.execute_call(function_name, coords, body).await;
+        }
+        self.call_builtin(function_name, vals, coords)
+    }
 
-                    if let Some(return_value) = ret_val_wrap {
-                        return Ok(Some(return_value));
-                    }
-                    return Ok(None);
+    // Builtins never touch the scope, so the bytecode VM calls them directly
+    pub(crate) fn call_builtin(&mut self, function_name: &str, mut vals: Vec<BaseValue>, coords: Coords) -> Result<Option<BaseValue>, Error> {
+        let Some((params, Some(run))) = builtins::builtin(function_name).map(|builtin| (&builtin.params, builtin.run)) else {
+            return Err(Error::runtime(format!("Unknown function: {}", function_name), coords));
+        };
+        if let builtins::Params::Fixed(params) = params {
+            for (val, (_, typ)) in vals.iter_mut().zip(params.iter()) {
+                if *typ == BaseType::Float {
+                    *val = widen(val.clone());
                 }
-                Err(Error::runtime(format!("Unknown function: {}", function_name), coords))
             }
         }
+        run(self, function_name, vals, coords)
     }
 
     async fn execute_init(&mut self, var: String, expr: Expression, coords: Coords) -> Result<(), Error>{
         let value = self.calculate_expression(expr).await?;
 
-        if let Some(_) = self.get(&var) {
+        if self.get(&var).is_some() {
             return Err(Error::runtime(format!("Variable {} is already defined!", &var), coords));
         }
         self.scope.lock().unwrap().variables.insert(var, value);
@@ -464,25 +591,52 @@ impl Execution {
 
     async fn execute_set(&mut self, var: &VariableCall, expr: Expression, coords: Coords) -> Result<(), Error> {
         let value = self.calculate_expression(expr).await?;
-        if self.get_variable(var, coords).await.is_ok() {
-            self.set_variable(var, value, coords).await?;
-            return Ok(())
+        self.set_variable(var, value, coords).await
+    }
+
+    async fn execute_update(&mut self, var: &VariableCall, expr: Expression, coords: Coords) -> Result<(), Error> {
+        let ExpressionType::Binary(op, _, rhs) = expr.expr_type else {
+            return Err(Error::runtime(format!("Expected a compound assignment to {}", var), coords));
+        };
+        let var = self.resolve_indices(var).await?;
+        let current = self.get_variable(&var, coords).await?;
+        let rhs = self.calculate_expression(*rhs).await?;
+        let value = apply_binary(op, &current, &rhs, coords)?;
+        self.set_variable(&var, value, coords).await
+    }
+
+    async fn execute_call(&mut self, function_name: &str, coords: Coords, nodes: Vec<AstNode>) -> Result<Option<BaseValue>, Error> {
+        self.calls.lock().unwrap().push(CallFrame { function: function_name.to_string(), coords, scope: Arc::clone(&self.scope) });
+        let result = self.execute_commands(nodes).await;
+        self.calls.lock().unwrap().pop();
+        match result? {
+            Flow::Return(value) => Ok(Some(value)),
+            _ => Ok(None),
         }
-        Err(Error::runtime(String::from("Couldn't set new value"), coords))
+    }
+
+    // Points the innermost call at the current scope and returns how many calls are below it
+    fn enter_statement(&self) -> usize {
+        let mut calls = self.calls.lock().unwrap();
+        if let Some(frame) = calls.last_mut() {
+            frame.scope = Arc::clone(&self.scope);
+        }
+        calls.len().saturating_sub(1)
     }
 
     pub async fn execute(&mut self) -> Result<(), Error> {
         match self.lines {
             AstProgram::Block(ref block) => {
-                self.execute_commands(block.nodes.clone()).await?;
-                self.canvas.add_command("end".into());
+                let nodes = block.nodes.clone();
+                self.execute_call("main", (0, 0, 0, 0), nodes).await?;
+                self.canvas.add_command(DrawCommand::End);
             },
             AstProgram::Forest(ref funcs) => {
                 for func in &funcs.0 {
                     if func.name == "main" {
                         let mut new_exec = self.create_subscope();
-                        new_exec.execute_commands(func.block.nodes.clone()).await?;
-                        self.canvas.add_command("end".into());
+                        new_exec.execute_call("main", func.header, func.block.nodes.clone()).await?;
+                        self.canvas.add_command(DrawCommand::End);
                         return Ok(());
                     }
                 }
@@ -499,10 +653,10 @@ impl Execution {
                 for func in &funcs.0 {
                     if func.name == "keyboard" {
                         let mut new_exec = self.create_subscope();
-                        new_exec.execute_init(func.args.get(0).unwrap().0.clone(), 
+                        new_exec.execute_init(func.args.first().unwrap().0.clone(), 
                         Expression{expr_type: ExpressionType::Value(
                                     BaseValue{val: BaseValueType::Int(key), coords: func.header}), coords:func.header}, func.header).await?;
-                        new_exec.execute_commands(func.block.nodes.clone()).await?;
+                        new_exec.execute_call("keyboard", func.header, func.block.nodes.clone()).await?;
                     }
                 }
                 Ok(())
@@ -517,13 +671,13 @@ impl Execution {
                 for func in &funcs.0 {
                     if func.name == "mouse" {
                         let mut new_exec = self.create_subscope();
-                        new_exec.execute_init(func.args.get(0).unwrap().0.clone(), 
+                        new_exec.execute_init(func.args.first().unwrap().0.clone(), 
                         Expression{expr_type: ExpressionType::Value(
                                     BaseValue{val: BaseValueType::Int(x), coords: func.header}), coords:func.header}, func.header).await?;
                         new_exec.execute_init(func.args.get(1).unwrap().0.clone(), 
                         Expression{expr_type: ExpressionType::Value(
                                     BaseValue{val: BaseValueType::Int(y), coords: func.header}), coords:func.header}, func.header).await?;
-                        new_exec.execute_commands(func.block.nodes.clone()).await?;
+                        new_exec.execute_call("mouse", func.header, func.block.nodes.clone()).await?;
                     }
                 }
                 Ok(())
@@ -531,10 +685,13 @@ impl Execution {
         }
     }
 
-    pub fn execute_commands<'a>(&'a mut self, nodes : Vec<AstNode>) -> Pin<Box<dyn Future<Output = Result<Option<BaseValue>, Error>> + 'a>> {
+    pub fn execute_commands<'a>(&'a mut self, nodes : Vec<AstNode>) -> Pin<Box<dyn Future<Output = Result<Flow, Error>> + 'a>> {
         Box::pin(async move {
-            TimeoutFuture::new(1).await;
+            self.timer.tick().await;
             for line in nodes {
+                self.budget.spend(1);
+                let depth = self.enter_statement();
+                self.debugger.before_statement(line.coords, depth).await;
                 match line.statement {
                     AstStatement::Command { name, args } => {
                         self.execute_function(&name, args, line.coords).await?;
@@ -545,18 +702,20 @@ impl Execution {
                     AstStatement::SetVal { val, expr } => {
                         self.execute_set(&val, expr, line.coords).await?;
                     }
+                    AstStatement::Update { val, expr } => {
+                        self.execute_update(&val, expr, line.coords).await?;
+                    }
                     
                     AstStatement::If { clause, block, else_block } => {
                         if let BaseValueType::Bool(val) = self.calculate_expression(clause).await?.val {
                             let mut new_exec = self.create_subscope();
-                            if val {
-                                if let Some(return_value) = new_exec.execute_commands(block.nodes).await? {
-                                    return Ok(Some(return_value));
-                                }
-                            } else if let Some(else_block) = else_block {
-                                if let Some(return_value) = new_exec.execute_commands(else_block.nodes).await? {
-                                    return Ok(Some(return_value));
-                                }
+                            let flow = match (val, else_block) {
+                                (true, _) => new_exec.execute_commands(block.nodes).await?,
+                                (false, Some(else_block)) => new_exec.execute_commands(else_block.nodes).await?,
+                                (false, None) => Flow::Next,
+                            };
+                            if flow != Flow::Next {
+                                return Ok(flow);
                             }
                         } else {
                             return Err(Error::runtime(String::from("If clause must be a boolean expression"), line.coords));
@@ -567,10 +726,13 @@ impl Execution {
                             match self.calculate_expression(clause.clone()).await?.val {
                                 BaseValueType::Bool(while_clause) => {
                                     if while_clause {
+                                        self.budget.spend(1);
+                                        self.budget.check(&self.canvas, line.coords)?;
                                         let mut new_exec = self.create_subscope();
-                                        let result = new_exec.execute_commands(block.nodes.clone()).await?;
-                                        if let Some(return_value) = result {
-                                            return Ok(Some(return_value));
+                                        match new_exec.execute_commands(block.nodes.clone()).await? {
+                                            Flow::Break => break,
+                                            Flow::Return(value) => return Ok(Flow::Return(value)),
+                                            _ => {},
                                         }
                                     } else {
                                         break;
@@ -580,40 +742,58 @@ impl Execution {
                             }
                         }
                     },
-                    AstStatement::For { val, from, to, block } => {
-                        if let BaseValueType::Int(f) = self.calculate_expression(from).await?.val {
-                            if let BaseValueType::Int(t) = self.calculate_expression(to).await?.val {
-                                if f <= t {
-                                    for cycle in f..=t {
-                                    if let Some(return_value) = self.execute_for(val.clone(), cycle, block.clone(), line.coords).await?{
-                                        return Ok(Some(return_value));
-                                    }
-                                }                  
-                                } else {
-                                    for cycle in (t..=f).rev() {
-                                        if let Some(return_value) = self.execute_for(val.clone(), cycle, block.clone(), line.coords).await?{
-                                            return Ok(Some(return_value));
-                                        }
-                                    }
-                                }                 
+                    AstStatement::For { val, from, to, step, exclusive, block } => {
+                        let from = self.calculate_expression(from).await?.val;
+                        let to = self.calculate_expression(to).await?.val;
+                        let step = match step {
+                            Some(step) => Some(self.calculate_expression(*step).await?.val),
+                            None => None,
+                        };
+                        let range = ForRange::new(&from, &to, step.as_ref(), exclusive, line.coords)?;
+                        for index in 0..range.count {
+                            match self.execute_for(vec![(val.clone(), range.value(index))], block.clone(), line.coords).await? {
+                                Flow::Break => break,
+                                Flow::Return(value) => return Ok(Flow::Return(value)),
+                                _ => {},
+                            }
+                        }
+                    },
+                    AstStatement::ForEach { index, val, iterable, block } => {
+                        // Goes over the elements the array had when the loop started
+                        let BaseValueType::Array(elems) = self.calculate_expression(iterable).await?.val else {
+                            return Err(Error::runtime(String::from("For loop can only go over an array or a list"), line.coords));
+                        };
+                        for (i, elem) in elems.into_iter().enumerate() {
+                            let mut vars: Vec<_> = index.iter().map(|index| (index.clone(), BaseValueType::Int(i as i32))).collect();
+                            vars.push((val.clone(), elem.val));
+                            match self.execute_for(vars, block.clone(), line.coords).await? {
+                                Flow::Break => break,
+                                Flow::Return(value) => return Ok(Flow::Return(value)),
+                                _ => {},
                             }
                         }
                     },
                     AstStatement::Return { expr } => {
                         let val = self.calculate_expression(expr).await?;
-                        return Ok(Some(val.clone()))
+                        return Ok(Flow::Return(val))
                     },
+                    AstStatement::Break => return Ok(Flow::Break),
+                    AstStatement::Continue => return Ok(Flow::Continue),
                 }
             }
-            Ok(None)
+            Ok(Flow::Next)
         })
     }
 
-    async fn execute_for(&mut self, val: String, cycle : i32, block : AstBlock, coords: Coords) -> Result<Option<BaseValue>, Error> {
+    async fn execute_for(&mut self, vars: Vec<(String, BaseValueType)>, block : AstBlock, coords: Coords) -> Result<Flow, Error> {
+        self.budget.spend(1);
+        self.budget.check(&self.canvas, coords)?;
         let mut new_exec = self.create_subscope();
-        new_exec.execute_init(val, 
-                        Expression{expr_type: ExpressionType::Value(
-                                    BaseValue{val: BaseValueType::Int(cycle), coords}), coords}, coords).await?;
+        for (val, cycle) in vars {
+            new_exec.execute_init(val, 
+                            Expression{expr_type: ExpressionType::Value(
+                                        BaseValue{val: cycle, coords}), coords}, coords).await?;
+        }
         let result = new_exec.execute_commands(block.nodes.clone()).await;
         result
     }
@@ -632,7 +812,15 @@ impl Execution {
                         BaseValueType::Id(var) => {
                             self.get_variable(&var, expr.coords).await
                         },
-                        BaseValueType::FunctionCall(name, exprs, _ ) => {
+                        BaseValueType::FunctionCall(name, exprs) if LIST_MUTATORS.contains(&name.as_str()) => {
+                            // A subscope writes the changed list through to the scope that owns it
+                            let value = self.create_subscope().execute_list_call(&name, exprs, expr.coords).await?;
+                            if let Some(v) = value {
+                                return Ok(v);
+                            }
+                            Err(Error::runtime(format!("Function {} didn't return a value", name), expr.coords))
+                        },
+                        BaseValueType::FunctionCall(name, exprs) => {
                            let mut vals = vec![];
                             for expr in exprs {
                                 let c = expr.coords;
@@ -644,83 +832,114 @@ impl Execution {
                             if let Some(v) = value {
                                 return Ok(v);
                             }
-                            return Err(Error::runtime(format!("Function {} didn't return a value", name), expr.coords));
-                        }
+                            Err(Error::runtime(format!("Function {} didn't return a value", name), expr.coords))
+                        },
+                        BaseValueType::Array(elems) => {
+                            let mut values = vec![];
+                            for elem in elems {
+                                let coords = elem.coords;
+                                values.push(self.calculate_expression(Expression { expr_type: ExpressionType::Value(elem), coords }).await?);
+                            }
+                            Ok(BaseValue { val: BaseValueType::Array(values), coords: base_value.coords })
+                        },
+                        BaseValueType::StructLiteral(name, fields) => {
+                            let mut values = vec![];
+                            for (field, expr) in fields {
+                                values.push((field, self.calculate_expression(expr).await?));
+                            }
+                            Ok(BaseValue { val: BaseValueType::Struct(name, values), coords: base_value.coords })
+                        },
                         x => Ok(BaseValue { val: x, coords: base_value.coords })
                     }
                 },
                 ExpressionType::Unary(op, inner) => {
                     let inner_val = self.calculate_expression(*inner).await?;
-                    match op {
-                        UnaryOperator::UnaryMinus => {
-                            match inner_val.val {
-                                BaseValueType::Int(num) => Ok(int((-1) * num, inner_val.coords)),
-                                BaseValueType::Float(num) => Ok(flt((-1.0) * num, inner_val.coords)),
-                                v => Err(Error::runtime(format!("Cannot apply unary minus to: {:?}", v), inner_val.coords))
-                            }
-                        },
-                        UnaryOperator::NOT => {
-                            match inner_val.val {
-                                BaseValueType::Bool(val) => Ok(bol(!val, inner_val.coords)),
-                                _ => Err(Error::runtime(String::from("Unary not only allowed on bool: {}"), inner_val.coords))
-                            }
-                        }
-                        UnaryOperator::Parentheses => Ok(inner_val)
-                    }
+                    apply_unary(op, inner_val)
                 },
                 ExpressionType::Binary(op, lhs, rhs) => {
                     let left_val = self.calculate_expression(*lhs).await?;
-                    let right_val = self.calculate_expression(*rhs).await?;
-
-                    if let BaseValueType::Int(x) = left_val.val {
-                        if let BaseValueType::Int(y) = right_val.val {
-                            return compare_ints(x, y, op, expr.coords);
-                        }
-                        if let BaseValueType::Float(y) = right_val.val {
-                            let t = x as f32;
-                            return compare_floats(t, y, op, expr.coords);
-                        }
-                    }
-
-                    if let BaseValueType::Float(y) = left_val.val {
-                        if let BaseValueType::Int(x) = right_val.val {
-                            let t = x as f32;
-                            return compare_floats(y, t, op, expr.coords);
-                        }
-                        if let BaseValueType::Float(x) = right_val.val {
-                            return compare_floats(y, x, op, expr.coords);
-                        }
+                    // `&&` and `||` skip the right side once the left one decides the result
+                    match (op, &left_val.val) {
+                        (Operator::AND, BaseValueType::Bool(false)) => return Ok(bol(false, expr.coords)),
+                        (Operator::OR, BaseValueType::Bool(true)) => return Ok(bol(true, expr.coords)),
+                        _ => {},
                     }
-
-                    if let BaseValueType::Bool(a) = left_val.val {
-                        if let BaseValueType::Bool(b) = right_val.val {
-                            return compare_bools(a, b, op, expr.coords);
-                        }
-                    }
-
-                    Err(Error::runtime(String::from("Unsolvable expression!"), expr.coords))
+                    let right_val = self.calculate_expression(*rhs).await?;
+                    apply_binary(op, &left_val, &right_val, expr.coords)
                 },
             }
         })
     }
 }
 
+pub(crate) fn apply_unary(op: UnaryOperator, inner_val: BaseValue) -> Result<BaseValue, Error> {
+    match op {
+        UnaryOperator::UnaryMinus => {
+            match inner_val.val {
+                BaseValueType::Int(num) => Ok(int(-num, inner_val.coords)),
+                BaseValueType::Float(num) => Ok(flt(-num, inner_val.coords)),
+                v => Err(Error::runtime(format!("Cannot apply unary minus to: {:?}", v), inner_val.coords))
+            }
+        },
+        UnaryOperator::NOT => {
+            match inner_val.val {
+                BaseValueType::Bool(val) => Ok(bol(!val, inner_val.coords)),
+                _ => Err(Error::runtime(String::from("Unary not only allowed on bool: {}"), inner_val.coords))
+            }
+        }
+        UnaryOperator::Parentheses => Ok(inner_val)
+    }
+}
+
+pub(crate) fn apply_binary(op: Operator, left_val: &BaseValue, right_val: &BaseValue, coords: Coords) -> Result<BaseValue, Error> {
+    if let BaseValueType::Int(x) = left_val.val {
+        if let BaseValueType::Int(y) = right_val.val {
+            return compare_ints(x, y, op, coords);
+        }
+        if let BaseValueType::Float(y) = right_val.val {
+            let t = x as f32;
+            return compare_floats(t, y, op, coords);
+        }
+    }
+
+    if let BaseValueType::Float(y) = left_val.val {
+        if let BaseValueType::Int(x) = right_val.val {
+            let t = x as f32;
+            return compare_floats(y, t, op, coords);
+        }
+        if let BaseValueType::Float(x) = right_val.val {
+            return compare_floats(y, x, op, coords);
+        }
+    }
+
+    if let BaseValueType::Bool(a) = left_val.val {
+        if let BaseValueType::Bool(b) = right_val.val {
+            return compare_bools(a, b, op, coords);
+        }
+    }
+
+    if let (BaseValueType::StringVal(a), BaseValueType::StringVal(b)) = (&left_val.val, &right_val.val) {
+        return compare_strings(a, b, op, coords);
+    }
+
+    Err(Error::runtime(String::from("Unsolvable expression!"), coords))
+}
 
 fn compare_ints(x: i32, y : i32, op: Operator, coords: Coords) -> Result<BaseValue, Error> {
     match op {
 
-        Operator::EQ => return Ok(bol(x == y, coords)),
-        Operator::NQ => return Ok(bol(x != y, coords)),
-        Operator::GT => return Ok(bol(x > y, coords)),
-        Operator::LT => return Ok(bol(x < y, coords)),
-        Operator::GQ => return Ok(bol(x >= y, coords)),
-        Operator::LQ => return Ok(bol(x <= y, coords)),
+        Operator::EQ => Ok(bol(x == y, coords)),
+        Operator::NQ => Ok(bol(x != y, coords)),
+        Operator::GT => Ok(bol(x > y, coords)),
+        Operator::LT => Ok(bol(x < y, coords)),
+        Operator::GQ => Ok(bol(x >= y, coords)),
+        Operator::LQ => Ok(bol(x <= y, coords)),
         
         Operator::Plus => Ok(int(x + y, coords)),
         Operator::Minus => Ok(int(x - y, coords)),
         Operator::Mult => Ok(int(x * y, coords)),
-        Operator::Div => if y == 0 { Err(Error::runtime(format!("Division by 0"), coords)) } else {Ok(int(x / y, coords)) },
-        Operator::Mod => Ok(int(x % y, coords)),
+        Operator::Div => if y == 0 { Err(Error::runtime("Division by 0".to_string(), coords)) } else {Ok(int(x / y, coords)) },
+        Operator::Mod => if y == 0 { Err(Error::runtime("Division by 0".to_string(), coords)) } else {Ok(int(x % y, coords)) },
         v => Err(Error::runtime(format!("Cannot apply operator {:?} to values of type int!",v), coords))   
     }
 }
@@ -738,14 +957,29 @@ fn compare_floats(x: f32, y : f32, op: Operator, coords: Coords) -> Result<BaseV
         Operator::Plus => Ok(flt(x + y, coords)),
         Operator::Minus => Ok(flt(x - y, coords)),
         Operator::Mult => Ok(flt(x * y, coords)),
-        Operator::Div => if y == 0.0 { Err(Error::runtime(format!("Division by 0"), coords)) } else {Ok(flt(x / y, coords)) },
-        Operator::Mod => Ok(flt(x % y, coords)),
+        Operator::Div => if y == 0.0 { Err(Error::runtime("Division by 0".to_string(), coords)) } else {Ok(flt(x / y, coords)) },
+        Operator::Mod => if y == 0.0 { Err(Error::runtime("Division by 0".to_string(), coords)) } else {Ok(flt(x % y, coords)) },
 
         v => Err(Error::runtime(format!("Cannot apply operator {:?} to values of type float!",v), coords))
 
     }
 }
 
+fn compare_strings(a: &str, b: &str, op: Operator, coords: Coords) -> Result<BaseValue, Error> {
+    match op {
+        Operator::EQ => Ok(bol(a == b, coords)),
+        Operator::NQ => Ok(bol(a != b, coords)),
+        Operator::GT => Ok(bol(a > b, coords)),
+        Operator::LT => Ok(bol(a < b, coords)),
+        Operator::GQ => Ok(bol(a >= b, coords)),
+        Operator::LQ => Ok(bol(a <= b, coords)),
+
+        Operator::Plus => Ok(BaseValue { val: BaseValueType::StringVal(format!("{}{}", a, b)), coords }),
+
+        o => Err(Error::runtime(format!("Cannot apply operator '{:?}' to values of type string!", o), coords))
+    }
+}
+
 fn compare_bools(a: bool, b : bool, op: Operator, coords: Coords) -> Result<BaseValue, Error> {
     match op {
 
@@ -0,0 +1,610 @@
+use quanta_parser::{ast::{BaseType, BaseValue, BaseValueType, Coords, Type}, error::Error};
+use crate::utils::{command::{DrawCommand, Rgba}, inspect::Value};
+use BaseType::*;
+
+use super::{flt, get_random, int, Execution};
+
+pub(crate) type Run = fn(&mut Execution, &str, Vec<BaseValue>, Coords) -> Result<Option<BaseValue>, Error>;
+
+pub(crate) enum Params {
+    Fixed(&'static [(&'static str, BaseType)]),
+    // Checked by a rule of its own in the type checker, the text is what the docs show
+    Special(&'static str),
+}
+
+// A function every program can call. `returns` is only read for `Fixed` params
+pub(crate) struct Builtin {
+    pub name: &'static str,
+    pub params: Params,
+    pub returns: Option<BaseType>,
+    pub doc: &'static str,
+    // `None` for the list functions, they change a variable and go through `change_list`
+    pub run: Option<Run>,
+}
+
+impl Builtin {
+    // `circle(int x, int y, int radius)`, `round(float value) -> int`
+    pub fn signature(&self) -> String {
+        match &self.params {
+            Params::Fixed(params) => {
+                let params = params.iter().map(|(name, typ)| format!("{} {}", typ, name)).collect::<Vec<_>>().join(", ");
+                match &self.returns {
+                    Some(typ) => format!("{}({}) -> {}", self.name, params, typ),
+                    None => format!("{}({})", self.name, params),
+                }
+            },
+            Params::Special(text) => format!("{}{}", self.name, text),
+        }
+    }
+}
+
+pub(crate) fn builtin(name: &str) -> Option<&'static Builtin> {
+    BUILTINS.iter().find(|builtin| builtin.name == name)
+}
+
+// `[{"name": ..., "signature": ..., "doc": ...}]` for the editor
+pub fn to_json() -> String {
+    let builtins: Vec<_> = BUILTINS.iter()
+        .map(|builtin| serde_json::json!({ "name": builtin.name, "signature": builtin.signature(), "doc": builtin.doc }))
+        .collect();
+    serde_json::to_string(&builtins).expect("Builtins are always representable as JSON")
+}
+
+fn set_color(color: &std::sync::Mutex<Rgba>, function_name: &str, vals: &[BaseValue], coords: Coords) -> Result<Option<BaseValue>, Error> {
+    if let Some(BaseValueType::Color(r, g, b, a)) = vals.first().map(|v| &v.val) {
+        *color.lock().unwrap() = Rgba::new(*r, *g, *b, *a);
+        Ok(None)
+    } else {
+        Err(Error::runtime(format!("Incorrect arguments for {} function: expected a color, got {:?}!", function_name, vals.first()), coords))
+    }
+}
+
+// min, max, clamp and abs give an int for ints, a float once any float is among them
+fn numeric(function_name: &str, vals: Vec<BaseValue>, coords: Coords) -> Result<Option<BaseValue>, Error> {
+    let numbers = vals.iter()
+        .map(|v| match v.val { BaseValueType::Int(i) => Some(i as f64), BaseValueType::Float(f) => Some(f as f64), _ => None })
+        .collect::<Option<Vec<f64>>>()
+        .ok_or_else(|| Error::runtime(format!("{}: expected int or float arguments", function_name), coords))?;
+    let value = match (function_name, numbers.as_slice()) {
+        ("abs", [x]) => x.abs(),
+        ("min", [a, b]) => a.min(*b),
+        ("max", [a, b]) => a.max(*b),
+        ("clamp", [x, low, high]) => {
+            if low > high {
+                return Err(Error::runtime(format!("clamp: lower bound {} is above upper bound {}", low, high), coords));
+            }
+            x.clamp(*low, *high)
+        },
+        _ => return Err(Error::runtime(format!("{}: wrong number of arguments", function_name), coords)),
+    };
+    if vals.iter().all(|v| matches!(v.val, BaseValueType::Int(_))) {
+        Ok(Some(int(value as i32, coords)))
+    } else {
+        Ok(Some(flt(value as f32, coords)))
+    }
+}
+
+fn print(exec: &mut Execution, function_name: &str, vals: Vec<BaseValue>, coords: Coords) -> Result<Option<BaseValue>, Error> {
+    let mut text = vals.iter()
+        .map(|val| Value::from_base(val).map(|v| v.printed()))
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| Error::runtime(format!("{}: can't print this value", function_name), coords))?
+        .join(" ");
+    if function_name == "println" {
+        text.push('\n');
+    }
+    exec.canvas.print(text);
+    Ok(None)
+}
+
+// Angles are in degrees, like in `arc`
+fn trigonometry(_: &mut Execution, function_name: &str, vals: Vec<BaseValue>, coords: Coords) -> Result<Option<BaseValue>, Error> {
+    let angle = expect_arg!(function_name, vals, 0, Float(v) => v.to_radians());
+    let value = match function_name { "sin" => angle.sin(), "cos" => angle.cos(), _ => angle.tan() };
+    Ok(Some(flt(value, coords)))
+}
+
+pub(crate) static BUILTINS: &[Builtin] = &[
+    Builtin {
+        name: "circle",
+        params: Params::Fixed(&[("x", Int), ("y", Int), ("radius", Int)]),
+        returns: None,
+        doc: "Circle with the center in (x, y)",
+        run: Some(|exec, _, vals, _| {
+            let x = expect_arg!("circle", vals, 0, Int(v) => *v);
+            let y = expect_arg!("circle", vals, 1, Int(v) => *v);
+            let r = expect_arg!("circle", vals, 2, Int(v) => *v);
+            exec.canvas.add_command(DrawCommand::Circle { x, y, r, style: exec.shape_style() });
+            Ok(None)
+        }),
+    },
+    Builtin {
+        name: "line",
+        params: Params::Fixed(&[("x1", Int), ("y1", Int), ("x2", Int), ("y2", Int)]),
+        returns: None,
+        doc: "Line from (x1, y1) to (x2, y2)",
+        run: Some(|exec, _, vals, _| {
+            let x1 = expect_arg!("line", vals, 0, Int(v) => *v);
+            let y1 = expect_arg!("line", vals, 1, Int(v) => *v);
+            let x2 = expect_arg!("line", vals, 2, Int(v) => *v);
+            let y2 = expect_arg!("line", vals, 3, Int(v) => *v);
+            exec.canvas.add_command(DrawCommand::Line { x1, y1, x2, y2, style: exec.line_style() });
+            Ok(None)
+        }),
+    },
+    Builtin {
+        name: "rectangle",
+        params: Params::Fixed(&[("x1", Int), ("y1", Int), ("x2", Int), ("y2", Int)]),
+        returns: None,
+        doc: "Rectangle with the corners (x1, y1) and (x2, y2)",
+        run: Some(|exec, _, vals, _| {
+            let x1 = expect_arg!("rectangle", vals, 0, Int(v) => *v);
+            let y1 = expect_arg!("rectangle", vals, 1, Int(v) => *v);
+            let x2 = expect_arg!("rectangle", vals, 2, Int(v) => *v);
+            let y2 = expect_arg!("rectangle", vals, 3, Int(v) => *v);
+            exec.canvas.add_command(DrawCommand::Rectangle { x1, y1, x2, y2, style: exec.shape_style() });
+            Ok(None)
+        }),
+    },
+    Builtin {
+        name: "polygon",
+        params: Params::Special("(int x1, int y1, int x2, int y2, int x3, int y3, ...)"),
+        returns: None,
+        doc: "Polygon through three or more points",
+        run: Some(|exec, _, vals, _| {
+            let mut nums = vec![];
+            for val in &vals {
+                if let BaseValueType::Int(num) = val.val {
+                    nums.push(num);
+                } else {
+                    return Err(Error::runtime(String::from("Incorrect arguments for polygon function!"), val.coords));
+                }
+            }
+            let points = nums.chunks_exact(2).map(|p| (p[0], p[1])).collect();
+            exec.canvas.add_command(DrawCommand::Polygon { points, style: exec.shape_style() });
+            Ok(None)
+        }),
+    },
+    Builtin {
+        name: "arc",
+        params: Params::Fixed(&[("circle_x", Int), ("circle_y", Int), ("radius", Int), ("angle_from", Int), ("angle_to", Int)]),
+        returns: None,
+        doc: "Arc of a circle, angles are in degrees clockwise from the X axis",
+        run: Some(|exec, _, vals, _| {
+            let x = expect_arg!("arc", vals, 0, Int(v) => *v);
+            let y = expect_arg!("arc", vals, 1, Int(v) => *v);
+            let r = expect_arg!("arc", vals, 2, Int(v) => *v);
+            let start = expect_arg!("arc", vals, 3, Int(v) => *v);
+            let end = expect_arg!("arc", vals, 4, Int(v) => *v);
+            exec.canvas.add_command(DrawCommand::Arc { x, y, r, start, end, style: exec.shape_style() });
+            Ok(None)
+        }),
+    },
+    Builtin {
+        name: "text",
+        params: Params::Fixed(&[("x", Int), ("y", Int), ("text", StringType)]),
+        returns: None,
+        doc: "Writes the text in the figure color, (x, y) is its top left corner",
+        run: Some(|exec, _, vals, _| {
+            let x = expect_arg!("text", vals, 0, Int(v) => *v);
+            let y = expect_arg!("text", vals, 1, Int(v) => *v);
+            let text = expect_arg!("text", vals, 2, StringVal(v) => v.clone());
+            exec.canvas.add_command(DrawCommand::Text { x, y, text, style: exec.text_style() });
+            Ok(None)
+        }),
+    },
+    Builtin {
+        name: "setLineColor",
+        params: Params::Fixed(&[("color", Color)]),
+        returns: None,
+        doc: "Color of lines and of the edges of figures",
+        run: Some(|exec, name, vals, coords| set_color(&exec.line_color, name, &vals, coords)),
+    },
+    Builtin {
+        name: "setFigureColor",
+        params: Params::Fixed(&[("color", Color)]),
+        returns: None,
+        doc: "Color the figures are filled with",
+        run: Some(|exec, name, vals, coords| set_color(&exec.figure_color, name, &vals, coords)),
+    },
+    Builtin {
+        name: "setLineWidth",
+        params: Params::Fixed(&[("width", Int)]),
+        returns: None,
+        doc: "Width of lines in pixels",
+        run: Some(|exec, _, vals, coords| {
+            let width = expect_arg!("setLineWidth", vals, 0, Int(width) => *width);
+            if width < 0 {
+                return Err(Error::runtime(String::from("Line width can't be negative!"), coords));
+            }
+            *exec.line_width.lock().unwrap() = width;
+            Ok(None)
+        }),
+    },
+    Builtin {
+        name: "setFontSize",
+        params: Params::Fixed(&[("size", Int)]),
+        returns: None,
+        doc: "Font size of `text` in pixels",
+        run: Some(|exec, _, vals, coords| {
+            let size = expect_arg!("setFontSize", vals, 0, Int(size) => *size);
+            if size <= 0 {
+                return Err(Error::runtime(String::from("Font size must be positive!"), coords));
+            }
+            *exec.font_size.lock().unwrap() = size;
+            Ok(None)
+        }),
+    },
+    Builtin {
+        name: "setFont",
+        params: Params::Fixed(&[("font", StringType)]),
+        returns: None,
+        doc: "Font of `text`, like 'serif'",
+        run: Some(|exec, _, vals, coords| {
+            let font = expect_arg!("setFont", vals, 0, StringVal(font) => font.trim().to_string());
+            if font.is_empty() {
+                return Err(Error::runtime(String::from("Font name can't be empty!"), coords));
+            }
+            *exec.font.lock().unwrap() = font;
+            Ok(None)
+        }),
+    },
+    Builtin {
+        name: "print",
+        params: Params::Special("(any values, ...)"),
+        returns: None,
+        doc: "Prints the values separated by spaces",
+        run: Some(print),
+    },
+    Builtin {
+        name: "println",
+        params: Params::Special("(any values, ...)"),
+        returns: None,
+        doc: "Prints the values separated by spaces and ends the line",
+        run: Some(print),
+    },
+    Builtin {
+        name: "sleep",
+        params: Params::Fixed(&[("sleep_time", Int)]),
+        returns: None,
+        doc: "Stops for the given number of milliseconds",
+        run: Some(|exec, _, vals, coords| {
+            let sleep_time = expect_arg!("sleep", vals, 0, Int(time) => *time);
+            if sleep_time < 0 {
+                return Err(Error::runtime(String::from("Sleep time can't be negative!"), coords));
+            }
+            exec.canvas.add_command(DrawCommand::Sleep { ms: sleep_time });
+            exec.budget.reset();
+            Ok(None)
+        }),
+    },
+    Builtin {
+        name: "animate",
+        params: Params::Fixed(&[]),
+        returns: None,
+        doc: "From now on figures are shown only on `frame()`",
+        run: Some(|exec, _, _, _| {
+            exec.canvas.add_command(DrawCommand::Animate);
+            Ok(None)
+        }),
+    },
+    Builtin {
+        name: "frame",
+        params: Params::Fixed(&[]),
+        returns: None,
+        doc: "Shows the next frame of the animation",
+        run: Some(|exec, _, _, _| {
+            exec.canvas.add_command(DrawCommand::Frame);
+            exec.budget.reset();
+            Ok(None)
+        }),
+    },
+    Builtin {
+        name: "clear",
+        params: Params::Fixed(&[]),
+        returns: None,
+        doc: "Clears the canvas",
+        run: Some(|exec, _, _, _| {
+            exec.canvas.add_command(DrawCommand::Clear);
+            Ok(None)
+        }),
+    },
+    Builtin {
+        name: "rgb",
+        params: Params::Fixed(&[("red", Int), ("green", Int), ("blue", Int)]),
+        returns: Some(Color),
+        doc: "Color from its red, green and blue parts, each from 0 to 255",
+        run: Some(|_, _, vals, coords| {
+            let r = expect_arg!("rgb", vals, 0, Int(v) => *v);
+            let g = expect_arg!("rgb", vals, 1, Int(v) => *v);
+            let b = expect_arg!("rgb", vals, 2, Int(v) => *v);
+            if !(0..=255).contains(&r) || !(0..=255).contains(&g) || !(0..=255).contains(&b) {
+                return Err(Error::runtime(String::from("RGB values must be between 0 and 255"), coords));
+            }
+            Ok(Some(BaseValue{val: BaseValueType::Color(r as u8, g as u8, b as u8, 255), coords}))
+        }),
+    },
+    Builtin {
+        name: "Color::Random",
+        params: Params::Fixed(&[]),
+        returns: Some(Color),
+        doc: "A random color",
+        run: Some(|_, _, _, coords| {
+            let r = (get_random() * 255.0) as u8;
+            let g = (get_random() * 255.0) as u8;
+            let b = (get_random() * 255.0) as u8;
+            Ok(Some(BaseValue{val: BaseValueType::Color(r, g, b, 255), coords}))
+        }),
+    },
+    Builtin {
+        name: "string",
+        params: Params::Special("(int|float|bool value) -> string"),
+        returns: None,
+        doc: "The value as text",
+        run: Some(|_, _, vals, coords| {
+            // Same text the REPL shows, floats keep their decimal point
+            let text = match vals.first().map(|v| &v.val) {
+                Some(BaseValueType::StringVal(s)) => s.clone(),
+                Some(v @ (BaseValueType::Int(_) | BaseValueType::Float(_) | BaseValueType::Bool(_))) => {
+                    Value::from_base(&BaseValue { val: v.clone(), coords }).unwrap().to_string()
+                },
+                _ => return Err(Error::runtime(String::from("string: expected an int, float or bool argument"), coords)),
+            };
+            Ok(Some(BaseValue { val: BaseValueType::StringVal(text), coords }))
+        }),
+    },
+    Builtin {
+        name: "len",
+        params: Params::Special("(list|array|string value) -> int"),
+        returns: None,
+        doc: "Number of elements of a list or an array, or of letters of a string",
+        run: Some(|_, _, vals, coords| {
+            let len = match vals.first().map(|v| &v.val) {
+                Some(BaseValueType::Array(elems)) => elems.len(),
+                Some(BaseValueType::StringVal(s)) => s.chars().count(),
+                _ => return Err(Error::runtime(String::from("len: expected a list, an array or a string"), coords)),
+            };
+            Ok(Some(int(len as i32, coords)))
+        }),
+    },
+    Builtin {
+        name: "push",
+        params: Params::Special("(list<T> list, T value)"),
+        returns: None,
+        doc: "Adds the value to the end of the list",
+        run: None,
+    },
+    Builtin {
+        name: "pop",
+        params: Params::Special("(list<T> list) -> T"),
+        returns: None,
+        doc: "Takes the last element out of the list",
+        run: None,
+    },
+    Builtin {
+        name: "insert",
+        params: Params::Special("(list<T> list, int index, T value)"),
+        returns: None,
+        doc: "Puts the value into the list at the index",
+        run: None,
+    },
+    Builtin {
+        name: "remove",
+        params: Params::Special("(list<T> list, int index) -> T"),
+        returns: None,
+        doc: "Takes the element at the index out of the list",
+        run: None,
+    },
+    Builtin {
+        name: "round",
+        params: Params::Fixed(&[("value", Float)]),
+        returns: Some(Int),
+        doc: "Rounds to the nearest int",
+        run: Some(|_, _, vals, coords| {
+            let num = expect_arg!("round", vals, 0, Float(v) => *v);
+            Ok(Some(int(num.round() as i32, coords)))
+        }),
+    },
+    Builtin {
+        name: "floor",
+        params: Params::Fixed(&[("value", Float)]),
+        returns: Some(Int),
+        doc: "Rounds down",
+        run: Some(|_, _, vals, coords| {
+            let num = expect_arg!("floor", vals, 0, Float(v) => *v);
+            Ok(Some(int(num.floor() as i32, coords)))
+        }),
+    },
+    Builtin {
+        name: "ceil",
+        params: Params::Fixed(&[("value", Float)]),
+        returns: Some(Int),
+        doc: "Rounds up",
+        run: Some(|_, _, vals, coords| {
+            let num = expect_arg!("ceil", vals, 0, Float(v) => *v);
+            Ok(Some(int(num.ceil() as i32, coords)))
+        }),
+    },
+    Builtin {
+        name: "int",
+        params: Params::Fixed(&[("value", Float)]),
+        returns: Some(Int),
+        doc: "The float without its fraction, 2.7 gives 2 and -2.7 gives -2",
+        run: Some(|_, _, vals, coords| {
+            let num = expect_arg!("int", vals, 0, Float(v) => *v);
+            Ok(Some(int(num.trunc() as i32, coords)))
+        }),
+    },
+    Builtin {
+        name: "decimal",
+        params: Params::Fixed(&[("value", Int)]),
+        returns: Some(Float),
+        doc: "The int as a float, for division with a fraction",
+        run: Some(|_, _, vals, coords| {
+            let num = expect_arg!("decimal", vals, 0, Int(v) => *v);
+            Ok(Some(flt(num as f32, coords)))
+        }),
+    },
+    Builtin {
+        name: "sqrt",
+        params: Params::Fixed(&[("value", Float)]),
+        returns: Some(Float),
+        doc: "Square root",
+        run: Some(|_, _, vals, coords| {
+            let num = expect_arg!("sqrt", vals, 0, Float(v) => *v);
+            if num < 0.0 {
+                return Err(Error::runtime(String::from("Cannot calculate square root of a negative number"), coords));
+            }
+            Ok(Some(flt(num.sqrt(), coords)))
+        }),
+    },
+    Builtin {
+        name: "abs",
+        params: Params::Special("(int|float value) -> int|float"),
+        returns: None,
+        doc: "Absolute value, of the same type as the argument",
+        run: Some(|_, name, vals, coords| numeric(name, vals, coords)),
+    },
+    Builtin {
+        name: "min",
+        params: Params::Special("(int|float a, int|float b) -> int|float"),
+        returns: None,
+        doc: "The smaller of two ints or of two floats",
+        run: Some(|_, name, vals, coords| numeric(name, vals, coords)),
+    },
+    Builtin {
+        name: "max",
+        params: Params::Special("(int|float a, int|float b) -> int|float"),
+        returns: None,
+        doc: "The bigger of two ints or of two floats",
+        run: Some(|_, name, vals, coords| numeric(name, vals, coords)),
+    },
+    Builtin {
+        name: "clamp",
+        params: Params::Special("(int|float value, int|float low, int|float high) -> int|float"),
+        returns: None,
+        doc: "The value kept between low and high",
+        run: Some(|_, name, vals, coords| numeric(name, vals, coords)),
+    },
+    Builtin {
+        name: "sin",
+        params: Params::Fixed(&[("angle", Float)]),
+        returns: Some(Float),
+        doc: "Sine of an angle in degrees",
+        run: Some(trigonometry),
+    },
+    Builtin {
+        name: "cos",
+        params: Params::Fixed(&[("angle", Float)]),
+        returns: Some(Float),
+        doc: "Cosine of an angle in degrees",
+        run: Some(trigonometry),
+    },
+    Builtin {
+        name: "tan",
+        params: Params::Fixed(&[("angle", Float)]),
+        returns: Some(Float),
+        doc: "Tangent of an angle in degrees",
+        run: Some(trigonometry),
+    },
+    Builtin {
+        name: "atan2",
+        params: Params::Fixed(&[("y", Float), ("x", Float)]),
+        returns: Some(Float),
+        doc: "Angle in degrees towards the point (x, y)",
+        run: Some(|_, _, vals, coords| {
+            let y = expect_arg!("atan2", vals, 0, Float(v) => *v);
+            let x = expect_arg!("atan2", vals, 1, Float(v) => *v);
+            Ok(Some(flt(y.atan2(x).to_degrees(), coords)))
+        }),
+    },
+    Builtin {
+        name: "radians",
+        params: Params::Fixed(&[("degrees", Float)]),
+        returns: Some(Float),
+        doc: "Degrees to radians",
+        run: Some(|_, _, vals, coords| Ok(Some(flt(expect_arg!("radians", vals, 0, Float(v) => v.to_radians()), coords)))),
+    },
+    Builtin {
+        name: "degrees",
+        params: Params::Fixed(&[("radians", Float)]),
+        returns: Some(Float),
+        doc: "Radians to degrees",
+        run: Some(|_, _, vals, coords| Ok(Some(flt(expect_arg!("degrees", vals, 0, Float(v) => v.to_degrees()), coords)))),
+    },
+    Builtin {
+        name: "pow",
+        params: Params::Fixed(&[("base", Float), ("power", Float)]),
+        returns: Some(Float),
+        doc: "base to the power",
+        run: Some(|_, _, vals, coords| {
+            let base = expect_arg!("pow", vals, 0, Float(v) => *v);
+            let power = expect_arg!("pow", vals, 1, Float(v) => *v);
+            Ok(Some(flt(base.powf(power), coords)))
+        }),
+    },
+    Builtin {
+        name: "exp",
+        params: Params::Fixed(&[("value", Float)]),
+        returns: Some(Float),
+        doc: "e to the power of the value",
+        run: Some(|_, _, vals, coords| Ok(Some(flt(expect_arg!("exp", vals, 0, Float(v) => v.exp()), coords)))),
+    },
+    Builtin {
+        name: "log",
+        params: Params::Fixed(&[("value", Float)]),
+        returns: Some(Float),
+        doc: "Natural logarithm",
+        run: Some(|_, _, vals, coords| {
+            let num = expect_arg!("log", vals, 0, Float(v) => *v);
+            if num <= 0.0 {
+                return Err(Error::runtime(String::from("Cannot calculate logarithm of a number that is not positive"), coords));
+            }
+            Ok(Some(flt(num.ln(), coords)))
+        }),
+    },
+    Builtin {
+        name: "lerp",
+        params: Params::Fixed(&[("from", Float), ("to", Float), ("t", Float)]),
+        returns: Some(Float),
+        doc: "The point between from and to, from at t = 0.0 and to at t = 1.0",
+        run: Some(|_, _, vals, coords| {
+            let from = expect_arg!("lerp", vals, 0, Float(v) => *v);
+            let to = expect_arg!("lerp", vals, 1, Float(v) => *v);
+            let t = expect_arg!("lerp", vals, 2, Float(v) => *v);
+            Ok(Some(flt(from + (to - from) * t, coords)))
+        }),
+    },
+    Builtin {
+        name: "map",
+        params: Params::Fixed(&[("value", Float), ("from_low", Float), ("from_high", Float), ("to_low", Float), ("to_high", Float)]),
+        returns: Some(Float),
+        doc: "Moves the value from one range to the other",
+        run: Some(|_, _, vals, coords| {
+            let value = expect_arg!("map", vals, 0, Float(v) => *v);
+            let from_low = expect_arg!("map", vals, 1, Float(v) => *v);
+            let from_high = expect_arg!("map", vals, 2, Float(v) => *v);
+            let to_low = expect_arg!("map", vals, 3, Float(v) => *v);
+            let to_high = expect_arg!("map", vals, 4, Float(v) => *v);
+            if from_low == from_high {
+                return Err(Error::runtime(String::from("map: the range to map from is empty"), coords));
+            }
+            Ok(Some(flt(to_low + (value - from_low) * (to_high - to_low) / (from_high - from_low), coords)))
+        }),
+    },
+    Builtin {
+        name: "random",
+        params: Params::Fixed(&[("lower_bound", Int), ("upper_bound", Int)]),
+        returns: Some(Int),
+        doc: "A random int from lower_bound to upper_bound, both included",
+        run: Some(|_, _, vals, coords| {
+            let mut lower_bound = expect_arg!("random", vals, 0, Int(v) => *v);
+            let mut upper_bound = expect_arg!("random", vals, 1, Int(v) => *v);
+            if lower_bound >= upper_bound {
+                std::mem::swap(&mut lower_bound, &mut upper_bound);
+            }
+            let random_value = (get_random() * ((upper_bound - lower_bound + 1) as f64) + (lower_bound as f64)) as i32;
+            Ok(Some(int(random_value.min(upper_bound), coords)))
+        }),
+    },
+];
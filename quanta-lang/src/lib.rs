@@ -2,14 +2,23 @@ mod utils;
 mod compiler;
 mod program;
 mod execution;
-//mod tests;
+mod bytecode;
+mod vm;
+#[cfg(test)]
+mod tests;
 mod runtime;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod render;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod repl;
+pub mod svg;
 //mod linear_execution;
 //mod linear_runtime;
 
 use wasm_bindgen::prelude::*;
 
 use crate::utils::message::{CompilationMessage};
+pub use crate::utils::{command, inspect, timer};
 
 // When the `wee_alloc` feature is enabled, use `wee_alloc` as the global
 // allocator.
@@ -29,7 +38,14 @@ pub fn greet() {
 
 
 
+// Name, signature and doc of every builtin, for autocompletion in the editor
 #[wasm_bindgen]
+pub fn builtins_json() -> String {
+    execution::builtins::to_json()
+}
+
+#[wasm_bindgen]
+#[derive(Default)]
 pub struct Compiler {
 
 
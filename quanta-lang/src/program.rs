@@ -1,9 +1,13 @@
 use std::collections::{HashMap, HashSet};
 
 use quanta_parser::{ast::*, error::Error};
+use crate::execution::builtins::{Params, BUILTINS};
 use BaseType::*;
 use TypeName::*;
 
+const LIST_BUILTINS: [&str; 5] = ["len", "push", "pop", "insert", "remove"];
+const NUMERIC_BUILTINS: [&str; 4] = ["abs", "min", "max", "clamp"];
+
 #[derive(Debug, Clone)]
 pub struct Scope {
     variables: HashMap<String, (Type, Expression)>,
@@ -27,9 +31,13 @@ pub struct Program {
     pub lines: AstProgram, 
     pub scope : Scope,
     pub global_vars : HashMap<String, (Type, Expression)>,
-    pub function_defs : HashMap<String, (Vec<(String, Type)>, Option<Type>)>,
-    pub functions : HashMap<String, (Vec<(String, Type)>, Option<Type>, AstBlock)>,
-    keywords: HashSet<String>
+    pub function_defs : HashMap<String, FunctionSignature>,
+    pub functions : HashMap<String, FunctionBody>,
+    // Field names and types of every struct
+    pub structs : HashMap<String, Vec<(String, Type)>>,
+    keywords: HashSet<String>,
+    // Whether `break` and `continue` have a loop to act on
+    in_loop: bool,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -49,96 +57,45 @@ impl ReturnType {
     }
 }
 
-fn int_type() -> Type 
+fn string_type() -> Type
 {
-    Type { type_name: Primitive(Int), is_const: false }
+    Type {type_name: Primitive(StringType), is_const: false}
 }
 
-fn float_type() -> Type
-{
-    Type {type_name: Primitive(Float), is_const: false}
+// A loop body that returns on every path still doesn't return if a `break` can get out first
+fn leaves_early(block: &AstBlock, body: ReturnType) -> ReturnType {
+    fn breaks(block: &AstBlock) -> bool {
+        block.nodes.iter().any(|node| match &node.statement {
+            AstStatement::Break => true,
+            AstStatement::If { block, else_block, .. } => breaks(block) || else_block.as_ref().is_some_and(breaks),
+            _ => false,
+        })
+    }
+    match body {
+        ReturnType::Full(t) if breaks(block) => ReturnType::Partial(t),
+        body => body,
+    }
 }
 
-fn color_type() -> Type
-{
-    Type {type_name: Primitive(Color), is_const: false}
+// User functions are added next to the builtins by `type_check`
+fn builtin_defs() -> HashMap<String, FunctionSignature> {
+    BUILTINS.iter().map(|builtin| {
+        let params = match builtin.params {
+            Params::Fixed(params) => params.iter().map(|(name, typ)| (String::from(*name), Type::typ(typ.clone()))).collect(),
+            Params::Special(_) => vec![],
+        };
+        (String::from(builtin.name), (params, builtin.returns.clone().map(Type::typ)))
+    }).collect()
 }
 
 pub fn create_program(ast: AstProgram) -> Program {
-    Program {lines: ast, scope: Scope { variables: HashMap::new(), outer_scope: Box::new(None) }, 
+    let keywords = BUILTINS.iter().map(|builtin| builtin.name).chain([
+        "PI", "for", "while", "break", "continue", "global", "func", "struct", "if", "else",
+        "int", "bool", "color", "float", "string", "array", "list", "Color", "true", "false"
+    ]).map(String::from).collect();
+    Program {lines: ast, scope: Scope { variables: HashMap::new(), outer_scope: Box::new(None) },
     global_vars: HashMap::new(),
-    functions: HashMap::new(), function_defs: HashMap::from([
-        (String::from("circle"), (vec![
-            (String::from("x"), int_type()),
-            (String::from("y"), int_type()),
-            (String::from("radius"), int_type())
-        ], None)),
-        (String::from("line"), (vec![
-            (String::from("x1"), int_type()),
-            (String::from("y1"), int_type()),
-            (String::from("x2"), int_type()),
-            (String::from("y2"), int_type())
-        ], None)),
-        (String::from("rectangle"), (vec![
-            (String::from("x1"), int_type()),
-            (String::from("y1"), int_type()),
-            (String::from("x2"), int_type()),
-            (String::from("y2"), int_type())
-        ], None)),
-        (String::from("setLineColor"), (vec![
-            (String::from("color"), color_type())
-        ], None)),
-        (String::from("setFigureColor"), (vec![
-            (String::from("color"), color_type())
-        ], None)),
-        (String::from("setLineWidth"), (vec![
-            (String::from("width"), int_type())
-        ], None)),
-        (String::from("polygon"), (vec![], None)), // at least 6 Ints for polygon
-        (String::from("arc"), (vec![
-            (String::from("circle_x"), int_type()),
-            (String::from("circle_y"), int_type()),
-            (String::from("radius"), int_type()),
-            (String::from("angle_from"), int_type()),
-            (String::from("angle_to"), int_type())
-        ], None)),
-        (String::from("sleep"), (vec![
-            (String::from("sleep_time"), int_type())
-        ], None)),
-        (String::from("animate"), (vec![], None)),
-        (String::from("frame"), (vec![], None)),
-        (String::from("clear"), (vec![], None)),
-        (String::from("Color::Random"), (vec![], Some(color_type()))),
-        (String::from("round"), (vec![
-            (String::from("value"), float_type())
-        ], Some(int_type()))),
-        (String::from("decimal"), (vec![
-            (String::from("value"), int_type())
-        ], Some(float_type()))),
-        (String::from("ceil"), (vec![
-            (String::from("value"), float_type())
-        ], Some(int_type()))),
-        (String::from("floor"), (vec![
-            (String::from("value"), float_type())
-        ], Some(int_type()))),
-        (String::from("abs"), (vec![
-            (String::from("value"), int_type())
-        ], Some(int_type()))),
-        (String::from("sqrt"), (vec![
-            (String::from("value"), float_type())
-        ], Some(float_type()))),
-        (String::from("random"), (vec![(String::from("lower_bound"), int_type()), (String::from("upper_bound"), int_type())], Some(int_type()))),
-        (String::from("rgb"), (vec![
-            (String::from("red"), int_type()),
-            (String::from("green"), int_type()),
-            (String::from("blue"), int_type())
-        ], Some(color_type()))),
-    ]), keywords: HashSet::from(["circle", "line", "rectangle", 
-                    "setLineColor", "setFigureColor", "setLineWidth", "polygon", "arc", "sleep", "animate", "frame", "clear", "rgb",
-                    "round", "decimal", "ceil", "floor", "abs", "sqrt", "random",
-                    "for", "while", "global", "func", "if", "else",
-                    "int", "bool", "color", "float", "array", "Color", "true", "false"
-    ].map(|x| String::from(x)))}
+    functions: HashMap::new(), function_defs: builtin_defs(), keywords, structs: HashMap::new(), in_loop: false}
 }
 
 
@@ -161,12 +118,14 @@ impl Program {
 
     fn create_subprogram(&self, lines: Option<AstBlock>) -> Program {
         Program {
-            lines: lines.map(|x| AstProgram::Block(x)).unwrap_or(self.lines.clone()),
+            lines: lines.map(AstProgram::Block).unwrap_or(self.lines.clone()),
             scope: Scope { variables: HashMap::new(), outer_scope: Box::new(Some(self.scope.clone())) },
             global_vars: self.global_vars.clone(),
             functions: self.functions.clone(),
             function_defs: self.function_defs.clone(),
-            keywords: self.keywords.clone()
+            structs: self.structs.clone(),
+            keywords: self.keywords.clone(),
+            in_loop: self.in_loop,
         }
     }
 
@@ -174,7 +133,20 @@ impl Program {
         match self.lines {
             AstProgram::Block(ref block) => self.type_check_block(block.clone()),
             AstProgram::Forest(ref forest) => {
+                // A field can only use structs declared above it, so no struct contains itself
+                for declaration in &forest.2 {
+                    if self.keywords.contains(&declaration.name) {
+                        return Err(Error::type_er(format!("'{}' is a keyword, it cannot be the name of a struct", declaration.name), declaration.coords));
+                    }
+                    for (_, typ) in &declaration.fields {
+                        self.check_type(typ, declaration.coords)?;
+                    }
+                    self.structs.insert(declaration.name.clone(), declaration.fields.clone());
+                }
                 for func in &forest.0 {
+                    for typ in func.args.iter().map(|(_, t)| t).chain(&func.return_type) {
+                        self.check_type(typ, func.header)?;
+                    }
                     if self.keywords.contains(&func.name) {
                         return Err(Error::type_er(format!("'{}' is a keyword, it cannot be the name of a function", func.name), func.header));
                     }
@@ -185,19 +157,19 @@ impl Program {
                     }
                     if &func.name == "keyboard" {
                         if func.args.len() != 1 {
-                            return Err(Error::type_er(format!("Special function 'keyboard' has to have exactly 1 argument"), func.header));
+                            return Err(Error::type_er("Special function 'keyboard' has to have exactly 1 argument".to_string(), func.header));
                         }
-                        if func.args.get(0).unwrap().clone().1.type_name != TypeName::Primitive(BaseType::Int) {
-                            return Err(Error::type_er(format!("Special function 'keyboard' has to receive an integer, but got {}", func.args.get(0).unwrap().clone().1.to_string()), func.header));
+                        if func.args.first().unwrap().clone().1.type_name != TypeName::Primitive(BaseType::Int) {
+                            return Err(Error::type_er(format!("Special function 'keyboard' has to receive an integer, but got {}", func.args.first().unwrap().clone().1), func.header));
                         }
                     }
                     if &func.name == "mouse" {
                         if func.args.len() != 2 {
-                            return Err(Error::type_er(format!("Special function 'mouse' has to have exactly 2 arguments"), func.header));
+                            return Err(Error::type_er("Special function 'mouse' has to have exactly 2 arguments".to_string(), func.header));
                         }
-                        if func.args.get(0).unwrap().clone().1.type_name != TypeName::Primitive(BaseType::Int)
+                        if func.args.first().unwrap().clone().1.type_name != TypeName::Primitive(BaseType::Int)
                          || func.args.get(1).unwrap().clone().1.type_name != TypeName::Primitive(BaseType::Int) {
-                            return Err(Error::type_er(format!("Special function 'mouse' has to receive two integers, but got {} and {}", func.args.get(0).unwrap().clone().1.to_string(), func.args.get(1).unwrap().clone().1.to_string()), func.header));
+                            return Err(Error::type_er(format!("Special function 'mouse' has to receive two integers, but got {} and {}", func.args.first().unwrap().clone().1, func.args.get(1).unwrap().clone().1), func.header));
                         }
                     }
                     self.function_defs.insert(func.name.clone(), (func.args.clone(), func.return_type.clone()));
@@ -206,9 +178,10 @@ impl Program {
                     if self.keywords.contains(name) {
                         return Err(Error::type_er(format!("'{}' is a keyword, it cannot be the name of a variable", name), *coords));
                     }
+                    self.check_type(typ, *coords)?;
                     let expr_type = self.type_check_expr(&expr.clone())?;
-                    if expr_type.type_name != typ.type_name {
-                        return Err(Error::type_er(format!("Global variable {} of type {} cannot be assigned a type {}", name, typ.to_string(), expr_type.to_string()), *coords));
+                    if !typ.can_assign(&expr_type) {
+                        return Err(Error::type_er(format!("Global variable {} of type {} cannot be assigned a type {}", name, typ, expr_type), *coords));
                     }
                     if self.contains_key(name) {
                         return Err(Error::logic(format!("Global variable {} is re-defined!", name), *coords));
@@ -224,10 +197,11 @@ impl Program {
                                 Primitive(Float) => Expression{expr_type: ExpressionType::Value(BaseValue{val: BaseValueType::Float(0.0), coords: (0,0,0,0)}), coords: (0,0,0,0)},
                                 Primitive(Bool) => Expression{expr_type: ExpressionType::Value(BaseValue{val: BaseValueType::Bool(false), coords: (0,0,0,0)}), coords: (0,0,0,0)},
                                 Primitive(Color) => Expression{expr_type: ExpressionType::Value(BaseValue{val: BaseValueType::Color(0,0,0,255), coords: (0,0,0,0)}), coords: (0,0,0,0)},
-                                //Primitive(StringType) => Expression{expr_type: ExpressionType::Value(BaseValue{val: BaseValueType::StringVal(String::new()), coords: (0,0,0,0)}), coords: (0,0,0,0)},
-                                Array(_, _) => {
+                                Primitive(StringType) => Expression{expr_type: ExpressionType::Value(BaseValue{val: BaseValueType::StringVal(String::new()), coords: (0,0,0,0)}), coords: (0,0,0,0)},
+                                Array(_, _) | TypeName::List(_) => {
                                     Expression{expr_type: ExpressionType::Value(BaseValue{val: BaseValueType::Array(vec![]), coords: (0,0,0,0)}), coords: (0,0,0,0)}
-                                }
+                                },
+                                TypeName::Struct(name) => Expression{expr_type: ExpressionType::Value(BaseValue{val: BaseValueType::Struct(name, vec![]), coords: (0,0,0,0)}), coords: (0,0,0,0)},
                             }
                         };
                         sub.scope.variables.insert(arg.0.clone(), (arg.1.clone(), simple_expr));
@@ -261,9 +235,9 @@ impl Program {
                     if t != *return_type {
                         return Some(Error::logic(format!("Function {} return type mismatch: expected '{}', got '{}'", func.name, return_type, t), func.header));
                     }
-                    return Some(Error::logic(format!("Expected a return statement at the end of function {}", func.name), func.header));
+                    Some(Error::logic(format!("Expected a return statement at the end of function {}", func.name), func.header))
                 } else {
-                    return Some(Error::logic(format!("Function {} has no return type defined", func.name), func.header));
+                    Some(Error::logic(format!("Function {} has no return type defined", func.name), func.header))
                 }
             },
             Ok(ReturnType::None) => {
@@ -272,7 +246,7 @@ impl Program {
                 }
                 None
             },
-            Err(err) => return Some(err),   
+            Err(err) => Some(err),   
         }
     }
 
@@ -296,7 +270,7 @@ impl Program {
                         }
                     }
                 },
-                AstStatement::SetVal { val, expr } => {
+                AstStatement::SetVal { val, expr } | AstStatement::Update { val, expr } => {
                     match self.clone().type_check_set_val(val.clone(), expr.clone(), line.coords) {
                         Err(err) => return Err(err),
                         Ok((var_type, expr)) => {
@@ -332,8 +306,30 @@ impl Program {
                         }
                     }
                 },
-                AstStatement::For { val, from, to, block } => {
-                    match self.create_subprogram(None).type_check_for(val.clone(), from.clone(), to.clone(), block.clone(), line.coords)? {
+                AstStatement::For { val, from, to, step, exclusive: _, block } => {
+                    match self.create_subprogram(None).type_check_for(val.clone(), from.clone(), to.clone(), step.clone().map(|step| *step), block.clone(), line.coords)? {
+                        ReturnType::None => {},
+                        ReturnType::Partial(t) => {
+                            if let Some(rt) = &return_type {
+                                if *rt != t {
+                                    return Err(Error::logic(format!("For block return type mismatch: expected '{}', got '{}'", rt, t), line.coords));
+                                }
+                            } else {
+                                return_type = Some(t);
+                            }
+                        },
+                        ReturnType::Full(t) => {
+                            if let Some(rt) = &return_type {
+                                if *rt != t {
+                                    return Err(Error::logic(format!("For block return type mismatch: expected '{}', got '{}'", rt, t), line.coords));
+                                }
+                            }
+                            return Ok(ReturnType::Full(t));
+                        }
+                    }
+                },
+                AstStatement::ForEach { index, val, iterable, block } => {
+                    match self.create_subprogram(None).type_check_for_each(index.clone(), val.clone(), iterable.clone(), block.clone())? {
                         ReturnType::None => {},
                         ReturnType::Partial(t) => {
                             if let Some(rt) = &return_type {
@@ -385,6 +381,12 @@ impl Program {
                     }
                     return Ok(ReturnType::Full(expr_type))
                 },
+                AstStatement::Break | AstStatement::Continue => {
+                    if !self.in_loop {
+                        let name = if matches!(line.statement, AstStatement::Break) { "break" } else { "continue" };
+                        return Err(Error::logic(format!("'{}' can only be used inside a for or while loop", name), line.coords));
+                    }
+                },
             }
         }
         if let Some(rt) = &return_type {
@@ -398,8 +400,17 @@ impl Program {
     fn type_check_command(&self, name : String, args : Vec<Expression>, coords: Coords) -> Option<Error> {
         // todo warning unused return type
         if let Some((params, _)) = self.function_defs.get(&name) {
+            if name == "print" || name == "println" {
+                return args.iter().find_map(|arg| self.clone().type_check_expr(arg).err());
+            }
+            if LIST_BUILTINS.contains(&name.as_str()) {
+                return self.type_check_list_call(&name, &args, coords).err();
+            }
+            if NUMERIC_BUILTINS.contains(&name.as_str()) {
+                return self.type_check_numeric(&name, &args, coords).err();
+            }
             if name == "polygon" {
-                if args.len() < 6 || args.len() % 2 != 0 {
+                if args.len() < 6 || !args.len().is_multiple_of(2) {
                     return Some(Error::logic(format!("Wrong number of arguments for command polygon: got {}, expected at least 6 (even number) for polygon", args.len()), coords));
                 }
                 for arg in &args {
@@ -421,7 +432,7 @@ impl Program {
                 match self.clone().type_check_expr(&args[i]) {
                     Err(error) => return Some(error),
                     Ok(arg_type) => {
-                        if arg_type.type_name != param_type.type_name {
+                        if !param_type.can_pass(&arg_type) {
                             return Some(Error::type_er(format!("Wrong type of argument '{}' for command '{}': got '{}', expected '{}'", param_name, name, arg_type, param_type), coords));
                         }
                     }
@@ -450,8 +461,9 @@ impl Program {
         if self.keywords.contains(&val) {
             return Err(Error::type_er(format!("'{}' cannot be a variable, it is a keyword", val), coords));
         }
-        if let Some(_) = self.get(&val) {
-            return Err(Error::logic(format!("Variable {} is re-defined!", val), coords));
+        self.check_type(&new_type_def, coords)?;
+        if self.get(&val).is_some() {
+            Err(Error::logic(format!("Variable {} is re-defined!", val), coords))
         } else {
             let expr_type = self.clone().type_check_expr(&expr)?;
             if !new_type_def.can_assign(&expr_type) {
@@ -465,7 +477,7 @@ impl Program {
         let clause_type = self.clone().type_check_expr(&clause)?;
              
         if clause_type.type_name != Primitive(Bool) {
-            return Err(Error::logic(format!("If clause must be a bool expression"), clause.coords));
+            return Err(Error::logic("If clause must be a bool expression".to_string(), clause.coords));
         }
 
         let (l1, r1, _, _) = block.coords;
@@ -473,7 +485,7 @@ impl Program {
         let mut if_prog = self.create_subprogram(Some(block));
         let if_type = if_prog.type_check()?;
 
-        if matches!(else_block, None) {
+        if else_block.is_none() {
             if let ReturnType::Full(t) = if_type {
                 return Ok(ReturnType::Partial(t));
             }
@@ -496,35 +508,63 @@ impl Program {
             return Ok(if_type);
         }
 
-        return Ok(ReturnType::Partial(if_type.t().unwrap().clone()));
+        // One branch returns a value, so it's known, but the other may fall through
+        Ok(ReturnType::Partial(if_type.t().or(else_type.t()).unwrap().clone()))
         
     }
 
-    fn type_check_for(&self, val : String, from : Expression, to : Expression, block : AstBlock, _: Coords) -> Result<ReturnType, Error> {
+    fn type_check_for(&self, val : String, from : Expression, to : Expression, step : Option<Expression>, block : AstBlock, _: Coords) -> Result<ReturnType, Error> {
         let f = self.clone().type_check_expr(&from)?;
-        let t = self.clone().type_check_expr(&to)?;
-        if f.type_name != Primitive(Int) {
-            return Err(Error::logic(format!("For loop range can only be integer values"), from.coords))  
+        if f.type_name != Primitive(Int) && f.type_name != Primitive(Float) {
+            return Err(Error::logic("For loop range can only be int or float values".to_string(), from.coords))  
         }
-        if t.type_name != Primitive(Int) {
-            return Err(Error::logic(format!("For loop range can only be integer values"), to.coords))  
+        // The loop variable takes the type of `from`, `to` and the step have to match it
+        for bound in std::iter::once(&to).chain(step.as_ref()) {
+            let t = self.clone().type_check_expr(bound)?;
+            if t.type_name != f.type_name {
+                return Err(Error::logic(format!("For loop range mixes '{}' and '{}', use decimal() or int() to convert", f, t), bound.coords))
+            }
+        }
+        let mut for_prog = self.create_subprogram(Some(block.clone()));
+        for_prog.in_loop = true;
+        for_prog.scope.variables.insert(val, (Type{type_name:f.type_name, is_const:false}, from));
+        Ok(leaves_early(&block, for_prog.type_check()?))
+    }
+
+    // The loop variable gets the element type of the array or list
+    fn type_check_for_each(&self, index: Option<String>, val: String, iterable: Expression, block: AstBlock) -> Result<ReturnType, Error> {
+        let iterable_type = self.clone().type_check_expr(&iterable)?;
+        let elem = match &iterable_type.type_name {
+            Array(elem, _) => elem.as_ref().clone(),
+            TypeName::List(elem) => Some(elem.as_ref().clone()),
+            _ => None,
+        }.ok_or_else(|| Error::logic(format!("For loop can only go over an array or a list, but got '{}'", iterable_type), iterable.coords))?;
+        let mut for_prog = self.create_subprogram(Some(block.clone()));
+        for_prog.in_loop = true;
+        for name in index.iter().chain(std::iter::once(&val)) {
+            if self.keywords.contains(name) {
+                return Err(Error::type_er(format!("'{}' is a keyword, it cannot be the name of a variable", name), iterable.coords));
+            }
+        }
+        if let Some(index) = index {
+            for_prog.scope.variables.insert(index, (Type::typ(Int), iterable.clone()));
         }
-        let mut for_prog = self.create_subprogram(Some(block));
-        for_prog.scope.variables.insert(val, (Type{type_name:Primitive(Int), is_const:false}, from));
-        for_prog.type_check()
+        for_prog.scope.variables.insert(val, (Type { is_const: false, ..elem }, iterable));
+        Ok(leaves_early(&block, for_prog.type_check()?))
     }
 
     fn type_check_while(&self, clause : Expression, block : AstBlock) -> Result<ReturnType, Error> {
         let clause_type = self.clone().type_check_expr(&clause)?;
         if clause_type.type_name != Primitive(Bool) {
-            return Err(Error::logic(format!("While clause must be a bool expression"), clause.coords));
+            return Err(Error::logic("While clause must be a bool expression".to_string(), clause.coords));
         }
         let mut while_prog = self.clone();
-        while_prog.lines = AstProgram::Block(block);
-        while_prog.type_check()
+        while_prog.lines = AstProgram::Block(block.clone());
+        while_prog.in_loop = true;
+        Ok(leaves_early(&block, while_prog.type_check()?))
     }
 
-    fn type_check_expr(&self, expr : &Expression) -> Result<Type, Error> {
+    pub(crate) fn type_check_expr(&self, expr : &Expression) -> Result<Type, Error> {
         match &expr.expr_type {
             ExpressionType::Value(base_value) => {
                 let expr_type =  self.clone().type_check_baseval(base_value)?;
@@ -533,25 +573,25 @@ impl Program {
             ExpressionType::Unary(op, inner) => {
                 match op {
                     UnaryOperator::UnaryMinus => {
-                        let inner_type = self.clone().type_check_expr(&*inner)?;
-                        if inner_type.type_name == Primitive(Int) {Ok(Type::typ(Int))} else 
-                        if inner_type.type_name == Primitive(Float) {Ok(Type::typ(Float))} else 
-                        {Err(Error::type_er(format!("Unary minus can only be applied to types 'int' and 'float', but got {}", inner_type), expr.coords))}
+                        let inner_type = self.clone().type_check_expr(inner)?;
+                        if inner_type.type_name == Primitive(Int) {Ok(Type::typ(Int))}
+                        else if inner_type.type_name == Primitive(Float) {Ok(Type::typ(Float))}
+                        else {Err(Error::type_er(format!("Unary minus can only be applied to types 'int' and 'float', but got {}", inner_type), expr.coords))}
                     },
                     UnaryOperator::NOT => {
-                        let inner_type = self.clone().type_check_expr(&*inner)?;
+                        let inner_type = self.clone().type_check_expr(inner)?;
                         if inner_type.type_name == Primitive(Bool) {
                             Ok(Type::typ(Bool))
                         } else {
                             Err(Error::type_er(format!("Unary NOT operator can only be applied to bool expressions, but got {}", inner_type), expr.coords))
                         }
                     },
-                    UnaryOperator::Parentheses =>  self.clone().type_check_expr(&*inner),
+                    UnaryOperator::Parentheses =>  self.clone().type_check_expr(inner),
                 }
             },
             ExpressionType::Binary(op, lhs, rhs) => {
-                let lhs_type =  self.clone().type_check_expr(&*lhs)?;
-                let rhs_type =  self.clone().type_check_expr(&*rhs)?;
+                let lhs_type =  self.clone().type_check_expr(lhs)?;
+                let rhs_type =  self.clone().type_check_expr(rhs)?;
                 if *op == Operator::AND || *op == Operator::OR {
                     if lhs_type.type_name != Primitive(Bool) {
                         return Err(Error::type_er(format!("Expected bool expression for operator '{:?}', got '{}'", *op, lhs_type), lhs.coords))
@@ -559,6 +599,18 @@ impl Program {
                         return Err(Error::type_er(format!("Expected bool expression for operator '{:?}', got '{}'", *op, rhs_type), rhs.coords))
                     }
                     Ok(Type::typ(Bool))
+                } else if lhs_type.type_name == Primitive(StringType) || rhs_type.type_name == Primitive(StringType) {
+                    let (other, coords) = if lhs_type.type_name != Primitive(StringType) { (&lhs_type, lhs.coords) } else { (&rhs_type, rhs.coords) };
+                    if other.type_name != Primitive(StringType) {
+                        return Err(Error::type_er(format!("Expected string expression for operator '{:?}', got '{}', use string() to convert it", *op, other), coords));
+                    }
+                    match op {
+                        Operator::Plus => Ok(Type::typ(StringType)),
+                        Operator::Minus | Operator::Mult | Operator::Div | Operator::Mod => {
+                            Err(Error::type_er(format!("Operator '{:?}' can't be applied to strings, only '+' joins them", *op), expr.coords))
+                        },
+                        _ => Ok(Type::typ(Bool)),
+                    }
                 } else {
                     if lhs_type.type_name != Primitive(Int) && lhs_type.type_name != Primitive(Float) {
                         return Err(Error::type_er(format!("Expected int or float expression for operator '{:?}', got '{}'", *op, lhs_type), lhs.coords));
@@ -578,7 +630,68 @@ impl Program {
         }
     }
 
+    // `abs`, `min`, `max` and `clamp` give an int for ints, with a float among them the ints are widened
+    fn type_check_numeric(&self, name: &str, args: &[Expression], coords: Coords) -> Result<Type, Error> {
+        let argc = match name { "abs" => 1, "clamp" => 3, _ => 2 };
+        if args.len() != argc {
+            return Err(Error::type_er(format!("Function '{}' expects {} arguments, but got {}", name, argc, args.len()), coords));
+        }
+        let mut result = Type::typ(Int);
+        for arg in args {
+            let arg_type = self.type_check_expr(arg)?;
+            match arg_type.type_name {
+                Primitive(Int) => {},
+                Primitive(Float) => result = Type::typ(Float),
+                _ => return Err(Error::type_er(format!("Function '{}' expects ints or floats, but got '{}'", name, arg_type), arg.coords)),
+            }
+        }
+        Ok(result)
+    }
+
+    // `len` reads any list, array or string, the others change the list variable they are given
+    fn type_check_list_call(&self, name: &str, args: &[Expression], coords: Coords) -> Result<Option<Type>, Error> {
+        let argc = match name { "len" | "pop" => 1, "push" | "remove" => 2, _ => 3 };
+        if args.len() != argc {
+            return Err(Error::type_er(format!("Function '{}' expects {} arguments, but got {}", name, argc, args.len()), coords));
+        }
+        if name == "len" {
+            let arg_type = self.type_check_expr(&args[0])?;
+            return match arg_type.type_name {
+                TypeName::List(_) | Array(_, _) | Primitive(StringType) => Ok(Some(Type::typ(Int))),
+                _ => Err(Error::type_er(format!("Function 'len' expects a list, an array or a string, but got '{}'", arg_type), args[0].coords)),
+            };
+        }
+        let ExpressionType::Value(BaseValue { val: BaseValueType::Id(var), .. }) = &args[0].expr_type else {
+            return Err(Error::type_er(format!("Function '{}' has to receive a list variable first", name), args[0].coords));
+        };
+        let list_type = self.type_check_var(var, coords)?;
+        let TypeName::List(elem) = &list_type.type_name else {
+            return Err(Error::type_er(format!("Function '{}' expects a list, but '{}' is a '{}'", name, var, list_type), args[0].coords));
+        };
+        if list_type.is_const {
+            return Err(Error::type_er(format!("Const list {} cannot be changed", var), coords));
+        }
+        if name == "insert" || name == "remove" {
+            let index_type = self.type_check_expr(&args[1])?;
+            if index_type.type_name != Primitive(Int) {
+                return Err(Error::type_er(format!("Function '{}' expects an int index, but got '{}'", name, index_type), args[1].coords));
+            }
+        }
+        if name == "push" || name == "insert" {
+            let value = args.last().unwrap();
+            let value_type = self.type_check_expr(value)?;
+            if !elem.can_assign(&value_type) {
+                return Err(Error::type_er(format!("Cannot add '{}' to list {} of type '{}'", value_type, var, list_type), value.coords));
+            }
+            return Ok(None);
+        }
+        Ok(Some(Type { is_const: false, ..elem.as_ref().clone() }))
+    }
+
     fn recursive_type_check_var(&self, tp: &Type, depth: usize, coords: Coords) -> Result<Type, Error> {
+        if let TypeName::List(inner) = &tp.type_name {
+            return if depth == 1 { Ok(inner.as_ref().clone()) } else { self.recursive_type_check_var(inner, depth - 1, coords) };
+        }
         if let Array(inner_type, _) = &tp.type_name {
             if let Some(inner) = inner_type.as_ref() {
                 if depth == 1 {
@@ -593,19 +706,69 @@ impl Program {
         Err(Error::type_er(String::from("Expected an array type"), coords))
     }
 
+    fn check_type(&self, typ: &Type, coords: Coords) -> Result<(), Error> {
+        match &typ.type_name {
+            TypeName::Struct(name) if !self.structs.contains_key(name) => Err(Error::type_er(format!("Unknown type '{}'", name), coords)),
+            Array(inner, _) => inner.as_ref().as_ref().map_or(Ok(()), |inner| self.check_type(inner, coords)),
+            TypeName::List(inner) => self.check_type(inner, coords),
+            _ => Ok(()),
+        }
+    }
+
+    fn type_check_index(&self, index: &Expression) -> Result<(), Error> {
+        let t = self.type_check_expr(index)?;
+        if t.type_name != Primitive(Int) {
+            return Err(Error::type_er(format!("Array index must be 'int', got '{}'", t), index.coords));
+        }
+        Ok(())
+    }
+
+    // Fields of a const struct can't be changed either
+    fn type_check_path(&self, var: &VariableCall, root: Type, path: &[Accessor], coords: Coords) -> Result<Type, Error> {
+        let mut current = root.clone();
+        for step in path {
+            current = match (step, &current.type_name) {
+                (Accessor::Index(index), _) => {
+                    self.type_check_index(index)?;
+                    self.recursive_type_check_var(&current, 1, coords)?
+                },
+                (Accessor::Field(field), TypeName::Struct(name)) => {
+                    match self.structs.get(name).and_then(|fields| fields.iter().find(|(f, _)| f == field)) {
+                        Some((_, field_type)) => field_type.clone(),
+                        None => return Err(Error::type_er(format!("Struct {} has no field '{}'", name, field), coords)),
+                    }
+                },
+                (Accessor::Field(field), other) => return Err(Error::type_er(format!("'{}' is a '{}', it has no field '{}'", var, other, field), coords)),
+            };
+        }
+        current.is_const |= root.is_const;
+        Ok(current)
+    }
+
     fn type_check_var(&self, var: &VariableCall, coords: Coords) -> Result<Type, Error> {
         let (name, depth) = match var {
             VariableCall::Name(name) => (name, 0),
-            VariableCall::ArrayCall(name, inds) => (name, inds.len())
+            VariableCall::ArrayCall(name, inds) => {
+                for index in inds {
+                    self.type_check_index(index)?;
+                }
+                (name, inds.len())
+            },
+            VariableCall::FieldCall(name, path) => {
+                return match self.get(name) {
+                    Some((tp, _)) => self.type_check_path(var, tp.clone(), path, coords),
+                    None => Err(Error::logic(format!("Variable {} is not defined!", var), coords)),
+                };
+            },
         };
         if self.keywords.contains(name) {
             return Err(Error::type_er(format!("'{}' is a keyword, it cannot be a name of a variable", name), coords));
         }
         if let Some((tp, _)) = self.get(name) {
             if depth == 0 { 
-                return Ok(tp.clone());
+                Ok(tp.clone())
             } else {
-                return self.recursive_type_check_var(tp, depth, coords);
+                self.recursive_type_check_var(tp, depth, coords)
             }
         } else {
             Err(Error::logic(format!("Variable {} is not defined!", var), coords))
@@ -616,12 +779,26 @@ impl Program {
         use BaseType::*;
         let coords = base.coords;
         match &base.val {
-            BaseValueType::Id(var) => self.type_check_var(&var, coords),
+            BaseValueType::Id(var) => self.type_check_var(var, coords),
             BaseValueType::Int(_) => Ok(Type::typ(Int)),
             BaseValueType::Bool(_) => Ok(Type::typ(Bool)),
             BaseValueType::Color(_, _, _, _) => Ok(Type::typ(Color)),
             BaseValueType::RandomColor(_) => Ok(Type::typ(Color)),
             BaseValueType::Float(_) => Ok(Type::typ(Float)),
+            BaseValueType::StringVal(_) => Ok(Type::typ(StringType)),
+            BaseValueType::StructLiteral(name, fields) => {
+                let Some(declared) = self.structs.get(name) else {
+                    return Err(Error::type_er(format!("Unknown struct {}", name), coords));
+                };
+                for ((field, expr), (_, field_type)) in fields.iter().zip(declared) {
+                    let expr_type = self.type_check_expr(expr)?;
+                    if !field_type.can_assign(&expr_type) {
+                        return Err(Error::type_er(format!("Field '{}' of struct {} has type '{}', but got '{}'", field, name, field_type, expr_type), expr.coords));
+                    }
+                }
+                Ok(Type { type_name: TypeName::Struct(name.clone()), is_const: false })
+            },
+            BaseValueType::Struct(name, _) => Ok(Type { type_name: TypeName::Struct(name.clone()), is_const: false }),
             BaseValueType::Array(arr) => {
                 let types: Result<Vec<Type>, Error> = arr.iter()
                     .map(|item| self.type_check_baseval(item))
@@ -637,17 +814,33 @@ impl Program {
                 }
                 Ok(Type{type_name:Array(Box::new(Some(inner_type.clone())), arr.len()), is_const: false})
             },
-            BaseValueType::FunctionCall(name,arg_list, return_type ) => {
+            BaseValueType::FunctionCall(name, arg_list) => {
                 match self.function_defs.get(name) {
                     None => Err(Error::type_er(format!("Unknown function '{}'", name), base.coords)),
-                    Some((arg_defs, _)) => {
+                    Some(_) if LIST_BUILTINS.contains(&name.as_str()) => {
+                        self.type_check_list_call(name, arg_list, base.coords)?
+                            .ok_or_else(|| Error::type_er(format!("Function '{}' doesn't return a value", name), base.coords))
+                    },
+                    Some(_) if NUMERIC_BUILTINS.contains(&name.as_str()) => self.type_check_numeric(name, arg_list, base.coords),
+                    Some(_) if name == "string" => {
+                        if arg_list.len() != 1 {
+                            return Err(Error::type_er(format!("Function 'string' expects 1 argument, but got {}", arg_list.len()), base.coords))
+                        }
+                        let expr_type = self.type_check_expr(&arg_list[0])?;
+                        if ![Primitive(Int), Primitive(Float), Primitive(Bool), Primitive(StringType)].contains(&expr_type.type_name) {
+                            return Err(Error::type_er(format!("Function 'string' converts int, float and bool values, but got '{}'", expr_type), arg_list[0].coords))
+                        }
+                        Ok(string_type())
+                    },
+                    Some((_, None)) => Err(Error::type_er(format!("Function {} has no return type", name), base.coords)),
+                    Some((arg_defs, Some(return_type))) => {
                         if arg_list.len() != arg_defs.len() {
                             return Err(Error::type_er(format!("Funcion '{}' expects {} arguments, but got {}", name, arg_defs.len(), arg_list.len()), base.coords))
                         }
                         for (i, (arg_name, arg_def)) in arg_defs.iter().enumerate() {
                             let expr_type = self.type_check_expr(arg_list.get(i).unwrap())?;
-                            if !arg_def.can_assign(&expr_type) {
-                                return Err(Error::type_er(format!("Funcion '{}' expects argument '{}' of type '{}', but got '{}'", name, arg_name, arg_def.to_string(), expr_type.to_string()), base.coords));
+                            if !arg_def.can_pass(&expr_type) {
+                                return Err(Error::type_er(format!("Funcion '{}' expects argument '{}' of type '{}', but got '{}'", name, arg_name, arg_def, expr_type), base.coords));
                             }
                         } 
                         Ok(return_type.clone())
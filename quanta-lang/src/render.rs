@@ -0,0 +1,250 @@
+use quanta_parser::error::Error;
+use tiny_skia::{Color, FillRule, LineCap, LineJoin, Paint, Path, PathBuilder, Pixmap, Rect, Stroke, Transform};
+
+use crate::utils::{command::{DrawCommand, Rgba}, message::CommandBlock};
+
+// Same virtual canvas as web/canvas-runtime.js
+pub const CANVAS_SIZE: u32 = 1000;
+const BACKGROUND: (u8, u8, u8) = (10, 15, 31);
+
+// Rasterizes the canvas command stream off-screen and encodes frames as PNG.
+pub struct Rasterizer {
+    pixmap: Pixmap,
+    scale: f32,
+    animated: bool,
+    dirty: bool,
+}
+
+// 5x7 pixel font for ' ' to '~', one byte per column with the top row in the lowest bit.
+// Browsers draw text with real fonts, frames rendered here are only meant to be readable.
+const GLYPHS: [[u8; 5]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00], [0x00, 0x00, 0x5F, 0x00, 0x00], [0x00, 0x07, 0x00, 0x07, 0x00], [0x14, 0x7F, 0x14, 0x7F, 0x14],
+    [0x24, 0x2A, 0x7F, 0x2A, 0x12], [0x23, 0x13, 0x08, 0x64, 0x62], [0x36, 0x49, 0x55, 0x22, 0x50], [0x00, 0x05, 0x03, 0x00, 0x00],
+    [0x00, 0x1C, 0x22, 0x41, 0x00], [0x00, 0x41, 0x22, 0x1C, 0x00], [0x08, 0x2A, 0x1C, 0x2A, 0x08], [0x08, 0x08, 0x3E, 0x08, 0x08],
+    [0x00, 0x50, 0x30, 0x00, 0x00], [0x08, 0x08, 0x08, 0x08, 0x08], [0x00, 0x60, 0x60, 0x00, 0x00], [0x20, 0x10, 0x08, 0x04, 0x02],
+    [0x3E, 0x51, 0x49, 0x45, 0x3E], [0x00, 0x42, 0x7F, 0x40, 0x00], [0x42, 0x61, 0x51, 0x49, 0x46], [0x21, 0x41, 0x45, 0x4B, 0x31],
+    [0x18, 0x14, 0x12, 0x7F, 0x10], [0x27, 0x45, 0x45, 0x45, 0x39], [0x3C, 0x4A, 0x49, 0x49, 0x30], [0x01, 0x71, 0x09, 0x05, 0x03],
+    [0x36, 0x49, 0x49, 0x49, 0x36], [0x06, 0x49, 0x49, 0x29, 0x1E], [0x00, 0x36, 0x36, 0x00, 0x00], [0x00, 0x56, 0x36, 0x00, 0x00],
+    [0x08, 0x14, 0x22, 0x41, 0x00], [0x14, 0x14, 0x14, 0x14, 0x14], [0x00, 0x41, 0x22, 0x14, 0x08], [0x02, 0x01, 0x51, 0x09, 0x06],
+    [0x32, 0x49, 0x79, 0x41, 0x3E], [0x7E, 0x11, 0x11, 0x11, 0x7E], [0x7F, 0x49, 0x49, 0x49, 0x36], [0x3E, 0x41, 0x41, 0x41, 0x22],
+    [0x7F, 0x41, 0x41, 0x22, 0x1C], [0x7F, 0x49, 0x49, 0x49, 0x41], [0x7F, 0x09, 0x09, 0x01, 0x01], [0x3E, 0x41, 0x41, 0x51, 0x32],
+    [0x7F, 0x08, 0x08, 0x08, 0x7F], [0x00, 0x41, 0x7F, 0x41, 0x00], [0x20, 0x40, 0x41, 0x3F, 0x01], [0x7F, 0x08, 0x14, 0x22, 0x41],
+    [0x7F, 0x40, 0x40, 0x40, 0x40], [0x7F, 0x02, 0x04, 0x02, 0x7F], [0x7F, 0x04, 0x08, 0x10, 0x7F], [0x3E, 0x41, 0x41, 0x41, 0x3E],
+    [0x7F, 0x09, 0x09, 0x09, 0x06], [0x3E, 0x41, 0x51, 0x21, 0x5E], [0x7F, 0x09, 0x19, 0x29, 0x46], [0x46, 0x49, 0x49, 0x49, 0x31],
+    [0x01, 0x01, 0x7F, 0x01, 0x01], [0x3F, 0x40, 0x40, 0x40, 0x3F], [0x1F, 0x20, 0x40, 0x20, 0x1F], [0x7F, 0x20, 0x18, 0x20, 0x7F],
+    [0x63, 0x14, 0x08, 0x14, 0x63], [0x03, 0x04, 0x78, 0x04, 0x03], [0x61, 0x51, 0x49, 0x45, 0x43], [0x00, 0x7F, 0x41, 0x41, 0x00],
+    [0x02, 0x04, 0x08, 0x10, 0x20], [0x00, 0x41, 0x41, 0x7F, 0x00], [0x04, 0x02, 0x01, 0x02, 0x04], [0x40, 0x40, 0x40, 0x40, 0x40],
+    [0x00, 0x01, 0x02, 0x04, 0x00], [0x20, 0x54, 0x54, 0x54, 0x78], [0x7F, 0x48, 0x44, 0x44, 0x38], [0x38, 0x44, 0x44, 0x44, 0x20],
+    [0x38, 0x44, 0x44, 0x48, 0x7F], [0x38, 0x54, 0x54, 0x54, 0x18], [0x08, 0x7E, 0x09, 0x01, 0x02], [0x08, 0x14, 0x54, 0x54, 0x3C],
+    [0x7F, 0x08, 0x04, 0x04, 0x78], [0x00, 0x44, 0x7D, 0x40, 0x00], [0x20, 0x40, 0x44, 0x3D, 0x00], [0x00, 0x7F, 0x10, 0x28, 0x44],
+    [0x00, 0x41, 0x7F, 0x40, 0x00], [0x7C, 0x04, 0x18, 0x04, 0x78], [0x7C, 0x08, 0x04, 0x04, 0x78], [0x38, 0x44, 0x44, 0x44, 0x38],
+    [0x7C, 0x14, 0x14, 0x14, 0x08], [0x08, 0x14, 0x14, 0x18, 0x7C], [0x7C, 0x08, 0x04, 0x04, 0x08], [0x48, 0x54, 0x54, 0x54, 0x20],
+    [0x04, 0x3F, 0x44, 0x40, 0x20], [0x3C, 0x40, 0x40, 0x20, 0x7C], [0x1C, 0x20, 0x40, 0x20, 0x1C], [0x3C, 0x40, 0x30, 0x40, 0x3C],
+    [0x44, 0x28, 0x10, 0x28, 0x44], [0x0C, 0x50, 0x50, 0x50, 0x3C], [0x44, 0x64, 0x54, 0x4C, 0x44], [0x00, 0x08, 0x36, 0x41, 0x00],
+    [0x00, 0x00, 0x7F, 0x00, 0x00], [0x00, 0x41, 0x36, 0x08, 0x00], [0x10, 0x08, 0x08, 0x10, 0x08],
+];
+// Drawn for letters the pixel font doesn't have
+const MISSING_GLYPH: [u8; 5] = [0x7F, 0x41, 0x41, 0x41, 0x7F];
+
+fn text_path(x: f32, y: f32, size: f32, text: &str) -> Option<Path> {
+    // 7 rows of the glyph and one of spacing make up the font size
+    let pixel = size / 8.0;
+    let mut pb = PathBuilder::new();
+    for (i, c) in text.chars().enumerate() {
+        let glyph = match c {
+            ' '..='~' => &GLYPHS[c as usize - ' ' as usize],
+            _ => &MISSING_GLYPH,
+        };
+        let left = x + (i * 6) as f32 * pixel;
+        for (column, bits) in glyph.iter().enumerate() {
+            for row in 0..7 {
+                if bits & (1 << row) != 0 {
+                    if let Some(rect) = Rect::from_xywh(left + column as f32 * pixel, y + row as f32 * pixel, pixel, pixel) {
+                        pb.push_rect(rect);
+                    }
+                }
+            }
+        }
+    }
+    pb.finish()
+}
+
+fn render_error(message: String) -> Error {
+    Error::runtime(message, (0, 0, 0, 0))
+}
+
+fn to_color(color: Rgba) -> Color {
+    Color::from_rgba8(color.r, color.g, color.b, color.a)
+}
+
+fn arc_path(cx: f32, cy: f32, r: f32, from_deg: f32, to_deg: f32) -> Option<Path> {
+    // Clockwise on screen, like CanvasRenderingContext2D.arc without `ccw`
+    let full_turn = std::f32::consts::TAU;
+    let start = from_deg.to_radians();
+    let mut sweep = to_deg.to_radians() - start;
+    if sweep >= full_turn {
+        sweep = full_turn;
+    } else {
+        sweep = sweep.rem_euclid(full_turn);
+    }
+    let segments = ((r * sweep / 4.0).ceil() as usize).clamp(8, 720);
+    let mut pb = PathBuilder::new();
+    pb.move_to(cx + r * start.cos(), cy + r * start.sin());
+    for i in 1..=segments {
+        let angle = start + sweep * (i as f32) / (segments as f32);
+        pb.line_to(cx + r * angle.cos(), cy + r * angle.sin());
+    }
+    pb.finish()
+}
+
+fn polygon_path(points: &[(f32, f32)], close: bool) -> Option<Path> {
+    let mut pb = PathBuilder::new();
+    pb.move_to(points[0].0, points[0].1);
+    for (x, y) in &points[1..] {
+        pb.line_to(*x, *y);
+    }
+    if close {
+        pb.close();
+    }
+    pb.finish()
+}
+
+impl Default for Rasterizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Rasterizer {
+    pub fn new() -> Rasterizer {
+        Rasterizer::with_scale(1.0)
+    }
+
+    pub fn with_scale(scale: f32) -> Rasterizer {
+        let side = ((CANVAS_SIZE as f32) * scale).round().max(1.0) as u32;
+        let mut rasterizer = Rasterizer {
+            pixmap: Pixmap::new(side, side).expect("Canvas size must be positive"),
+            scale,
+            animated: false,
+            dirty: false,
+        };
+        rasterizer.clear();
+        rasterizer
+    }
+
+    pub fn width(&self) -> u32 {
+        self.pixmap.width()
+    }
+
+    pub fn height(&self) -> u32 {
+        self.pixmap.height()
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> Option<(u8, u8, u8, u8)> {
+        self.pixmap.pixel(x, y).map(|p| {
+            let c = p.demultiply();
+            (c.red(), c.green(), c.blue(), c.alpha())
+        })
+    }
+
+    pub fn to_png(&self) -> Result<Vec<u8>, Error> {
+        self.pixmap.encode_png().map_err(|e| render_error(format!("Couldn't encode PNG frame: {}", e)))
+    }
+
+    // Draws every block and returns the frames a viewer would have seen:
+    // one per `frame()`, one per `sleep()` outside of animations, and the final picture.
+    pub fn render_blocks(&mut self, blocks: &[CommandBlock]) -> Result<Vec<Vec<u8>>, Error> {
+        let mut frames = vec![];
+        for block in blocks {
+            for command in block.commands() {
+                self.draw(command);
+            }
+            match block.get_status() {
+                0 => frames.push(self.capture()?),
+                2 => {
+                    if self.dirty || frames.is_empty() {
+                        frames.push(self.capture()?);
+                    }
+                    break;
+                },
+                3 => break,
+                _ => {
+                    if block.sleep_for > 0 && !self.animated && self.dirty {
+                        frames.push(self.capture()?);
+                    }
+                }
+            }
+        }
+        Ok(frames)
+    }
+
+    fn capture(&mut self) -> Result<Vec<u8>, Error> {
+        self.dirty = false;
+        self.to_png()
+    }
+
+    pub fn draw(&mut self, command: &DrawCommand) {
+        match command {
+            DrawCommand::Circle { x, y, r, style } => {
+                if let Some(path) = PathBuilder::from_circle(*x as f32, *y as f32, (*r).max(0) as f32) {
+                    self.draw_shape(&path, Some(style.fill), style.stroke, style.width);
+                }
+            },
+            DrawCommand::Rectangle { x1, y1, x2, y2, style } => {
+                let (x1, y1, x2, y2) = (*x1 as f32, *y1 as f32, *x2 as f32, *y2 as f32);
+                if let Some(path) = polygon_path(&[(x1, y1), (x2, y1), (x2, y2), (x1, y2)], true) {
+                    self.draw_shape(&path, Some(style.fill), style.stroke, style.width);
+                }
+            },
+            DrawCommand::Line { x1, y1, x2, y2, style } => {
+                let points = [(*x1 as f32, *y1 as f32), (*x2 as f32, *y2 as f32)];
+                if let Some(path) = polygon_path(&points, false) {
+                    self.draw_shape(&path, None, style.stroke, style.width);
+                }
+            },
+            DrawCommand::Polygon { points, style } => {
+                let points: Vec<(f32, f32)> = points.iter().map(|(x, y)| (*x as f32, *y as f32)).collect();
+                if points.len() >= 2 {
+                    if let Some(path) = polygon_path(&points, true) {
+                        self.draw_shape(&path, Some(style.fill), style.stroke, style.width);
+                    }
+                }
+            },
+            DrawCommand::Arc { x, y, r, start, end, style } => {
+                if let Some(path) = arc_path(*x as f32, *y as f32, (*r).max(0) as f32, *start as f32, *end as f32) {
+                    self.draw_shape(&path, Some(style.fill), style.stroke, style.width);
+                }
+            },
+            DrawCommand::Text { x, y, text, style } => {
+                if let Some(path) = text_path(*x as f32, *y as f32, style.size.max(0) as f32, text) {
+                    self.draw_shape(&path, Some(style.fill), style.fill, 0);
+                }
+            },
+            DrawCommand::Clear => self.clear(),
+            DrawCommand::Animate => self.animated = true,
+            // sleep/frame/end are consumed by Runtime::get_commands
+            DrawCommand::Sleep { .. } | DrawCommand::Frame | DrawCommand::End => {}
+        }
+    }
+
+    fn clear(&mut self) {
+        let (r, g, b) = BACKGROUND;
+        self.pixmap.fill(Color::from_rgba8(r, g, b, 255));
+        self.dirty = true;
+    }
+
+    fn draw_shape(&mut self, path: &Path, fill: Option<Rgba>, stroke: Rgba, width: i32) {
+        let transform = Transform::from_scale(self.scale, self.scale);
+        let mut paint = Paint { anti_alias: true, ..Paint::default() };
+        if let Some(fill) = fill {
+            paint.set_color(to_color(fill));
+            self.pixmap.fill_path(path, &paint, FillRule::Winding, transform, None);
+        }
+        if width > 0 {
+            paint.set_color(to_color(stroke));
+            let stroke = Stroke { width: width as f32, line_cap: LineCap::Round, line_join: LineJoin::Round, ..Stroke::default() };
+            self.pixmap.stroke_path(path, &paint, &stroke, transform, None);
+        }
+        self.dirty = true;
+    }
+}
@@ -0,0 +1,109 @@
+use std::collections::HashMap;
+
+use futures::executor::block_on;
+use quanta_parser::{ast::{AstBlock, AstProgram, BaseValue}, error::Error, parse_ast, parse_expression};
+
+use crate::{execution::{Execution, Flow}, program::{create_program, Program}, utils::{canvas::{construct_canvas, CanvasReader, Output}, command::DrawCommand, inspect::Value, timer::default_timer}};
+
+// What a single input produced
+#[derive(Debug, Clone, PartialEq)]
+pub struct Evaluation {
+    pub value: Option<Value>,
+    pub commands: Vec<DrawCommand>,
+    pub output: String,
+}
+
+// Runs statements and expressions one input at a time. Variables declared by earlier
+// inputs stay in scope, and every input is type checked against them before it runs.
+pub struct Repl {
+    program: Program,
+    exec: Execution,
+    canvas: CanvasReader,
+}
+
+impl Default for Repl {
+    fn default() -> Repl {
+        Repl::new()
+    }
+}
+
+fn ends_statement(input: &str) -> bool {
+    input.ends_with(';') || input.ends_with('}')
+}
+
+impl Repl {
+    pub fn new() -> Repl {
+        let empty = AstProgram::Block(AstBlock { nodes: vec![], coords: (0, 0, 0, 0) });
+        let (canvas, reader) = construct_canvas();
+        let exec = Execution::new(empty.clone(), HashMap::new(), canvas, default_timer());
+        Repl { program: create_program(empty), exec, canvas: reader }
+    }
+
+    pub fn eval(&mut self, input: &str) -> Result<Evaluation, Error> {
+        // Every input is shown once it is done, so the limits start over for the next one
+        self.exec.budget.reset();
+        self.exec.canvas.mark_shown();
+        let value = self.run(input.trim());
+        // Drawn and printed even when the input failed halfway, so drained either way
+        let mut evaluation = Evaluation { value: None, commands: vec![], output: String::new() };
+        for output in self.canvas.get_output() {
+            match output {
+                Output::Draw(command) => evaluation.commands.push(command),
+                Output::Print(text) => evaluation.output.push_str(&text),
+            }
+        }
+        evaluation.value = value?.as_ref().and_then(Value::from_base);
+        Ok(evaluation)
+    }
+
+    // Steps and shapes a single input may run or draw, 0 means no limit
+    pub fn set_step_budget(&self, steps: u32) {
+        self.exec.budget.set_step_limit(steps);
+    }
+
+    pub fn set_command_budget(&self, commands: u32) {
+        self.exec.budget.set_command_limit(commands);
+    }
+
+    // A failed input is undone, so the type checker and the runtime keep agreeing on what is declared
+    fn run(&mut self, input: &str) -> Result<Option<BaseValue>, Error> {
+        match parse_expression(input) {
+            Ok(expr) => {
+                self.program.type_check_expr(&expr)?;
+                Ok(Some(block_on(self.exec.calculate_expression(expr))?))
+            },
+            Err(expr_error) => {
+                // Without a trailing `;` the input was more likely meant as an expression
+                let block = self.parse_statements(input).map_err(|e| if ends_statement(input) { e } else { expr_error })?;
+                let mut checked = self.program.clone();
+                checked.type_check_block(block.clone())?;
+                let variables = self.exec.scope.lock().unwrap().variables.clone();
+                match block_on(self.exec.execute_commands(block.nodes)) {
+                    Ok(flow) => {
+                        self.program = checked;
+                        Ok(match flow {
+                            Flow::Return(value) => Some(value),
+                            _ => None,
+                        })
+                    },
+                    Err(error) => {
+                        self.exec.scope.lock().unwrap().variables = variables;
+                        Err(error)
+                    },
+                }
+            },
+        }
+    }
+
+    // Forgiving about the `;` after the last statement, `x = 3` works like `x = 3;`
+    fn parse_statements(&self, input: &str) -> Result<AstBlock, Error> {
+        let ast = match parse_ast(input) {
+            Err(_) if !ends_statement(input) => parse_ast(&format!("{};", input)),
+            ast => ast,
+        }?;
+        match ast {
+            AstProgram::Block(block) => Ok(block),
+            AstProgram::Forest(_) => Err(Error::logic(String::from("Functions and globals belong in a program file, the REPL runs statements and expressions"), (0, 0, 0, 0))),
+        }
+    }
+}
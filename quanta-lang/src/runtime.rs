@@ -1,8 +1,7 @@
 use wasm_bindgen::prelude::*;
-use wasm_bindgen_futures::{spawn_local};
 use quanta_parser::{ast::keys::key_to_number};
 
-use crate::{execution::{Execution, Scope}, program::Program, utils::{canvas::{Canvas, CanvasReader}, message::{CommandBlock, RuntimeError}}};
+use crate::{bytecode::{self, Bytecode}, execution::{Execution, Scope}, program::Program, vm::Vm, utils::{canvas::{Canvas, CanvasReader, Output}, command::DrawCommand, debugger::Debugger, inspect::Snapshot, message::{CommandBlock, RuntimeError, StatementSpan}, timer::{default_timer, run_pending, spawn, Timer}}};
 
 use std::{collections::HashMap, sync::{Arc, Mutex}};
 
@@ -14,6 +13,8 @@ pub struct Runtime {
     mouse_execution: Option<Execution>,
     canvas: CanvasReader,
     runtime_error: Arc<Mutex<RuntimeError>>,
+    bytecode: Option<Arc<Bytecode>>,
+    debugger: Debugger,
 }
 
 
@@ -25,8 +26,20 @@ impl Runtime {
         }
         let runtime_error = Arc::clone(&self.runtime_error);
         let new_exec = self.main_execution.clone();
-        spawn_local(async move {
-            match new_exec.clone().execute().await {
+        let bytecode = self.bytecode.clone();
+        spawn(async move {
+            let result = match bytecode {
+                Some(bytecode) => {
+                    let mut canvas = new_exec.canvas.clone();
+                    let result = Vm::new(bytecode, new_exec).run().await;
+                    if result.is_ok() {
+                        canvas.add_command(DrawCommand::End);
+                    }
+                    result
+                },
+                None => new_exec.clone().execute().await,
+            };
+            match result {
                 Ok(_) => {},
                 Err(err) => {
                     let mut inner_error = runtime_error.lock().unwrap();
@@ -39,7 +52,7 @@ impl Runtime {
     pub fn execute_key(&self, key: String) {
         if let Some(key_code) = key_to_number(key.as_str()) {
             if let Some(exec) = self.key_execution.clone() {
-                    spawn_local(async move {
+                    spawn(async move {
                     match exec.clone().execute_key(key_code).await {
                     Ok(_) => {},
                     Err(err) => {
@@ -53,7 +66,7 @@ impl Runtime {
 
     pub fn execute_mouse(&self, x: i32, y:i32) {
         if let Some(exec) = self.mouse_execution.clone() {
-                spawn_local(async move {
+                spawn(async move {
                 match exec.clone().execute_mouse(x, y).await {
                 Ok(_) => {},
                 Err(err) => {
@@ -73,62 +86,122 @@ impl Runtime {
                 return result;
             }
         
-        for command in self.canvas.get_commands() {
+        for output in self.canvas.get_output() {
             if self.runtime_error.lock().unwrap().error_code != 0 {
                 block.set_status(3);
                 result.push(block);
                 return result;
             }
-            if command.starts_with("sleep") {
-                let time = command.split(' ').collect::<Vec<&str>>().get(1).unwrap().parse::<i32>().unwrap(); //parse i32
-                block.sleep_for = time;
-                result.push(block);
-                block = CommandBlock::new();
-            } else if command.starts_with("frame") {
-                block.set_status(0);
-                result.push(block);
-                block = CommandBlock::new();
-            } else if command.starts_with("end") {
-                block.set_status(2);
-                result.push(block);
-                return result;
-            } else {
-                block.push(command);
+            let command = match output {
+                Output::Draw(command) => command,
+                Output::Print(text) => {
+                    block.print(&text);
+                    continue;
+                },
+            };
+            match command {
+                DrawCommand::Sleep { ms } => {
+                    block.sleep_for = ms;
+                    result.push(block);
+                    block = CommandBlock::new();
+                },
+                DrawCommand::Frame => {
+                    block.set_status(0);
+                    result.push(block);
+                    block = CommandBlock::new();
+                },
+                DrawCommand::End => {
+                    block.set_status(2);
+                    result.push(block);
+                    return result;
+                },
+                command => block.push(command),
             }
         }
         result.push(block);
-        return result;
+        result
     }
 
     pub fn get_runtime_error(&self) -> RuntimeError {
         self.runtime_error.lock().unwrap().clone()
     }
+
+    // Steps and shapes a program may run or draw before the next frame() or sleep(), 0 means no limit
+    pub fn set_step_budget(&self, steps: u32) {
+        self.main_execution.budget.set_step_limit(steps);
+    }
+
+    pub fn set_command_budget(&self, commands: u32) {
+        self.main_execution.budget.set_command_limit(commands);
+    }
+
+    pub fn set_breakpoint(&self, line: usize) {
+        self.debugger.set_breakpoint(line);
+    }
+
+    pub fn remove_breakpoint(&self, line: usize) {
+        self.debugger.remove_breakpoint(line);
+    }
+
+    pub fn clear_breakpoints(&self) {
+        self.debugger.clear_breakpoints();
+    }
+
+    pub fn pause(&self) {
+        self.debugger.pause();
+    }
+
+    pub fn resume(&self) {
+        self.debugger.resume();
+        run_pending();
+    }
+
+    pub fn step_into(&self) {
+        self.debugger.step_into();
+        run_pending();
+    }
+
+    pub fn step_over(&self) {
+        self.debugger.step_over();
+        run_pending();
+    }
+
+    pub fn step_out(&self) {
+        self.debugger.step_out();
+        run_pending();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.debugger.paused_at().is_some()
+    }
+
+    pub fn get_paused_span(&self) -> Option<StatementSpan> {
+        self.debugger.paused_at().map(StatementSpan::new)
+    }
+
+    // See utils::inspect for the format
+    pub fn get_snapshot_json(&self) -> String {
+        self.snapshot().to_json()
+    }
 }
 
 impl Runtime {
     pub async fn new(prog : Program, canv: Canvas, canvas: CanvasReader) -> Runtime {
+        Runtime::with_timer(prog, canv, canvas, default_timer()).await
+    }
+
+    pub async fn with_timer(prog : Program, canv: Canvas, canvas: CanvasReader, timer: Arc<dyn Timer>) -> Runtime {
         //let exec = Execution::from_program(prog.clone(), canv);
-        let global_vars = Arc::new(Mutex::new(HashMap::new()));
+        // Programs the bytecode compiler can't handle yet keep running on the tree-walker
+        let bytecode = bytecode::lower(&prog).ok().map(Arc::new);
+
         let global_var_defs = Arc::new(Mutex::new(prog.global_vars));
-        let fig_col = Arc::new(Mutex::new(String::from("#ffffff")));
-        let lin_col = Arc::new(Mutex::new(String::from("#000000")));
-        let lin_wid = Arc::new(Mutex::new(1));
-
-        let exec = Execution {
-            lines : prog.lines.clone(),
-            scope : Arc::new(Mutex::new(Scope { variables: HashMap::new(), outer_scope: None })),
-            global_vars: Arc::clone(&global_vars),
-            canvas: canv.clone(),
-            functions: prog.functions.clone(),
-            figure_color: Arc::clone(&fig_col),
-            line_color: Arc::clone(&lin_col),
-            line_width: Arc::clone(&lin_wid),
-            random_color: Arc::new(Mutex::new(0)),
-        };
+        let exec = Execution::new(prog.lines.clone(), prog.functions.clone(), canv.clone(), timer);
 
         let keyboard_exec = if exec.functions.contains_key("keyboard") {
             let mut c = exec.clone();
             c.scope = Arc::new(Mutex::new(Scope { variables: HashMap::new(), outer_scope: None }));
+            c.calls = Arc::new(Mutex::new(vec![]));
             Some(c)
         } else { 
             None 
@@ -137,12 +210,14 @@ impl Runtime {
         let mouse_exec = if exec.functions.contains_key("mouse") { 
             let mut c = exec.clone();
             c.scope = Arc::new(Mutex::new(Scope { variables: HashMap::new(), outer_scope: None }));
+            c.calls = Arc::new(Mutex::new(vec![]));
             Some(c)
         } else { 
             None 
         };
 
-        let defs = global_var_defs.lock().unwrap();
+        let debugger = exec.debugger.clone();
+        let defs = global_var_defs.lock().unwrap().clone();
 
         let mut runtime_error = RuntimeError::zero();
 
@@ -160,8 +235,29 @@ impl Runtime {
             main_execution: exec, 
             key_execution: keyboard_exec,
             mouse_execution: mouse_exec,
-            canvas: canvas,
+            canvas,
             runtime_error: Arc::new(Mutex::new(runtime_error)),
+            bytecode,
+            debugger,
         }
     }
-}
\ No newline at end of file
+
+    // Calls are only there while the program is paused
+    pub fn snapshot(&self) -> Snapshot {
+        let exec = &self.main_execution;
+        let calls = exec.calls.lock().unwrap().clone();
+        let globals = exec.global_vars.lock().unwrap().clone();
+        Snapshot::capture(&calls, &globals)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn uses_bytecode(&self) -> bool {
+        self.bytecode.is_some()
+    }
+
+    #[cfg(test)]
+    pub(crate) fn without_bytecode(mut self) -> Runtime {
+        self.bytecode = None;
+        self
+    }
+}
@@ -0,0 +1,136 @@
+use quanta_parser::error::Error;
+
+use crate::utils::{command::{DrawCommand, Rgba}, message::CommandBlock};
+
+const CANVAS_SIZE: u32 = 1000;
+const BACKGROUND: &str = "#0a0f1f";
+
+// Turns the command stream of a finished drawing into a standalone SVG document.
+#[derive(Debug, Clone, Default)]
+pub struct SvgExporter {
+    elements: Vec<String>,
+}
+
+fn export_error(message: String) -> Error {
+    Error::runtime(message, (0, 0, 0, 0))
+}
+
+// `#rrggbb` plus a separate opacity, `#rrggbbaa` isn't understood by every SVG viewer
+fn paint_attributes(attribute: &str, paint: Option<Rgba>) -> String {
+    match paint {
+        None => format!(" {}=\"none\"", attribute),
+        Some(c) if c.a < 255 => {
+            format!(" {}=\"#{:02x}{:02x}{:02x}\" {}-opacity=\"{:.3}\"", attribute, c.r, c.g, c.b, attribute, c.a as f32 / 255.0)
+        },
+        Some(c) => format!(" {}=\"#{:02x}{:02x}{:02x}\"", attribute, c.r, c.g, c.b),
+    }
+}
+
+fn style_attributes(fill: Option<Rgba>, stroke: Rgba, width: i32) -> String {
+    let mut attributes = paint_attributes("fill", fill);
+    if width > 0 {
+        attributes.push_str(&paint_attributes("stroke", Some(stroke)));
+        attributes.push_str(&format!(" stroke-width=\"{}\" stroke-linecap=\"round\" stroke-linejoin=\"round\"", width));
+    } else {
+        attributes.push_str(&paint_attributes("stroke", None));
+    }
+    attributes
+}
+
+fn escape_text(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn arc_path_data(cx: f32, cy: f32, r: f32, from_deg: f32, to_deg: f32) -> String {
+    // Clockwise on screen, like CanvasRenderingContext2D.arc without `ccw`
+    let mut sweep = to_deg - from_deg;
+    if sweep >= 360.0 {
+        sweep = 360.0;
+    } else {
+        sweep = sweep.rem_euclid(360.0);
+    }
+    let point = |deg: f32| {
+        let rad = deg.to_radians();
+        (cx + r * rad.cos(), cy + r * rad.sin())
+    };
+    let (sx, sy) = point(from_deg);
+    if sweep >= 360.0 {
+        // A single SVG arc can't start and end at the same point
+        let (mx, my) = point(from_deg + 180.0);
+        return format!("M {:.3} {:.3} A {} {} 0 0 1 {:.3} {:.3} A {} {} 0 0 1 {:.3} {:.3}", sx, sy, r, r, mx, my, r, r, sx, sy);
+    }
+    let (ex, ey) = point(from_deg + sweep);
+    let large_arc = if sweep > 180.0 { 1 } else { 0 };
+    format!("M {:.3} {:.3} A {} {} 0 {} 1 {:.3} {:.3}", sx, sy, r, r, large_arc, ex, ey)
+}
+
+impl SvgExporter {
+    pub fn new() -> SvgExporter {
+        SvgExporter { elements: vec![] }
+    }
+
+    pub fn export_blocks(&mut self, blocks: &[CommandBlock]) -> Result<String, Error> {
+        for block in blocks {
+            if block.get_status() == 3 {
+                return Err(export_error(String::from("Program stopped with a runtime error, nothing to export")));
+            }
+            for command in block.commands() {
+                self.draw(command)?;
+            }
+        }
+        Ok(self.finish())
+    }
+
+    pub fn finish(&self) -> String {
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{0}\" viewBox=\"0 0 {0} {0}\">\n",
+            CANVAS_SIZE
+        );
+        svg.push_str(&format!("  <rect x=\"0\" y=\"0\" width=\"{0}\" height=\"{0}\" fill=\"{1}\"/>\n", CANVAS_SIZE, BACKGROUND));
+        for element in &self.elements {
+            svg.push_str("  ");
+            svg.push_str(element);
+            svg.push('\n');
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    pub fn draw(&mut self, command: &DrawCommand) -> Result<(), Error> {
+        match command {
+            DrawCommand::Circle { x, y, r, style } => {
+                self.elements.push(format!("<circle cx=\"{}\" cy=\"{}\" r=\"{}\"{}/>",
+                    x, y, (*r).max(0), style_attributes(Some(style.fill), style.stroke, style.width)));
+            },
+            DrawCommand::Rectangle { x1, y1, x2, y2, style } => {
+                self.elements.push(format!("<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\"{}/>",
+                    x1.min(x2), y1.min(y2), (x2 - x1).abs(), (y2 - y1).abs(), style_attributes(Some(style.fill), style.stroke, style.width)));
+            },
+            DrawCommand::Line { x1, y1, x2, y2, style } => {
+                self.elements.push(format!("<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\"{}/>",
+                    x1, y1, x2, y2, style_attributes(None, style.stroke, style.width)));
+            },
+            DrawCommand::Polygon { points, style } => {
+                if points.len() >= 2 {
+                    let points: Vec<String> = points.iter().map(|(x, y)| format!("{},{}", x, y)).collect();
+                    self.elements.push(format!("<polygon points=\"{}\"{}/>",
+                        points.join(" "), style_attributes(Some(style.fill), style.stroke, style.width)));
+                }
+            },
+            DrawCommand::Arc { x, y, r, start, end, style } => {
+                let path = arc_path_data(*x as f32, *y as f32, (*r).max(0) as f32, *start as f32, *end as f32);
+                self.elements.push(format!("<path d=\"{}\"{}/>", path, style_attributes(Some(style.fill), style.stroke, style.width)));
+            },
+            DrawCommand::Text { x, y, text, style } => {
+                self.elements.push(format!("<text x=\"{}\" y=\"{}\" font-size=\"{}\" font-family=\"{}\" dominant-baseline=\"hanging\" xml:space=\"preserve\"{}>{}</text>",
+                    x, y, style.size, escape_text(&style.font), paint_attributes("fill", Some(style.fill)), escape_text(text)));
+            },
+            DrawCommand::Clear => self.elements.clear(),
+            DrawCommand::Animate => {
+                return Err(export_error(String::from("Animated programs can't be exported to SVG, remove animate() to export a drawing")));
+            },
+            DrawCommand::Sleep { .. } | DrawCommand::Frame | DrawCommand::End => {}
+        }
+        Ok(())
+    }
+}
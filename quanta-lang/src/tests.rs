@@ -1,506 +1,1659 @@
-use crate::{Compiler};
-//use crate:utils::message::LinearCompilationMessage;
+use futures::executor::block_on;
 
-impl Compiler {
-    // pub fn linear_compile_code(&mut self, source : &str) -> LinearCompilationMessage {
-    //     self.linear_compile(source)
-    // }
+use crate::{command::{self, DrawCommand, LineStyle, Rgba, ShapeStyle, TextStyle}, inspect::Value, render::Rasterizer, repl::Repl, svg::SvgExporter, Compiler};
+
+fn compile_ok(src: &str) -> Vec<String> {
+    let mut compiler = Compiler::new();
+    let msg = block_on(compiler.compile(src));
+    assert_eq!(msg.error_code, 0, "Unexpected compile error: {}", msg.get_error_message());
+    let mut runtime = msg.get_runtime();
+    runtime.execute();
+    assert_eq!(runtime.get_runtime_error().error_code, 0, "Unexpected runtime error: {}", runtime.get_runtime_error().get_error_message());
+    let blocks = runtime.get_commands();
+    blocks.iter().fold(vec![], 
+    |mut res, block| {
+        let mut coms = block.commands().iter().map(ToString::to_string).collect();
+        res.append(&mut coms);
+        res
+    })
 }
 
-#[cfg(test)]
-mod tests {
-    
-    use crate::Compiler;
+fn render_ok(src: &str) -> (Rasterizer, Vec<Vec<u8>>) {
+    let msg = block_on(Compiler::new().compile(src));
+    assert_eq!(msg.error_code, 0, "Unexpected compile error: {}", msg.get_error_message());
+    let mut runtime = msg.get_runtime();
+    runtime.execute();
+    let mut rasterizer = Rasterizer::new();
+    let frames = rasterizer.render_blocks(&runtime.get_commands()).expect("Rendering failed");
+    (rasterizer, frames)
+}
 
-    fn compile_ok(src: &str) -> Vec<String> {
-        let mut compiler = Compiler::new();
-        let msg = compiler.linear_compile(src);
+fn export_svg(src: &str) -> Result<String, quanta_parser::error::Error> {
+    let msg = block_on(Compiler::new().compile(src));
+    assert_eq!(msg.error_code, 0, "Unexpected compile error: {}", msg.get_error_message());
+    let mut runtime = msg.get_runtime();
+    runtime.execute();
+    SvgExporter::new().export_blocks(&runtime.get_commands())
+}
+
+// Runs `src` on the bytecode VM and on the tree-walker, both must agree on what they draw and print
+fn run_both_backends(src: &str) -> (Vec<String>, u32) {
+    let compile = || {
+        let msg = block_on(Compiler::new().compile(src));
         assert_eq!(msg.error_code, 0, "Unexpected compile error: {}", msg.get_error_message());
-        let mut exec = msg.get_runtime();
-        assert_eq!(Ok(()), exec.execute());
-        let blocks = exec.get_commands();
-        blocks.iter().fold(vec![], 
-        |mut res, block| {
-            let mut coms = block.get_commands();
-            res.append(&mut coms);
-            if block.sleep_for >= 0 {
-                res.push(format!("sleep {}", block.sleep_for).into());
-            }
-            res
-        })
+        msg.get_runtime()
+    };
+    let vm = compile();
+    assert!(vm.uses_bytecode(), "Program was not lowered to bytecode");
+    let mut results = vec![];
+    for mut runtime in [vm, compile().without_bytecode()] {
+        runtime.execute();
+        let error = runtime.get_runtime_error();
+        let message = if error.error_code != 0 { error.get_error_message() } else { String::new() };
+        let commands: Vec<String> = runtime.get_commands().iter()
+            .flat_map(|block| block.commands().iter().map(ToString::to_string).collect::<Vec<_>>())
+            .collect();
+        let printed: Vec<String> = runtime.get_commands().iter().map(|block| block.output().to_string()).collect();
+        results.push((commands, error.error_code, message, printed));
     }
+    assert_eq!(results[0], results[1]);
+    let (commands, code, _, _) = results.remove(0);
+    (commands, code)
+}
+
+#[test]
+fn test_file() {
+    let file_path = "../grammar/test.txt";
+
+    let contents = std::fs::read_to_string(file_path)
+        .expect("Should have been able to read the file");
+    assert!(!contents.is_empty());
+    println!("{:?}\n=====================================", compile_ok(&contents));
+}
+
+#[test]
+fn circle_with_defaults() {
+    let cmds = compile_ok("circle(320, 240, 100);");
+    let expected = vec![
+        "circle 320 240 100 fill=#ffffffff stroke=#000000ff width=1".to_string()
+    ];
+    assert_eq!(cmds, expected);
+}
+
+#[test]
+fn rectangle_with_custom_styles() {
+    let src = r#"
+        setFigureColor(Color::Red);
+        setLineColor(Color::Green);
+        setLineWidth(3);
+        rectangle(10, 20, 110, 220);
+    "#;
+    let cmds = compile_ok(src);
+    let expected = vec![
+        // Red -> #e92331, Green -> #7eb786
+        "rectangle 10 20 110 220 fill=#e92331ff stroke=#7eb786ff width=3".to_string()
+    ];
+    assert_eq!(cmds, expected);
+}
+
+#[test]
+fn style_persists_until_changed() {
+    let src = r#"
+        setFigureColor(Color::Blue);
+        circle(0, 0, 5);
+        rectangle(1, 2, 3, 4);
+    "#;
+    let cmds = compile_ok(src);
+    let expected = vec![
+        // Blue -> #2E73E6
+        "circle 0 0 5 fill=#2e73e6ff stroke=#000000ff width=1".to_string(),
+        "rectangle 1 2 3 4 fill=#2e73e6ff stroke=#000000ff width=1".to_string(),
+    ];
+    assert_eq!(cmds, expected);
+}
+
+#[test]
+fn style_updates_affect_following_commands_only() {
+    let src = r#"
+        setFigureColor(Color::Blue);
+        circle(1, 2, 3);
+        setFigureColor(Color::Yellow);
+        rectangle(4, 5, 6, 7);
+    "#;
+    let cmds = compile_ok(src);
+    let expected = vec![
+        // Blue -> #2e73e6, Yellow -> #fde25d
+        "circle 1 2 3 fill=#2e73e6ff stroke=#000000ff width=1".to_string(),
+        "rectangle 4 5 6 7 fill=#fde25dff stroke=#000000ff width=1".to_string(),
+    ];
+    assert_eq!(cmds, expected);
+}
+
+#[test]
+fn line_command_is_geometry_only() {
+    // Per spec: line(...) → "line x1 y1 x2 y2"
+    let src = r#"
+        setLineColor(Color::Green);
+        setLineWidth(7);
+        line(10, 10, 20, 20);
+    "#;
+    let cmds = compile_ok(src);
+    let expected = vec!["line 10 10 20 20 stroke=#7eb786ff width=7".to_string()];
+    assert_eq!(cmds, expected);
+}
+
+#[test]
+fn polygon_and_arc_match_shape_style_behavior() {
+    let src = r#"
+        setFigureColor(Color::Red);
+        setLineColor(Color::Blue);
+        setLineWidth(2);
+        polygon(0,0, 10,0, 10,10, 0,10);
+        arc(50, 60, 40, 0, 180);
+    "#;
+    let cmds = compile_ok(src);
+    let expected = vec![
+        // Red -> #e92331, Blue -> #2e73e6
+        "polygon 0 0 10 0 10 10 0 10 fill=#e92331ff stroke=#2e73e6ff width=2".to_string(),
+        "arc 50 60 40 0 180 fill=#e92331ff stroke=#2e73e6ff width=2".to_string(),
+    ];
+    assert_eq!(cmds, expected);
+}
+
+#[test]
+fn more_colors_map_correctly() {
+    let src = r#"
+        setFigureColor(Color::Pink);
+        setLineColor(Color::Cyan);
+        circle(5, 6, 7);
+        setFigureColor(Color::White);
+        setLineColor(Color::Black);
+        rectangle(1,2,3,4);
+    "#;
+    let cmds = compile_ok(src);
+    let expected = vec![
+        // Pink -> #fb9ab5, Cyan -> #3ba8e7
+        "circle 5 6 7 fill=#fb9ab5ff stroke=#3ba8e7ff width=1".to_string(),
+        "rectangle 1 2 3 4 fill=#ffffffff stroke=#000000ff width=1".to_string(),
+    ];
+    assert_eq!(cmds, expected);
+}
 
     #[test]
-    fn test_file() {
-        let file_path = "../grammar/test.txt";
+fn circle_with_arithmetic_expressions() {
+    let src = r#"
+        circle(10+20, 30-5, 2*10);
+    "#;
+    let cmds = compile_ok(src);
+    let expected = vec![
+        "circle 30 25 20 fill=#ffffffff stroke=#000000ff width=1".to_string(),
+    ];
+    assert_eq!(cmds, expected);
+}
 
-        let contents = std::fs::read_to_string(file_path)
-            .expect("Should have been able to read the file");
-        assert!(contents.len() > 0);
-        println!("{:?}\n=====================================", compile_ok(&contents));
-    }
+#[test]
+fn rectangle_with_division_and_modulo() {
+    let src = r#"
+        setFigureColor(Color::Cyan);
+        rectangle(100/2, 10%3, 7*5, 80/4);
+    "#;
+    let cmds = compile_ok(src);
+    let expected = vec![
+        // 100/2=50, 10%3=1, 7*5=35, 80/4=20
+        "rectangle 50 1 35 20 fill=#3ba8e7ff stroke=#000000ff width=1".to_string(),
+    ];
+    assert_eq!(cmds, expected);
+}
+
+#[test]
+fn mixed_operations_in_line_and_arc() {
+    let src = r#"
+        setLineColor(Color::Pink);
+        line(5*2, 20/2, 50-25, 3+7);
+        arc(100, 200, 10*2, 360/4, 50%7);
+    "#;
+    let cmds = compile_ok(src);
+    let expected = vec![
+        // line: (10, 10, 25, 10)
+        "line 10 10 25 10 stroke=#fb9ab5ff width=1".to_string(),
+        // arc: (100, 200, 20, 90, 1)
+        "arc 100 200 20 90 1 fill=#ffffffff stroke=#fb9ab5ff width=1".to_string(),
+    ];
+    assert_eq!(cmds, expected);
+}
 
     #[test]
-    fn circle_with_defaults() {
-        let cmds = compile_ok("circle(320, 240, 100);");
-        let expected = vec![
-            "circle 320 240 100 fill=#ffffff stroke=#000000 width=1".to_string()
-        ];
-        assert_eq!(cmds, expected);
-    }
+fn precedence_without_parentheses() {
+    let src = r#"
+        circle(2+3*4, 20-6/2, 10%4+1);
+    "#;
+    let cmds = compile_ok(src);
+    let expected = vec![
+        // 2+3*4 = 14, 20-6/2 = 17, 10%4+1 = 3
+        "circle 14 17 3 fill=#ffffffff stroke=#000000ff width=1".to_string(),
+    ];
+    assert_eq!(cmds, expected);
+}
+
+#[test]
+fn precedence_with_parentheses() {
+    let src = r#"
+        rectangle((2+3)*4, (20-6)/2, (10%4)+1, (8/2)*(3+1));
+    "#;
+    let cmds = compile_ok(src);
+    let expected = vec![
+        // (2+3)*4 = 20, (20-6)/2 = 7, (10%4)+1 = 3, (8/2)*(3+1) = 16
+        "rectangle 20 7 3 16 fill=#ffffffff stroke=#000000ff width=1".to_string(),
+    ];
+    assert_eq!(cmds, expected);
+}
+
+#[test]
+fn polygon_with_mixed_precedence_and_parentheses() {
+    let src = r#"
+        setFigureColor(Color::Yellow);
+        polygon(1+2*3, (4+6)%5, 18/3-2, (2+2)*(3+1), 7, 8);
+    "#;
+    let cmds = compile_ok(src);
+    let expected = vec![
+        // 1+2*3 = 7, (4+6)%5 = 0, 18/3-2 = 4, (2+2)*(3+1) = 16
+        "polygon 7 0 4 16 7 8 fill=#fde25dff stroke=#000000ff width=1".to_string(),
+    ];
+    assert_eq!(cmds, expected);
+}
+
+#[test]
+fn line_with_arithmetic_and_styles() {
+    let src = r#"
+        setLineColor(Color::Pink);
+        setLineWidth(2*3);
+        line(5*2, 20/2, 50-25, (3+7));
+    "#;
+    let cmds = compile_ok(src);
+    let expected = vec![
+        // line: (10, 10, 25, 10), stroke=#fb9ab5, width=6
+        "line 10 10 25 10 stroke=#fb9ab5ff width=6".to_string(),
+    ];
+    assert_eq!(cmds, expected);
+}
+
+#[test]
+fn arc_with_arithmetic_and_line_style() {
+    let src = r#"
+        setLineColor(Color::Cyan);
+        setLineWidth(4+1);
+        arc(100/2, 200-50, 5*4, 360/4, 50%7);
+    "#;
+    let cmds = compile_ok(src);
+    let expected = vec![
+        // arc(50, 150, 20, 90, 1), stroke=#3ba8e7, width=5
+        "arc 50 150 20 90 1 fill=#ffffffff stroke=#3ba8e7ff width=5".to_string(),
+    ];
+    assert_eq!(cmds, expected);
+}
 
     #[test]
-    fn rectangle_with_custom_styles() {
-        let src = r#"
-            setFigureColor(Color::Red);
-            setLineColor(Color::Green);
-            setLineWidth(3);
-            rectangle(10, 20, 110, 220);
-        "#;
-        let cmds = compile_ok(src);
-        let expected = vec![
-            // Red -> #e92331, Green -> #7eb786
-            "rectangle 10 20 110 220 fill=#e92331 stroke=#7eb786 width=3".to_string()
-        ];
-        assert_eq!(cmds, expected);
-    }
+fn int_variable_in_circle() {
+    let src = r#"
+        int x = 5;
+        circle(x, x*2, x+10);
+    "#;
+    let cmds = compile_ok(src);
+    let expected = vec![
+        // x=5 → (5, 10, 15)
+        "circle 5 10 15 fill=#ffffffff stroke=#000000ff width=1".to_string(),
+    ];
+    assert_eq!(cmds, expected);
+}
+
+#[test]
+fn color_variable_in_rectangle() {
+    let src = r#"
+        color b = Color::Red;
+        setFigureColor(b);
+        rectangle(0, 0, 20, 10);
+    "#;
+    let cmds = compile_ok(src);
+    let expected = vec![
+        "rectangle 0 0 20 10 fill=#e92331ff stroke=#000000ff width=1".to_string(),
+    ];
+    assert_eq!(cmds, expected);
+}
+
+// #[test]
+// fn float_variable_in_arc() {
+//     let src = r#"
+//         float t = 4.5;
+//         arc(10, 20, t*2, 0, 180);
+//     "#;
+//     let cmds = compile_ok(src);
+//     let expected = vec![
+//         // t=4.5, t*2=9.0 (assuming compiler casts float→int)
+//         "arc 10 20 9 0 180 fill=#ffffffff stroke=#000000ff width=1".to_string(),
+//     ];
+//     assert_eq!(cmds, expected);
+// }
+
+// #[test]
+// fn bool_variable_in_line_width() {
+//     let src = r#"
+//         bool d = true;
+//         setLineWidth(d*5); // true→1, false→0 ?
+//         line(0, 0, 10, 10);
+//     "#;
+//     let cmds = compile_ok(src);
+//     let expected = vec![
+//         // d=true → 1*5=5
+//         "line 0 0 10 10 stroke=#000000ff width=5".to_string(),
+//     ];
+//     assert_eq!(cmds, expected);
+// }
+
+#[test]
+fn mixed_variables_in_polygon() {
+    let src = r#"
+        int x = 2;
+        color c = Color::Blue;
+        setFigureColor(c);
+        polygon(x, 3, x*10, 3*2, 3, 4);
+    "#;
+    let cmds = compile_ok(src);
+    let expected = vec![
+        // x=2, y=3.5 → polygon(2, 3, 20, 7)
+        "polygon 2 3 20 6 3 4 fill=#2e73e6ff stroke=#000000ff width=1".to_string(),
+    ];
+    assert_eq!(cmds, expected);
+}
 
     #[test]
-    fn style_persists_until_changed() {
-        let src = r#"
-            setFigureColor(Color::Blue);
+fn polygon_requires_at_least_six_args() {
+    // Too few args (only 4): should be an error.
+    let src = r#"
+        polygon(0,0, 10,0);
+    "#;
+    let msg = block_on(Compiler::new().compile(src));
+    assert_ne!(msg.error_code, 0);
+    assert!(msg.get_error_message().to_lowercase().contains("polygon"));
+    assert!(msg.get_error_message().to_lowercase().contains("6"));
+}
+
+#[test]
+fn if_true_branch_executes() {
+    let src = r#"
+        if (3 > 2) {
+            setFigureColor(Color::Green);
+            circle(10, 20, 30);
+        } else {
+            setFigureColor(Color::Red);
             circle(0, 0, 5);
-            rectangle(1, 2, 3, 4);
-        "#;
-        let cmds = compile_ok(src);
-        let expected = vec![
-            // Blue -> #2E73E6
-            "circle 0 0 5 fill=#2e73e6 stroke=#000000 width=1".to_string(),
-            "rectangle 1 2 3 4 fill=#2e73e6 stroke=#000000 width=1".to_string(),
-        ];
-        assert_eq!(cmds, expected);
-    }
+        }
+    "#;
+    let cmds = compile_ok(src);
+    let expected = vec![
+        "circle 10 20 30 fill=#7eb786ff stroke=#000000ff width=1".to_string(), // green
+    ];
+    assert_eq!(cmds, expected);
+}
 
-    #[test]
-    fn style_updates_affect_following_commands_only() {
-        let src = r#"
+#[test]
+fn if_false_branch_executes_else() {
+    let src = r#"
+        if (1 == 2) {
+            rectangle(0,0, 1,1);
+        } else {
             setFigureColor(Color::Blue);
-            circle(1, 2, 3);
-            setFigureColor(Color::Yellow);
-            rectangle(4, 5, 6, 7);
-        "#;
-        let cmds = compile_ok(src);
-        let expected = vec![
-            // Blue -> #2e73e6, Yellow -> #fde25d
-            "circle 1 2 3 fill=#2e73e6 stroke=#000000 width=1".to_string(),
-            "rectangle 4 5 6 7 fill=#fde25d stroke=#000000 width=1".to_string(),
-        ];
-        assert_eq!(cmds, expected);
-    }
+            rectangle(10, 10, 20, 20);
+        }
+    "#;
+    let cmds = compile_ok(src);
+    let expected = vec![
+        "rectangle 10 10 20 20 fill=#2e73e6ff stroke=#000000ff width=1".to_string(), // blue
+    ];
+    assert_eq!(cmds, expected);
+}
 
-    #[test]
-    fn line_command_is_geometry_only() {
-        // Per spec: line(...) → "line x1 y1 x2 y2"
-        let src = r#"
-            setLineColor(Color::Green);
-            setLineWidth(7);
-            line(10, 10, 20, 20);
-        "#;
-        let cmds = compile_ok(src);
-        let expected = vec!["line 10 10 20 20 stroke=#7eb786 width=7".to_string()];
-        assert_eq!(cmds, expected);
-    }
+#[test]
+fn if_without_else_skips_when_false() {
+    let src = r#"
+        if (5 < 4) {
+            circle(1,2,3);
+        }
+    "#;
+    let cmds = compile_ok(src);
+    assert!(cmds.is_empty(), "Expected no commands when condition is false and no else-block");
+}
 
-    #[test]
-    fn polygon_and_arc_match_shape_style_behavior() {
-        let src = r#"
-            setFigureColor(Color::Red);
-            setLineColor(Color::Blue);
+#[test]
+fn boolean_operator_precedence_and_parentheses() {
+    // Expect true: !false && (true || false) == true
+    let src = r#"
+        if (!false && (true || false)) {
+            setLineColor(Color::Cyan);
             setLineWidth(2);
-            polygon(0,0, 10,0, 10,10, 0,10);
-            arc(50, 60, 40, 0, 180);
-        "#;
-        let cmds = compile_ok(src);
-        let expected = vec![
-            // Red -> #e92331, Blue -> #2e73e6
-            "polygon 0 0 10 0 10 10 0 10 fill=#e92331 stroke=#2e73e6 width=2".to_string(),
-            "arc 50 60 40 0 180 fill=#e92331 stroke=#2e73e6 width=2".to_string(),
-        ];
-        assert_eq!(cmds, expected);
-    }
+            line(0, 0, 5*2, 4+6);
+        } else {
+            line(0,0,1,1);
+        }
+    "#;
+    let cmds = compile_ok(src);
+    let expected = vec![
+        "line 0 0 10 10 stroke=#3ba8e7ff width=2".to_string(),
+    ];
+    assert_eq!(cmds, expected);
+}
 
-    #[test]
-    fn more_colors_map_correctly() {
-        let src = r#"
+#[test]
+fn comparisons_on_int_and_float_in_condition() {
+    let src = r#"
+        int x = 5;
+        float t = 4.5;
+        if ( ((x >= 5) && (t < 5.0)) && (x != 0) ) {
+            setFigureColor(Color::Yellow);
+            circle(x+5, x, x*2);
+        } else {
+            rectangle(0,0,1,1);
+        }
+    "#;
+    let cmds = compile_ok(src);
+    let expected = vec![
+        // x+5 = 10, (int)4.5 -> 4, (int)(9.0) -> 9
+        "circle 10 5 10 fill=#fde25dff stroke=#000000ff width=1".to_string(),
+    ];
+    assert_eq!(cmds, expected);
+}
+
+#[test]
+fn mixed_and_or_with_comparisons() {
+    // Test && vs || precedence: && binds tighter than ||
+    // Expr: (2 > 3) || (4 == 4 && 1 <= 2)  -> false || (true && true) -> true
+    let src = r#"
+        if ( 2 > 3 || 4 == 4 && 1 <= 2 ) {
             setFigureColor(Color::Pink);
-            setLineColor(Color::Cyan);
-            circle(5, 6, 7);
-            setFigureColor(Color::White);
-            setLineColor(Color::Black);
-            rectangle(1,2,3,4);
-        "#;
-        let cmds = compile_ok(src);
-        let expected = vec![
-            // Pink -> #fb9ab5, Cyan -> #3ba8e7
-            "circle 5 6 7 fill=#fb9ab5 stroke=#3ba8e7 width=1".to_string(),
-            "rectangle 1 2 3 4 fill=#ffffff stroke=#000000 width=1".to_string(),
-        ];
-        assert_eq!(cmds, expected);
-    }
+            circle(1,1,1);
+        } else {
+            setFigureColor(Color::Red);
+            circle(2,2,2);
+        }
+    "#;
+    let cmds = compile_ok(src);
+    let expected = vec![
+        "circle 1 1 1 fill=#fb9ab5ff stroke=#000000ff width=1".to_string(), // pink
+    ];
+    assert_eq!(cmds, expected);
+}
 
-        #[test]
-    fn circle_with_arithmetic_expressions() {
-        let src = r#"
-            circle(10+20, 30-5, 2*10);
-        "#;
-        let cmds = compile_ok(src);
-        let expected = vec![
-            "circle 30 25 20 fill=#ffffff stroke=#000000 width=1".to_string(),
-        ];
-        assert_eq!(cmds, expected);
-    }
+#[test]
+fn style_set_inside_if_persists_after_block() {
+    // Assuming style changes are global (not block-scoped).
+    let src = r#"
+        if (1 == 1) {
+            setFigureColor(Color::Red);
+        }
+        rectangle(0,0, 10,10);
+    "#;
+    let cmds = compile_ok(src);
+    let expected = vec![
+        "rectangle 0 0 10 10 fill=#e92331ff stroke=#000000ff width=1".to_string(), // red persists
+    ];
+    assert_eq!(cmds, expected);
+}
 
-    #[test]
-    fn rectangle_with_division_and_modulo() {
-        let src = r#"
+#[test]
+fn scope_set_inside_if_persists_after_block() {
+    // Assuming style changes are global (not block-scoped).
+    let src = r#"
+        int x = 0;
+        if (1 == 1) {
+            x = 10;
+            setFigureColor(Color::Red);
+        }
+        rectangle(0,0, x,x);
+    "#;
+    let cmds = compile_ok(src);
+    let expected = vec![
+        "rectangle 0 0 10 10 fill=#e92331ff stroke=#000000ff width=1".to_string(), // red persists
+    ];
+    assert_eq!(cmds, expected);
+}
+
+#[test]
+fn polygon_in_if_with_minimum_vertices_and_styles() {
+    let src = r#"
+        if ((3*3) == 9) {
             setFigureColor(Color::Cyan);
-            rectangle(100/2, 10%3, 7*5, 80/4);
-        "#;
-        let cmds = compile_ok(src);
-        let expected = vec![
-            // 100/2=50, 10%3=1, 7*5=35, 80/4=20
-            "rectangle 50 1 35 20 fill=#3ba8e7 stroke=#000000 width=1".to_string(),
-        ];
-        assert_eq!(cmds, expected);
-    }
+            setLineColor(Color::Black);
+            setLineWidth(3);
+            polygon(0,0, 10,0, 10,10);
+        }
+    "#;
+    let cmds = compile_ok(src);
+    let expected = vec![
+        "polygon 0 0 10 0 10 10 fill=#3ba8e7ff stroke=#000000ff width=3".to_string(),
+    ];
+    assert_eq!(cmds, expected);
+}
 
-    #[test]
-    fn mixed_operations_in_line_and_arc() {
-        let src = r#"
-            setLineColor(Color::Pink);
-            line(5*2, 20/2, 50-25, 3+7);
-            arc(100, 200, 10*2, 360/4, 50%7);
-        "#;
-        let cmds = compile_ok(src);
-        let expected = vec![
-            // line: (10, 10, 25, 10)
-            "line 10 10 25 10 stroke=#fb9ab5 width=1".to_string(),
-            // arc: (100, 200, 20, 90, 1)
-            "arc 100 200 20 90 1 fill=#ffffff stroke=#fb9ab5 width=1".to_string(),
-        ];
-        assert_eq!(cmds, expected);
-    }
+#[test]
+fn for_loop_runs_without_browser_timer() {
+    let src = r#"
+        for i in (1..3) {
+            circle(i, i, 10 * i);
+        }
+    "#;
+    let cmds = compile_ok(src);
+    let expected = vec![
+        "circle 1 1 10 fill=#ffffffff stroke=#000000ff width=1".to_string(),
+        "circle 2 2 20 fill=#ffffffff stroke=#000000ff width=1".to_string(),
+        "circle 3 3 30 fill=#ffffffff stroke=#000000ff width=1".to_string(),
+    ];
+    assert_eq!(cmds, expected);
+}
 
-        #[test]
-    fn precedence_without_parentheses() {
-        let src = r#"
-            circle(2+3*4, 20-6/2, 10%4+1);
-        "#;
-        let cmds = compile_ok(src);
-        let expected = vec![
-            // 2+3*4 = 14, 20-6/2 = 17, 10%4+1 = 3
-            "circle 14 17 3 fill=#ffffff stroke=#000000 width=1".to_string(),
-        ];
-        assert_eq!(cmds, expected);
+#[test]
+fn render_filled_circle() {
+    let (canvas, frames) = render_ok(r#"
+        setFigureColor(Color::Red);
+        circle(500, 500, 100);
+    "#);
+    assert_eq!(frames.len(), 1);
+    assert!(frames[0].starts_with(b"\x89PNG"));
+    assert_eq!(canvas.pixel(500, 500), Some((233, 35, 49, 255)));
+    assert_eq!(canvas.pixel(500, 700), Some((10, 15, 31, 255)));
+}
+
+#[test]
+fn render_rectangle_stroke_and_clear() {
+    let (canvas, _) = render_ok(r#"
+        circle(100, 100, 50);
+        clear();
+        setFigureColor(Color::Transparent);
+        setLineColor(Color::Yellow);
+        setLineWidth(10);
+        rectangle(200, 200, 400, 300);
+    "#);
+    assert_eq!(canvas.pixel(100, 100), Some((10, 15, 31, 255)));
+    assert_eq!(canvas.pixel(300, 250), Some((10, 15, 31, 255)));
+    assert_eq!(canvas.pixel(200, 250), Some((253, 226, 93, 255)));
+    assert_eq!(canvas.pixel(300, 300), Some((253, 226, 93, 255)));
+}
+
+#[test]
+fn render_arc_in_degrees_clockwise() {
+    let (canvas, _) = render_ok(r#"
+        setFigureColor(Color::Green);
+        arc(500, 500, 200, 0, 180);
+    "#);
+    assert_eq!(canvas.pixel(500, 600), Some((126, 183, 134, 255)));
+    assert_eq!(canvas.pixel(500, 400), Some((10, 15, 31, 255)));
+}
+
+#[test]
+fn render_one_png_per_animation_frame() {
+    let (_, frames) = render_ok(r#"
+        animate();
+        for i in (1..3) {
+            clear();
+            circle(100 * i, 500, 40);
+            frame();
+        }
+    "#);
+    assert_eq!(frames.len(), 3);
+    assert_ne!(frames[0], frames[1]);
+}
+
+#[test]
+fn render_typed_commands_directly() {
+    let mut canvas = Rasterizer::new();
+    let style = ShapeStyle { fill: Rgba::new(255, 0, 0, 255), stroke: Rgba::new(255, 0, 0, 255), width: 1 };
+    canvas.draw(&DrawCommand::Rectangle { x1: 0, y1: 0, x2: 10, y2: 10, style });
+    canvas.draw(&DrawCommand::Line { x1: 20, y1: 5, x2: 40, y2: 5, style: LineStyle { stroke: Rgba::WHITE, width: 4 } });
+    assert_eq!(canvas.pixel(5, 5), Some((255, 0, 0, 255)));
+    assert_eq!(canvas.pixel(30, 5), Some((255, 255, 255, 255)));
+}
+
+#[test]
+fn svg_keeps_fill_stroke_and_width() {
+    let svg = export_svg(r#"
+        setFigureColor(Color::Red);
+        setLineColor(Color::Blue);
+        setLineWidth(4);
+        circle(500, 500, 100);
+        line(0, 0, 10, 20);
+    "#).unwrap();
+    assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\""));
+    assert!(svg.contains("<circle cx=\"500\" cy=\"500\" r=\"100\" fill=\"#e92331\" stroke=\"#2e73e6\" stroke-width=\"4\""));
+    assert!(svg.contains("<line x1=\"0\" y1=\"0\" x2=\"10\" y2=\"20\" fill=\"none\" stroke=\"#2e73e6\" stroke-width=\"4\""));
+    assert!(svg.trim_end().ends_with("</svg>"));
+}
+
+#[test]
+fn svg_arcs_and_polygons() {
+    let svg = export_svg(r#"
+        arc(500, 500, 100, 0, 90);
+        arc(500, 500, 100, 0, 360);
+        polygon(0, 0, 100, 0, 50, 80);
+    "#).unwrap();
+    assert!(svg.contains("d=\"M 600.000 500.000 A 100 100 0 0 1 500.000 600.000\""));
+    assert!(svg.contains("A 100 100 0 0 1 400.000 500.000 A 100 100 0 0 1 600.000 500.000"));
+    assert!(svg.contains("<polygon points=\"0,0 100,0 50,80\""));
+}
+
+#[test]
+fn svg_clear_and_animation() {
+    let svg = export_svg(r#"
+        circle(10, 10, 5);
+        clear();
+        rectangle(30, 40, 10, 20);
+    "#).unwrap();
+    assert!(!svg.contains("<circle"));
+    assert!(svg.contains("<rect x=\"10\" y=\"20\" width=\"20\" height=\"20\" fill=\"#ffffff\""));
+    assert!(export_svg("animate(); circle(10, 10, 5); frame();").is_err());
+}
+
+fn sample_commands() -> Vec<DrawCommand> {
+    let style = ShapeStyle { fill: Rgba::new(233, 35, 49, 255), stroke: Rgba::BLACK, width: 2 };
+    vec![
+        DrawCommand::Animate,
+        DrawCommand::Circle { x: 10, y: 20, r: 30, style },
+        DrawCommand::Polygon { points: vec![(0, 0), (10, 0), (5, -8)], style },
+        DrawCommand::Arc { x: 1, y: 2, r: 3, start: 0, end: 270, style },
+        DrawCommand::Line { x1: 0, y1: 0, x2: 1, y2: 1, style: LineStyle { stroke: Rgba::new(1, 2, 3, 4), width: 0 } },
+        DrawCommand::Text { x: 5, y: 6, text: String::from("it's"), style: TextStyle { fill: Rgba::BLACK, size: 12, font: String::from("serif") } },
+        DrawCommand::Sleep { ms: 16 },
+        DrawCommand::Frame,
+        DrawCommand::Clear,
+        DrawCommand::End,
+    ]
+}
+
+#[test]
+fn protocol_json_round_trip() {
+    let commands = sample_commands();
+    let json = command::to_json(&commands);
+    assert!(json.starts_with(r##"{"version":2,"commands":["animate",{"circle":{"x":10,"y":20,"r":30,"style":{"fill":"#e92331ff","stroke":"#000000ff","width":2}}}"##));
+    assert_eq!(command::from_json(&json).unwrap(), commands);
+    assert!(command::from_json(r#"{"version":1,"commands":[]}"#).is_err());
+    assert!(command::from_json(r#"{"version":2,"commands":[{"circle":{"x":1}}]}"#).is_err());
+}
+
+#[test]
+fn protocol_binary_round_trip() {
+    let commands = sample_commands();
+    let bytes = command::to_binary(&commands);
+    assert_eq!(bytes[0], command::PROTOCOL_VERSION as u8);
+    assert!(bytes.len() < command::to_json(&commands).len() / 4);
+    assert_eq!(command::from_binary(&bytes).unwrap(), commands);
+    let mut future = bytes.clone();
+    future[0] = 3;
+    assert!(command::from_binary(&future).is_err());
+    assert!(command::from_binary(&bytes[..bytes.len() - 1]).is_err());
+}
+
+#[test]
+fn command_blocks_carry_structured_commands() {
+    let msg = block_on(Compiler::new().compile("circle(1, 2, 3); sleep(5); frame();"));
+    let mut runtime = msg.get_runtime();
+    runtime.execute();
+    let blocks = runtime.get_commands();
+    assert_eq!(blocks[0].sleep_for, 5);
+    assert!(matches!(blocks[0].commands(), [DrawCommand::Circle { x: 1, y: 2, r: 3, .. }]));
+    assert_eq!(blocks[1].get_status(), 0);
+    assert!(blocks[1].commands().is_empty());
+    assert_eq!(command::from_json(&blocks[0].get_commands_json()).unwrap(), blocks[0].commands());
+}
+
+#[test]
+fn bytecode_matches_tree_walker_on_functions_and_globals() {
+    let (cmds, code) = run_both_backends(r#"
+        global {
+            int calls = 0;
+        }
+
+        func fib(int n) -> int {
+            calls = calls + 1;
+            if (n < 2) {
+                return n;
+            }
+            return fib(n - 1) + fib(n - 2);
+        }
+
+        func mark(int x) -> bool {
+            if (x % 2 == 0) {
+                circle(x, fib(x), 1);
+                return true;
+            }
+            return false;
+        }
+
+        func main() {
+            for i in (6..1) {
+                mark(i);
+            }
+            rectangle(calls, 0, 0, 0);
+        }
+    "#);
+    assert_eq!(code, 0);
+    assert_eq!(cmds, vec![
+        "circle 6 8 1 fill=#ffffffff stroke=#000000ff width=1".to_string(),
+        "circle 4 3 1 fill=#ffffffff stroke=#000000ff width=1".to_string(),
+        "circle 2 1 1 fill=#ffffffff stroke=#000000ff width=1".to_string(),
+        "rectangle 37 0 0 0 fill=#ffffffff stroke=#000000ff width=1".to_string(),
+    ]);
+}
+
+#[test]
+fn bytecode_matches_tree_walker_on_arrays_and_loops() {
+    let (cmds, code) = run_both_backends(r#"
+        array<array<int, 3>, 2> grid = {{1, 2, 3}, {4, 5, 6}};
+        int total = 0;
+        int i = 0;
+        while (i < 2) {
+            for j in (0..2) {
+                grid[i][j] = grid[i][j] * 10;
+                total = total + grid[i][j];
+            }
+            i = i + 1;
+        }
+        float f = decimal(total) / 4.0;
+        line(grid[1][2], total, round(f), -i);
+    "#);
+    assert_eq!(code, 0);
+    assert_eq!(cmds, vec!["line 60 210 53 -2 stroke=#000000ff width=1".to_string()]);
+}
+
+#[test]
+fn bytecode_reports_the_same_runtime_errors() {
+    let (_, code) = run_both_backends(r#"
+        array<int, 2> xs = {1, 2};
+        int i = 0;
+        while (true) {
+            circle(xs[i], 0, 1);
+            i = i + 1;
+        }
+    "#);
+    assert_eq!(code, 4);
+    let (_, code) = run_both_backends("int zero = 0; circle(1, 1, 1); circle(1 / zero, 1, 1);");
+    assert_eq!(code, 4);
+}
+
+#[test]
+fn bytecode_runs_long_loops() {
+    let msg = block_on(Compiler::new().compile(r#"
+        int sum = 0;
+        for i in (1..200000) {
+            sum = (sum + i) % 1000;
+        }
+        circle(sum, 0, 1);
+    "#));
+    let mut runtime = msg.get_runtime();
+    assert!(runtime.uses_bytecode());
+    runtime.execute();
+    let blocks = runtime.get_commands();
+    assert!(matches!(blocks[0].commands(), [DrawCommand::Circle { x: 0, .. }]));
+    assert_eq!(blocks[0].get_status(), 2);
+}
+
+fn drawn(runtime: &mut crate::runtime::Runtime) -> Vec<String> {
+    runtime.get_commands().iter()
+        .flat_map(|block| block.commands().iter().map(ToString::to_string).collect::<Vec<_>>())
+        .collect()
+}
+
+fn paused_line(runtime: &crate::runtime::Runtime) -> Option<usize> {
+    runtime.get_paused_span().map(|span| span.start_row)
+}
+
+#[test]
+fn debugger_stops_at_breakpoints_in_loops() {
+    let src = "for i in (1..3) {\n    circle(i, i, i);\n}\nline(0, 0, 1, 1);";
+    let msg = block_on(Compiler::new().compile(src));
+    let mut runtime = msg.get_runtime();
+    runtime.set_breakpoint(2);
+    runtime.execute();
+
+    for i in 1..=3 {
+        assert!(runtime.is_paused());
+        let span = runtime.get_paused_span().unwrap();
+        assert_eq!((span.start_row, span.start_column), (2, 5));
+        assert_eq!(drawn(&mut runtime).len(), if i == 1 { 0 } else { 1 });
+        runtime.resume();
     }
+    assert!(!runtime.is_paused());
+    assert_eq!(drawn(&mut runtime), vec![
+        "circle 3 3 3 fill=#ffffffff stroke=#000000ff width=1".to_string(),
+        "line 0 0 1 1 stroke=#000000ff width=1".to_string(),
+    ]);
+}
 
-    #[test]
-    fn precedence_with_parentheses() {
-        let src = r#"
-            rectangle((2+3)*4, (20-6)/2, (10%4)+1, (8/2)*(3+1));
-        "#;
-        let cmds = compile_ok(src);
-        let expected = vec![
-            // (2+3)*4 = 20, (20-6)/2 = 7, (10%4)+1 = 3, (8/2)*(3+1) = 16
-            "rectangle 20 7 3 16 fill=#ffffff stroke=#000000 width=1".to_string(),
-        ];
-        assert_eq!(cmds, expected);
+#[test]
+fn debugger_steps_over_into_and_out_of_calls() {
+    let src = "func square(int x) -> int {\n    int y = x * x;\n    return y;\n}\nfunc main() -> int {\n    int a = square(2);\n    int b = square(3);\n    circle(a, b, 1);\n    return 0;\n}";
+    let msg = block_on(Compiler::new().compile(src));
+    let mut runtime = msg.get_runtime();
+    runtime.pause();
+    runtime.execute();
+    assert_eq!(paused_line(&runtime), Some(6));
+
+    runtime.step_over();
+    assert_eq!(paused_line(&runtime), Some(7));
+    runtime.step_into();
+    assert_eq!(paused_line(&runtime), Some(2));
+    runtime.step_into();
+    assert_eq!(paused_line(&runtime), Some(3));
+    runtime.step_out();
+    assert_eq!(paused_line(&runtime), Some(8));
+    runtime.resume();
+    assert_eq!(paused_line(&runtime), None);
+    assert_eq!(runtime.get_runtime_error().error_code, 0);
+    assert_eq!(drawn(&mut runtime), vec!["circle 4 9 1 fill=#ffffffff stroke=#000000ff width=1".to_string()]);
+}
+
+// Holds every tick until the test lets the program go on, like a browser that is busy
+#[derive(Debug, Default)]
+struct HeldTimer {
+    waiting: std::sync::Arc<std::sync::Mutex<Option<std::task::Waker>>>,
+}
+
+impl HeldTimer {
+    fn release(&self) -> bool {
+        let waker = self.waiting.lock().unwrap().take();
+        waker.map(|waker| {
+            waker.wake();
+            crate::utils::timer::run_pending();
+        }).is_some()
     }
+}
 
-    #[test]
-    fn polygon_with_mixed_precedence_and_parentheses() {
-        let src = r#"
-            setFigureColor(Color::Yellow);
-            polygon(1+2*3, (4+6)%5, 18/3-2, (2+2)*(3+1), 7, 8);
-        "#;
-        let cmds = compile_ok(src);
-        let expected = vec![
-            // 1+2*3 = 7, (4+6)%5 = 0, 18/3-2 = 4, (2+2)*(3+1) = 16
-            "polygon 7 0 4 16 7 8 fill=#fde25d stroke=#000000 width=1".to_string(),
-        ];
-        assert_eq!(cmds, expected);
+impl crate::utils::timer::Timer for HeldTimer {
+    fn tick(&self) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()>>> {
+        let waiting = std::sync::Arc::clone(&self.waiting);
+        let mut parked = false;
+        Box::pin(std::future::poll_fn(move |cx| {
+            if parked {
+                return std::task::Poll::Ready(());
+            }
+            parked = true;
+            *waiting.lock().unwrap() = Some(cx.waker().clone());
+            std::task::Poll::Pending
+        }))
     }
+}
 
-    #[test]
-    fn line_with_arithmetic_and_styles() {
-        let src = r#"
-            setLineColor(Color::Pink);
-            setLineWidth(2*3);
-            line(5*2, 20/2, 50-25, (3+7));
-        "#;
-        let cmds = compile_ok(src);
-        let expected = vec![
-            // line: (10, 10, 25, 10), stroke=#fb9ab5, width=6
-            "line 10 10 25 10 stroke=#fb9ab5 width=6".to_string(),
-        ];
-        assert_eq!(cmds, expected);
+#[test]
+fn debugger_pauses_a_running_program() {
+    let src = "int x = 0;\nwhile (x < 20000) {\n    x = x + 1;\n}\nprint(x);";
+    for tree_walker in [false, true] {
+        let timer = std::sync::Arc::new(HeldTimer::default());
+        let msg = block_on(Compiler::new().compile_with_timer(src, std::sync::Arc::clone(&timer) as _));
+        let runtime = if tree_walker { msg.get_runtime().without_bytecode() } else { msg.get_runtime() };
+        assert_eq!(runtime.uses_bytecode(), !tree_walker);
+        // Let it get into the loop before pausing
+        runtime.execute();
+        assert!(timer.release());
+        assert!(!runtime.is_paused());
+
+        runtime.pause();
+        assert!(timer.release());
+        assert!(runtime.is_paused());
+        assert!(matches!(paused_line(&runtime), Some(2 | 3)));
+        runtime.resume();
+        while timer.release() {}
+        assert!(!runtime.is_paused());
+        assert_eq!(runtime.get_runtime_error().error_code, 0);
     }
+}
 
-    #[test]
-    fn arc_with_arithmetic_and_line_style() {
-        let src = r#"
-            setLineColor(Color::Cyan);
-            setLineWidth(4+1);
-            arc(100/2, 200-50, 5*4, 360/4, 50%7);
-        "#;
-        let cmds = compile_ok(src);
-        let expected = vec![
-            // arc(50, 150, 20, 90, 1), stroke=#3ba8e7, width=5
-            "arc 50 150 20 90 1 fill=#ffffff stroke=#3ba8e7 width=5".to_string(),
-        ];
-        assert_eq!(cmds, expected);
+#[test]
+fn snapshot_shows_scopes_globals_and_calls() {
+    let src = "global {\n    int frames = 2;\n}\nfunc shade(int x) -> color {\n    array<int, 2> parts = {4, 5};\n    circle(x, x, x);\n    return Color::Red;\n}\nfunc main() -> int {\n    bool ok = true;\n    for i in (1..1) {\n        color c = shade(i * 2);\n    }\n    return 0;\n}";
+    let msg = block_on(Compiler::new().compile(src));
+    assert_eq!(msg.error_code, 0, "Unexpected compile error: {}", msg.get_error_message());
+    for tree_walker in [false, true] {
+        let runtime = if tree_walker { msg.get_runtime().without_bytecode() } else { msg.get_runtime() };
+        assert_eq!(runtime.uses_bytecode(), !tree_walker);
+        runtime.set_breakpoint(6);
+        runtime.execute();
+        assert_eq!(paused_line(&runtime), Some(6));
+
+        let snapshot = runtime.snapshot();
+        assert_eq!(snapshot.globals.get("frames"), Some(&Value::Int(2)));
+        let calls: Vec<&str> = snapshot.calls.iter().map(|frame| frame.function.as_str()).collect();
+        assert_eq!(calls, vec!["main", "shade"]);
+
+        let shade = &snapshot.calls[1];
+        assert_eq!(shade.start_row, 12);
+        assert_eq!(shade.scopes[0].get("parts"), Some(&Value::Array(vec![Value::Int(4), Value::Int(5)])));
+        assert!(shade.scopes.iter().any(|scope| scope.get("x") == Some(&Value::Int(2))));
+        assert!(shade.scopes.iter().all(|scope| !scope.contains_key("ok")));
+
+        let main = &snapshot.calls[0];
+        assert_eq!(main.start_row, 9);
+        assert_eq!(main.scopes[0].get("i"), Some(&Value::Int(1)));
+        assert!(main.scopes.iter().any(|scope| scope.get("ok") == Some(&Value::Bool(true))));
+        // `c` is only set once `shade` returns
+        assert!(main.scopes.iter().all(|scope| !scope.contains_key("c")));
+
+        let json = runtime.get_snapshot_json();
+        assert!(json.contains(r#""frames":{"type":"int","value":2}"#), "{}", json);
+        runtime.resume();
+        assert!(runtime.snapshot().calls.is_empty());
     }
+}
 
-        #[test]
-    fn int_variable_in_circle() {
-        let src = r#"
-            int x = 5;
-            circle(x, x*2, x+10);
-        "#;
-        let cmds = compile_ok(src);
-        let expected = vec![
-            // x=5 → (5, 10, 15)
-            "circle 5 10 15 fill=#ffffff stroke=#000000 width=1".to_string(),
-        ];
-        assert_eq!(cmds, expected);
+fn run_error(src: &str, tree_walker: bool, configure: impl Fn(&crate::runtime::Runtime)) -> crate::utils::message::RuntimeError {
+    let msg = block_on(Compiler::new().compile(src));
+    assert_eq!(msg.error_code, 0, "Unexpected compile error: {}", msg.get_error_message());
+    let runtime = if tree_walker { msg.get_runtime().without_bytecode() } else { msg.get_runtime() };
+    configure(&runtime);
+    runtime.execute();
+    runtime.get_runtime_error()
+}
+
+#[test]
+fn endless_loops_run_out_of_steps() {
+    let src = "int x = 0;\nwhile (true) {\n    x = x + 1;\n}";
+    for tree_walker in [false, true] {
+        let error = run_error(src, tree_walker, |runtime| runtime.set_step_budget(10_000));
+        assert_eq!(error.error_code, 5);
+        assert_eq!((error.start_row, error.start_column), (2, 1));
+        assert!(error.get_error_message().contains("frame() or sleep()"), "{}", error.get_error_message());
+
+        let short = "int x = 0;\nwhile (x < 10) {\n    x = x + 1;\n}";
+        assert_eq!(run_error(short, tree_walker, |runtime| runtime.set_step_budget(100)).error_code, 0);
     }
 
-    #[test]
-    fn color_variable_in_rectangle() {
-        let src = r#"
-            color b = Color::Red;
-            setFigureColor(b);
-            rectangle(0, 0, 20, 10);
-        "#;
-        let cmds = compile_ok(src);
-        let expected = vec![
-            "rectangle 0 0 20 10 fill=#e92331 stroke=#000000 width=1".to_string(),
+    // Both backends count steps the same way, so they stop at the same loop count
+    let codes = |src: &str| [false, true].map(|tree_walker| run_error(src, tree_walker, |runtime| runtime.set_step_budget(100)).error_code);
+    let counted = |n: u32| format!("int x = 0;\nwhile (x < {}) {{\n    x = x + 1;\n}}", n);
+    for n in 40..60 {
+        let loops = [
+            counted(n),
+            format!("for i in (1..{}) {{\n    if (i > 0) {{ continue; }}\n}}", n),
+            format!("list<int> xs = {{}};\nfor i in (1..{}) {{ push(xs, i); }}\nfor x in xs {{\n    int y = x;\n}}", n / 2),
         ];
-        assert_eq!(cmds, expected);
+        for src in &loops {
+            let [vm, tree_walker] = codes(src);
+            assert_eq!(vm, tree_walker, "{}", src);
+        }
     }
+    assert_eq!((codes(&counted(49)), codes(&counted(50))), ([0, 0], [5, 5]));
+}
 
-    // #[test]
-    // fn float_variable_in_arc() {
-    //     let src = r#"
-    //         float t = 4.5;
-    //         arc(10, 20, t*2, 0, 180);
-    //     "#;
-    //     let cmds = compile_ok(src);
-    //     let expected = vec![
-    //         // t=4.5, t*2=9.0 (assuming compiler casts float→int)
-    //         "arc 10 20 9 0 180 fill=#ffffff stroke=#000000 width=1".to_string(),
-    //     ];
-    //     assert_eq!(cmds, expected);
-    // }
-
-    // #[test]
-    // fn bool_variable_in_line_width() {
-    //     let src = r#"
-    //         bool d = true;
-    //         setLineWidth(d*5); // true→1, false→0 ?
-    //         line(0, 0, 10, 10);
-    //     "#;
-    //     let cmds = compile_ok(src);
-    //     let expected = vec![
-    //         // d=true → 1*5=5
-    //         "line 0 0 10 10 stroke=#000000 width=5".to_string(),
-    //     ];
-    //     assert_eq!(cmds, expected);
-    // }
+#[test]
+fn loops_drawing_without_frames_run_out_of_commands() {
+    let src = "for i in (0..10) {\n    for j in (0..1000) {\n        circle(i, j, 1);\n    }\n}";
+    let animated = "for i in (0..10) {\n    for j in (0..100) {\n        circle(i, j, 1);\n    }\n    frame();\n}";
+    for tree_walker in [false, true] {
+        let error = run_error(src, tree_walker, |runtime| runtime.set_command_budget(500));
+        assert_eq!(error.error_code, 5);
+        assert_eq!((error.start_row, error.start_column), (2, 5));
 
-    #[test]
-    fn mixed_variables_in_polygon() {
-        let src = r#"
-            int x = 2;
-            color c = Color::Blue;
-            setFigureColor(c);
-            polygon(x, 3, x*10, 3*2, 3, 4);
-        "#;
-        let cmds = compile_ok(src);
-        let expected = vec![
-            // x=2, y=3.5 → polygon(2, 3, 20, 7)
-            "polygon 2 3 20 6 3 4 fill=#2e73e6 stroke=#000000 width=1".to_string(),
-        ];
-        assert_eq!(cmds, expected);
+        assert_eq!(run_error(animated, tree_walker, |runtime| runtime.set_command_budget(500)).error_code, 0);
+        assert_eq!(run_error(src, tree_walker, |runtime| runtime.set_command_budget(0)).error_code, 0);
     }
+}
+
+#[test]
+fn repl_keeps_variables_between_inputs() {
+    let mut repl = Repl::new();
+    assert_eq!(repl.eval("int r = 7 / 2;").unwrap().value, None);
+    assert_eq!(repl.eval("r").unwrap().value, Some(Value::Int(3)));
+    assert_eq!(repl.eval("decimal(7) / decimal(2)").unwrap().value, Some(Value::Float(3.5)));
+    assert_eq!(repl.eval("r = r * 2").unwrap().value, None);
+
+    let evaluation = repl.eval("for i in (1..2) { circle(r, i, 1); }").unwrap();
+    let drawn: Vec<String> = evaluation.commands.iter().map(ToString::to_string).collect();
+    assert_eq!(drawn, vec![
+        "circle 6 1 1 fill=#ffffffff stroke=#000000ff width=1".to_string(),
+        "circle 6 2 1 fill=#ffffffff stroke=#000000ff width=1".to_string(),
+    ]);
+    assert_eq!(repl.eval("decimal(r)").unwrap().value.unwrap().to_string(), "6.0");
+}
 
-        #[test]
-    fn polygon_requires_at_least_six_args() {
-        // Too few args (only 4): should be an error.
-        let src = r#"
-            polygon(0,0, 10,0);
-        "#;
-        let msg = Compiler::new().linear_compile_code(src);
-        assert_ne!(msg.error_code, 0);
-        assert!(msg.get_error_message().to_lowercase().contains("polygon"));
-        assert!(msg.get_error_message().to_lowercase().contains("6"));
+#[test]
+fn repl_rejects_ill_typed_inputs_without_losing_state() {
+    let mut repl = Repl::new();
+    repl.eval("int x = 1;").unwrap();
+    assert!(repl.eval("bool y = x;").is_err());
+    assert!(repl.eval("x + true").is_err());
+    assert!(repl.eval("y").is_err());
+    assert!(repl.eval("int x = 2;").is_err());
+    assert_eq!(repl.eval("x + 1").unwrap().value, Some(Value::Int(2)));
+    assert!(repl.eval("func f() -> int { return 1; }").is_err());
+}
+
+#[test]
+fn repl_limits_start_over_for_every_input() {
+    let mut repl = Repl::new();
+    repl.set_step_budget(1000);
+    repl.set_command_budget(100);
+    for _ in 0..5 {
+        repl.eval("for i in (0..300) { }").unwrap();
+        assert_eq!(repl.eval("for i in (0..59) { circle(i, i, 1); }").unwrap().commands.len(), 60);
     }
+    assert!(repl.eval("for i in (0..3000) { }").is_err());
+}
 
-    #[test]
-    fn if_true_branch_executes() {
-        let src = r#"
-            if (3 > 2) {
-                setFigureColor(Color::Green);
-                circle(10, 20, 30);
+#[test]
+fn repl_forgets_inputs_that_fail_at_runtime() {
+    let mut repl = Repl::new();
+    assert!(repl.eval("circle(1, 1, 1);\nint y = 2;\nint x = 1 / 0;").is_err());
+    assert!(repl.eval("x").is_err());
+    assert!(repl.eval("y").is_err());
+    let evaluation = repl.eval("int x = 2;").unwrap();
+    assert!(evaluation.commands.is_empty(), "{:?}", evaluation.commands);
+    assert_eq!(repl.eval("x").unwrap().value, Some(Value::Int(2)));
+    assert!(repl.eval("int y = 3;").is_ok());
+}
+
+#[test]
+fn else_if_chains_pick_the_first_true_branch() {
+    let (cmds, code) = run_both_backends(r#"
+        func size(int x) -> int {
+            if (x < 2) {
+                return 1;
+            } else if (x < 4) {
+                return 2;
+            } else if (x == 4) {
+                return 3;
             } else {
-                setFigureColor(Color::Red);
-                circle(0, 0, 5);
+                return 4;
             }
-        "#;
-        let cmds = compile_ok(src);
-        let expected = vec![
-            "circle 10 20 30 fill=#7eb786 stroke=#000000 width=1".to_string(), // green
-        ];
-        assert_eq!(cmds, expected);
-    }
+        }
 
-    #[test]
-    fn if_false_branch_executes_else() {
-        let src = r#"
-            if (1 == 2) {
-                rectangle(0,0, 1,1);
-            } else {
-                setFigureColor(Color::Blue);
-                rectangle(10, 10, 20, 20);
+        func main() {
+            for i in (1..5) {
+                circle(i, size(i), 1);
             }
-        "#;
-        let cmds = compile_ok(src);
-        let expected = vec![
-            "rectangle 10 10 20 20 fill=#2e73e6 stroke=#000000 width=1".to_string(), // blue
-        ];
-        assert_eq!(cmds, expected);
-    }
+        }
+    "#);
+    assert_eq!(code, 0);
+    let sizes: Vec<String> = cmds.iter().map(|cmd| cmd.split(' ').nth(2).unwrap().to_string()).collect();
+    assert_eq!(sizes, vec!["1", "2", "2", "3", "4"]);
+}
 
-    #[test]
-    fn if_without_else_skips_when_false() {
-        let src = r#"
-            if (5 < 4) {
-                circle(1,2,3);
+#[test]
+fn else_if_chains_report_errors_at_the_failing_branch() {
+    let src = "int x = 3;\nif (x < 2) {\n    circle(x, x, 1);\n} else if (x + 1) {\n    circle(x, x, 2);\n}\n";
+    let error = Compiler::new().check(src).unwrap_err();
+    assert_eq!((error.start, error.finish), ((4, 12), (4, 17)));
+
+    // Without a final else the chain may fall through
+    let src = "func f(int x) -> int {\n    if (x < 2) {\n        return 1;\n    } else if (x < 4) {\n        return 2;\n    }\n}\n\nfunc main() {\n    circle(f(1), 1, 1);\n}\n";
+    assert!(Compiler::new().check(src).is_err());
+    let src = src.replace("return 2;\n    }", "return 2;\n    } else {\n        return 3;\n    }");
+    assert!(Compiler::new().check(&src).is_ok());
+}
+
+#[test]
+fn break_and_continue_leave_the_innermost_loop() {
+    let (cmds, code) = run_both_backends(r#"
+        func first_multiple(int of, int from) -> int {
+            for i in (from..100) {
+                if (i % of == 0) {
+                    return i;
+                }
             }
-        "#;
-        let cmds = compile_ok(src);
-        assert!(cmds.is_empty(), "Expected no commands when condition is false and no else-block");
-    }
+            return -1;
+        }
 
-    #[test]
-    fn boolean_operator_precedence_and_parentheses() {
-        // Expect true: !false && (true || false) == true
-        let src = r#"
-            if (!false && (true || false)) {
-                setLineColor(Color::Cyan);
-                setLineWidth(2);
-                line(0, 0, 5*2, 4+6);
-            } else {
-                line(0,0,1,1);
+        func main() {
+            for i in (1..10) {
+                if (i % 2 == 0) {
+                    continue;
+                }
+                if (i > 7) {
+                    break;
+                }
+                int j = 0;
+                while (true) {
+                    j = j + 1;
+                    if (j < i) {
+                        continue;
+                    }
+                    break;
+                }
+                circle(i, j, first_multiple(3, i));
             }
-        "#;
-        let cmds = compile_ok(src);
-        let expected = vec![
-            "line 0 0 10 10 stroke=#3ba8e7 width=2".to_string(),
-        ];
-        assert_eq!(cmds, expected);
+        }
+    "#);
+    assert_eq!(code, 0);
+    assert_eq!(cmds, vec![
+        "circle 1 1 3 fill=#ffffffff stroke=#000000ff width=1".to_string(),
+        "circle 3 3 3 fill=#ffffffff stroke=#000000ff width=1".to_string(),
+        "circle 5 5 6 fill=#ffffffff stroke=#000000ff width=1".to_string(),
+        "circle 7 7 9 fill=#ffffffff stroke=#000000ff width=1".to_string(),
+    ]);
+}
+
+#[test]
+fn break_and_continue_need_a_loop() {
+    let error = Compiler::new().check("int x = 1;\nif (x > 0) {\n    break;\n}\n").unwrap_err();
+    assert_eq!((error.start, error.finish), ((3, 5), (3, 11)));
+    assert!(error.message.contains("break"));
+    // A loop around the call doesn't count
+    assert!(Compiler::new().check("func skip() {\n    continue;\n}\n\nfunc main() {\n    for i in (1..3) {\n        skip();\n    }\n}\n").is_err());
+    assert!(Compiler::new().check("int break = 1;").is_err());
+
+    // The loop may be left before it returns
+    let src = "func f(int x) -> int {\n    while (true) {\n        if (x > 3) {\n            break;\n        }\n        return x;\n    }\n}\n\nfunc main() {\n    circle(f(1), 1, 1);\n}\n";
+    assert!(Compiler::new().check(src).is_err());
+    assert!(Compiler::new().check(&src.replace("break;", "x = 3;")).is_ok());
+}
+
+#[test]
+fn for_ranges_with_steps_exclusive_ends_and_floats() {
+    let (cmds, code) = run_both_backends(r#"
+        for i in (0..10 step 3) {
+            circle(i, 0, 1);
+        }
+        for i in (10..0 step -4) {
+            circle(i, 1, 1);
+        }
+        for i in (3..<0) {
+            circle(i, 2, 1);
+        }
+        for i in (0..<0) {
+            circle(i, 3, 1);
+        }
+        for i in (0..5 step -1) {
+            circle(i, 4, 1);
+        }
+        for x in (0.0..1.0 step 0.25) {
+            circle(round(x * 100.0), 5, 1);
+        }
+        for x in (0.0..<0.3 step 0.1) {
+            circle(round(x * 100.0), 6, 1);
+        }
+    "#);
+    assert_eq!(code, 0);
+    let points: Vec<String> = cmds.iter().map(|cmd| cmd.split(' ').skip(1).take(2).collect::<Vec<_>>().join(" ")).collect();
+    assert_eq!(points, vec![
+        "0 0", "3 0", "6 0", "9 0",
+        "10 1", "6 1", "2 1",
+        "3 2", "2 2", "1 2",
+        "0 5", "25 5", "50 5", "75 5", "100 5",
+        "0 6", "10 6", "20 6",
+    ]);
+
+    let (_, code) = run_both_backends("int s = 0;\nfor i in (0..10 step s) {\n    circle(i, i, 1);\n}\n");
+    assert_eq!(code, 4);
+}
+
+#[test]
+fn for_ranges_are_all_int_or_all_float() {
+    let error = Compiler::new().check("for x in (0.0..10 step 0.5) {\n    circle(1, 1, 1);\n}\n").unwrap_err();
+    assert_eq!((error.start, error.finish), ((1, 16), (1, 18)));
+    assert!(Compiler::new().check("for x in (0..10 step 0.5) {}").is_err());
+    assert!(Compiler::new().check("for x in (true..false) {}").is_err());
+    assert!(Compiler::new().check("for x in (0.0..1.0) {\n    int y = x;\n}\n").is_err());
+    assert!(Compiler::new().check("for x in (0.0..1.0) {\n    float y = x;\n}\n").is_ok());
+}
+
+#[test]
+fn strings_join_compare_and_convert() {
+    let (cmds, code) = run_both_backends(r#"
+        string name = 'it''s';
+        string label = name + ' ' + string(3) + string(2.0) + string(true);
+        if (label == 'it''s 32.0true' && 'abc' < 'abd' && name != '') {
+            circle(1, 1, 1);
+        }
+        setFontSize(12);
+        setFont('serif');
+        text(10, 20, label);
+    "#);
+    assert_eq!(code, 0);
+    assert_eq!(cmds, vec![
+        "circle 1 1 1 fill=#ffffffff stroke=#000000ff width=1",
+        "text 10 20 'it''s 32.0true' fill=#ffffffff size=12 font=serif",
+    ]);
+
+    let mut repl = Repl::new();
+    assert_eq!(repl.eval("'a' + 'b'").unwrap().value, Some(Value::String(String::from("ab"))));
+}
+
+#[test]
+fn strings_only_mix_with_strings() {
+    let error = Compiler::new().check("string s = 'a' + 1;").unwrap_err();
+    assert!(error.message.contains("string()"), "{}", error.message);
+    assert!(Compiler::new().check("string s = 'a' - 'b';").is_err());
+    assert!(Compiler::new().check("int x = 'a';").is_err());
+    assert!(Compiler::new().check("text(1, 1, 5);").is_err());
+    assert!(Compiler::new().check("string s = string('a') + string(Color::Red);").is_err());
+    assert_eq!(run_both_backends("setFontSize(0);").1, 4);
+}
+
+#[test]
+fn text_is_drawn_in_svg_and_png() {
+    let svg = export_svg("text(5, 6, 'a < b');").unwrap();
+    assert!(svg.contains(">a &lt; b</text>"), "{}", svg);
+    assert!(svg.contains("font-size=\"20\""));
+    let (rasterizer, _) = render_ok("setFigureColor(Color::Red); text(0, 0, 'H');");
+    assert_eq!(rasterizer.pixel(1, 6), Some((0xe9, 0x23, 0x31, 0xff)));
+    assert_ne!(rasterizer.pixel(6, 6), Some((0xe9, 0x23, 0x31, 0xff)));
+}
+
+// What each block printed, from both backends
+fn printed_blocks(src: &str) -> Vec<String> {
+    let compile = || block_on(Compiler::new().compile(src)).get_runtime();
+    let mut results = vec![];
+    for mut runtime in [compile(), compile().without_bytecode()] {
+        runtime.execute();
+        assert_eq!(runtime.get_runtime_error().error_code, 0, "{}", runtime.get_runtime_error().get_error_message());
+        results.push(runtime.get_commands().iter().map(|block| block.output().to_string()).collect::<Vec<_>>());
     }
+    assert_eq!(results[0], results[1]);
+    results.remove(0)
+}
 
-    #[test]
-    fn comparisons_on_int_and_float_in_condition() {
-        let src = r#"
-            int x = 5;
-            float t = 4.5;
-            if ( ((x >= 5) && (t < 5.0)) && (x != 0) ) {
-                setFigureColor(Color::Yellow);
-                circle(x+5, x, x*2);
-            } else {
-                rectangle(0,0,1,1);
+#[test]
+fn print_keeps_its_place_between_blocks() {
+    let blocks = printed_blocks(r#"
+        array<int, 3> a = {1, 2, 3};
+        print('a =', a);
+        println();
+        sleep(10);
+        println(2.0, true, Color::Red, {'x', 'y'}, 3 / 2);
+        frame();
+        circle(1, 1, 1);
+        print('done');
+    "#);
+    assert_eq!(blocks, vec![
+        "a = {1, 2, 3}\n",
+        "2.0 true rgb(233, 35, 49) {'x', 'y'} 1\n",
+        "done",
+    ]);
+
+    let mut repl = Repl::new();
+    let evaluation = repl.eval("println('x', 1);").unwrap();
+    assert_eq!((evaluation.output.as_str(), evaluation.value), ("x 1\n", None));
+    assert!(Compiler::new().check("print(1 + true);").is_err());
+    assert!(Compiler::new().check("int x = print(1);").is_err());
+}
+
+#[test]
+fn structs_are_values_with_named_fields() {
+    let (cmds, code) = run_both_backends(r#"
+        struct Point {
+            int x;
+            int y;
+        }
+
+        struct Ball {
+            Point pos;
+            array<int, 2> speed;
+            color fill;
+        }
+
+        func moved(Ball b) -> Ball {
+            b.pos.x = b.pos.x + b.speed[0];
+            b.pos.y = b.pos.y + b.speed[1];
+            return b;
+        }
+
+        func main() {
+            int a = 5;
+            Point p = Point {y: 2, x: a};
+            array<Ball, 2> balls = {
+                Ball {pos: p, speed: {1, 2}, fill: Color::Red},
+                Ball {pos: Point {x: 0, y: 0}, speed: {a, a}, fill: Color::Blue}
+            };
+            p.x = 100;
+            for i in (0..<2) {
+                balls[i] = moved(moved(balls[i]));
+                setFigureColor(balls[i].fill);
+                circle(balls[i].pos.x, balls[i].pos.y, balls[i].speed[1]);
             }
-        "#;
-        let cmds = compile_ok(src);
-        let expected = vec![
-            // x+5 = 10, (int)4.5 -> 4, (int)(9.0) -> 9
-            "circle 10 5 10 fill=#fde25d stroke=#000000 width=1".to_string(),
-        ];
-        assert_eq!(cmds, expected);
-    }
+            circle(p.x, p.y, 1);
+        }
+    "#);
+    assert_eq!(code, 0);
+    assert_eq!(cmds, vec![
+        "circle 7 6 2 fill=#e92331ff stroke=#000000ff width=1",
+        "circle 10 10 5 fill=#2e73e6ff stroke=#000000ff width=1",
+        "circle 100 2 1 fill=#2e73e6ff stroke=#000000ff width=1",
+    ]);
 
-    #[test]
-    fn mixed_and_or_with_comparisons() {
-        // Test && vs || precedence: && binds tighter than ||
-        // Expr: (2 > 3) || (4 == 4 && 1 <= 2)  -> false || (true && true) -> true
-        let src = r#"
-            if ( 2 > 3 || 4 == 4 && 1 <= 2 ) {
-                setFigureColor(Color::Pink);
-                circle(1,1,1);
-            } else {
-                setFigureColor(Color::Red);
-                circle(2,2,2);
+    let blocks = printed_blocks("struct Tag {\n    string name;\n    array<int, 2> xs;\n}\n\nfunc main() {\n    println(Tag {name: 'a', xs: {1, 2}});\n}\n");
+    assert_eq!(blocks, vec!["Tag {name: 'a', xs: {1, 2}}\n"]);
+}
+
+#[test]
+fn struct_fields_are_type_checked() {
+    let program = |body: &str| format!("struct Point {{\n    int x;\n    int y;\n}}\n\nfunc main() {{\n{}\n}}\n", body);
+    assert!(Compiler::new().check(&program("Point p = Point {x: 1, y: 2};\np.x = p.y;")).is_ok());
+    let error = Compiler::new().check(&program("Point p = Point {x: 1, y: 2};\np.z = 3;")).unwrap_err();
+    assert!(error.message.contains("no field 'z'"), "{}", error.message);
+    assert!(Compiler::new().check(&program("Point p = Point {x: 1, y: 2};\np.x = 1.5;")).is_err());
+    assert!(Compiler::new().check(&program("Point p = Point {x: 1.5, y: 2};")).is_err());
+    assert!(Compiler::new().check(&program("Point p = Point {x: 1};")).is_err());
+    assert!(Compiler::new().check(&program("Point p = Point {x: 1, y: 2, x: 3};")).is_err());
+    assert!(Compiler::new().check(&program("const Point p = Point {x: 1, y: 2};\np.x = 3;")).is_err());
+    assert!(Compiler::new().check(&program("int n = 3;\nn.x = 3;")).is_err());
+    assert!(Compiler::new().check(&program("Circle c = 3;")).is_err());
+    assert!(Compiler::new().check(&program("Point p = Point {x: 1, y: 2};\nPoint q = p + p;")).is_err());
+    // A struct can't contain itself
+    assert!(Compiler::new().check("struct Node {\n    int value;\n    Node next;\n}\n\nfunc main() {}\n").is_err());
+}
+
+#[test]
+fn lists_grow_and_shrink() {
+    let (cmds, code) = run_both_backends(r#"
+        struct Bag {
+            list<int> xs;
+        }
+
+        func total(list<int> xs) -> int {
+            int sum = 0;
+            for i in (0..<len(xs)) {
+                sum = sum + xs[i];
             }
-        "#;
-        let cmds = compile_ok(src);
-        let expected = vec![
-            "circle 1 1 1 fill=#fb9ab5 stroke=#000000 width=1".to_string(), // pink
-        ];
-        assert_eq!(cmds, expected);
-    }
+            return sum;
+        }
 
-    #[test]
-    fn style_set_inside_if_persists_after_block() {
-        // Assuming style changes are global (not block-scoped).
-        let src = r#"
-            if (1 == 1) {
-                setFigureColor(Color::Red);
+        func main() {
+            list<int> xs = {};
+            for i in (1..4) {
+                push(xs, i * 10);
             }
-            rectangle(0,0, 10,10);
-        "#;
-        let cmds = compile_ok(src);
-        let expected = vec![
-            "rectangle 0 0 10 10 fill=#e92331 stroke=#000000 width=1".to_string(), // red persists
-        ];
-        assert_eq!(cmds, expected);
+            insert(xs, 0, 5);
+            insert(xs, len(xs), 50);
+            int last = pop(xs);
+            int first = remove(xs, 0);
+            xs[1] = 25;
+            Bag bag = Bag {xs: {1, 2}};
+            push(bag.xs, len('abc'));
+            list<list<int>> nested = {{1}, {7}};
+            push(nested[1], pop(bag.xs));
+            circle(last, first, len(xs));
+            circle(total(xs), total(bag.xs), nested[1][1]);
+        }
+    "#);
+    assert_eq!(code, 0);
+    assert_eq!(cmds, vec![
+        "circle 50 5 4 fill=#ffffffff stroke=#000000ff width=1",
+        "circle 105 3 3 fill=#ffffffff stroke=#000000ff width=1",
+    ]);
+
+    for tree_walker in [false, true] {
+        let error = run_error("list<int> xs = {1, 2};\nint x = remove(xs, 2);", tree_walker, |_| {});
+        assert_eq!(error.error_code, 4);
+        assert!(error.get_error_message().contains("Index out of bounds for list xs: 2"), "{}", error.get_error_message());
+        let error = run_error("list<int> xs = {};\nint x = pop(xs);", tree_walker, |_| {});
+        assert_eq!(error.error_code, 4);
+        let error = run_error("list<int> xs = {1};\nxs[1] = 2;", tree_walker, |_| {});
+        assert!(error.get_error_message().contains("Index out of bounds for array xs: 1"), "{}", error.get_error_message());
     }
+}
 
-    #[test]
-    fn scope_set_inside_if_persists_after_block() {
-        // Assuming style changes are global (not block-scoped).
-        let src = r#"
-            int x = 0;
-            if (1 == 1) {
-                x = 10;
-                setFigureColor(Color::Red);
+#[test]
+fn list_functions_are_type_checked() {
+    assert!(Compiler::new().check("list<int> xs = {1, 2};\npush(xs, 3);\nint n = len(xs);").is_ok());
+    assert!(Compiler::new().check("list<int> xs = {1, 2};\npush(xs, 1.5);").is_err());
+    assert!(Compiler::new().check("list<int> xs = {1.5};").is_err());
+    assert!(Compiler::new().check("list<int> xs = {};\nfloat x = pop(xs);").is_err());
+    assert!(Compiler::new().check("list<int> xs = {};\nint x = push(xs, 1);").is_err());
+    assert!(Compiler::new().check("list<int> xs = {};\nremove(xs, 1.5);").is_err());
+    assert!(Compiler::new().check("array<int, 2> xs = {1, 2};\npush(xs, 3);").is_err());
+    assert!(Compiler::new().check("const list<int> xs = {1};\npush(xs, 3);").is_err());
+    assert!(Compiler::new().check("push({1}, 3);").is_err());
+    assert!(Compiler::new().check("int n = len(3);").is_err());
+}
+
+#[test]
+fn for_in_goes_over_elements() {
+    let (cmds, code) = run_both_backends(r#"
+        func firstOdd(list<int> xs) -> int {
+            for x in xs {
+                if (x % 2 == 1) {
+                    return x;
+                }
             }
-            rectangle(0,0, x,x);
-        "#;
-        let cmds = compile_ok(src);
-        let expected = vec![
-            "rectangle 0 0 10 10 fill=#e92331 stroke=#000000 width=1".to_string(), // red persists
-        ];
-        assert_eq!(cmds, expected);
+            return -1;
+        }
+
+        func main() {
+            array<array<int, 3>, 2> grid = {{1, 2, 3}, {4, 5, 6}};
+            int sum = 0;
+            for row in grid {
+                for cell in row {
+                    if (cell == 2) {
+                        continue;
+                    }
+                    sum = sum + cell;
+                }
+            }
+            list<int> xs = {10, 20, 30};
+            for i, x in xs {
+                // The loop goes over the elements the list had at the start
+                push(xs, x);
+                if (i == 1) {
+                    break;
+                }
+                circle(i, x, 1);
+            }
+            list<int> empty = {};
+            for x in empty {
+                circle(x, x, x);
+            }
+            circle(sum, len(xs), firstOdd({2, 7, 9}));
+        }
+    "#);
+    assert_eq!(code, 0);
+    assert_eq!(cmds, vec![
+        "circle 0 10 1 fill=#ffffffff stroke=#000000ff width=1",
+        "circle 19 5 7 fill=#ffffffff stroke=#000000ff width=1",
+    ]);
+
+    assert!(Compiler::new().check("int n = 3;\nfor x in n {}").is_err());
+    assert!(Compiler::new().check("array<float, 2> xs = {1.0, 2.0};\nfor x in xs {\n    int y = x;\n}").is_err());
+    assert!(Compiler::new().check("array<float, 2> xs = {1.0, 2.0};\nfor i, x in xs {\n    float y = x * decimal(i);\n}").is_ok());
+}
+
+#[test]
+fn compound_assignments_update_in_place() {
+    let (cmds, code) = run_both_backends(r#"
+        int x = 10;
+        x -= 3;
+        x *= 2 + 1;
+        x /= 2;
+        x %= 7;
+        x++;
+        array<array<int, 2>, 2> grid = {{1, 2}, {3, 4}};
+        int i = 1;
+        grid[i][0] += x;
+        grid[0][i]--;
+        float f = 1.5;
+        f *= 2.0;
+        string s = 'a';
+        s += 'b';
+        circle(x, grid[1][0], grid[0][1]);
+        text(round(f), 0, s);
+    "#);
+    assert_eq!(code, 0);
+    assert_eq!(cmds, vec![
+        "circle 4 7 1 fill=#ffffffff stroke=#000000ff width=1",
+        "text 3 0 'ab' fill=#ffffffff size=20 font=sans-serif",
+    ]);
+
+    let error = Compiler::new().check("const int x = 1;\nx += 2;").unwrap_err();
+    assert!(error.message.contains("Const variable x"), "{}", error.message);
+    assert_eq!((error.start, error.finish), ((2, 1), (2, 8)));
+    assert!(Compiler::new().check("int x = 1;\nx += 2.5;").is_err());
+    assert!(Compiler::new().check("bool b = true;\nb++;").is_err());
+}
+
+#[test]
+fn remainder_by_zero_is_a_runtime_error() {
+    for src in ["int x = 7;\nint y = 0;\nx %= y;", "int x = 7 % (1 - 1);", "float f = 7.0 % 0.0;"] {
+        for tree_walker in [false, true] {
+            let error = run_error(src, tree_walker, |_| {});
+            assert_eq!(error.error_code, 4, "{}", src);
+            assert!(error.get_error_message().contains("Division by 0"), "{}", error.get_error_message());
+        }
     }
+}
 
-    #[test]
-    fn polygon_in_if_with_minimum_vertices_and_styles() {
-        let src = r#"
-            if ((3*3) == 9) {
-                setFigureColor(Color::Cyan);
-                setLineColor(Color::Black);
-                setLineWidth(3);
-                polygon(0,0, 10,0, 10,10);
+#[test]
+fn and_or_skip_the_right_side() {
+    // Every call draws its number, so the circles show what was evaluated and in which order
+    let (cmds, code) = run_both_backends(r#"
+        func check(int n, bool result) -> bool {
+            circle(n, 0, 1);
+            return result;
+        }
+
+        func main() {
+            list<int> xs = {1};
+            int i = 1;
+            if (i < len(xs) && xs[i] > 0) {
+                circle(0, 0, 0);
             }
-        "#;
-        let cmds = compile_ok(src);
-        let expected = vec![
-            "polygon 0 0 10 0 10 10 fill=#3ba8e7 stroke=#000000 width=3".to_string(),
-        ];
-        assert_eq!(cmds, expected);
+            if (i >= len(xs) || xs[i] > 0) {
+                circle(0, 1, 1);
+            }
+            bool a = check(1, false) && check(2, true);
+            bool b = check(3, true) || check(4, true);
+            bool c = check(5, true) && check(6, false);
+            bool d = check(7, false) || check(8, true);
+            bool e = check(9, false) && check(10, true) || check(11, true);
+            bool f = check(12, true) || check(13, true) && check(14, true);
+            array<bool, 6> results = {a, b, c, d, e, f};
+            for j, result in results {
+                if (result) {
+                    circle(j, 100, 1);
+                }
+            }
+        }
+    "#);
+    assert_eq!(code, 0);
+    let centers: Vec<String> = cmds.iter().map(|cmd| cmd.split(' ').skip(1).take(2).collect::<Vec<_>>().join(" ")).collect();
+    assert_eq!(centers, vec![
+        "0 1", "1 0", "3 0", "5 0", "6 0", "7 0", "8 0", "9 0", "11 0", "12 0",
+        "1 100", "3 100", "4 100", "5 100",
+    ]);
+}
+
+#[test]
+fn math_functions_use_degrees_like_arc() {
+    let (cmds, code) = run_both_backends(r#"
+        circle(round(100.0 * sin(30.0)), round(100.0 * cos(60.0)), round(10.0 * tan(45.0)));
+        circle(round(atan2(1.0, 1.0)), round(degrees(PI)), round(100.0 * radians(180.0)));
+        circle(round(pow(2.0, 10.0)), round(exp(0.0)), round(log(exp(2.0))));
+        circle(min(3, 7), max(3, 7), clamp(15, 0, 10));
+        float low = min(1.5, 0.5);
+        circle(round(10.0 * lerp(10.0, 20.0, 0.25)), round(map(5.0, 0.0, 10.0, 100.0, 200.0)), round(10.0 * low + clamp(-1.5, 0.0, 1.0)));
+    "#);
+    assert_eq!(code, 0);
+    let centers: Vec<String> = cmds.iter().map(|cmd| cmd.split(' ').skip(1).take(3).collect::<Vec<_>>().join(" ")).collect();
+    assert_eq!(centers, vec!["50 50 10", "45 180 314", "1024 1 2", "3 7 10", "125 150 5"]);
+
+    assert!(Compiler::new().check("int x = min(1, 2.0);").is_err());
+    assert!(Compiler::new().check("float x = max(1, 2);").is_err());
+    assert!(Compiler::new().check("int x = clamp(1, 2);").is_err());
+    assert!(Compiler::new().check("float PI = 3.0;").is_err());
+    for tree_walker in [false, true] {
+        assert_eq!(run_error("float x = log(0.0);", tree_walker, |_| {}).error_code, 4);
+        let error = run_error("int x = clamp(5, 10, 0);", tree_walker, |_| {});
+        assert!(error.get_error_message().contains("lower bound 10 is above upper bound 0"), "{}", error.get_error_message());
     }
+}
 
+#[test]
+fn builtins_come_from_one_table() {
+    let (cmds, code) = run_both_backends(r#"
+        float f = abs(-2.5);
+        circle(abs(-3), abs(4), round(10.0 * f));
+    "#);
+    assert_eq!(code, 0);
+    assert_eq!(cmds[0].split(' ').skip(1).take(3).collect::<Vec<_>>(), vec!["3", "4", "25"]);
+    assert!(Compiler::new().check("int x = abs(1.5);").is_err());
+    assert!(Compiler::new().check("func draw() { circle(0, 0, 1); } func main() { int x = draw(); }").is_err());
+    assert!(Compiler::new().check("int x = nothing(1);").is_err());
+
+    // Only the list functions, which change their variable, aren't called like the rest
+    for builtin in crate::execution::builtins::BUILTINS {
+        assert_eq!(builtin.run.is_none(), crate::execution::LIST_MUTATORS.contains(&builtin.name), "{}", builtin.name);
+    }
+    let json: serde_json::Value = serde_json::from_str(&crate::builtins_json()).unwrap();
+    let circle = json.as_array().unwrap().iter().find(|builtin| builtin["name"] == "circle").unwrap();
+    assert_eq!(circle["signature"], "circle(int x, int y, int radius)");
+    assert!(json.as_array().unwrap().iter().any(|builtin| builtin["signature"] == "round(float value) -> int"));
+}
+
+#[test]
+fn ints_widen_to_floats() {
+    let (cmds, code) = run_both_backends(r#"
+        func half(float x) -> float {
+            return x / 2;
+        }
+
+        func main() {
+            float a = half(5);
+            float b = sqrt(16) + 1;
+            float m = max(2, 3.5);
+            if (3 < 3.5 && 2 == 2.0) {
+                circle(round(10.0 * a), round(b), round(10.0 * m));
+            }
+            circle(int(2.7), int(-2.7) + 10, int(7));
+            circle(abs(-3), min(3, 4), clamp(20, 0, 10));
+        }
+    "#);
+    assert_eq!(code, 0);
+    let centers: Vec<String> = cmds.iter().map(|cmd| cmd.split(' ').skip(1).take(3).collect::<Vec<_>>().join(" ")).collect();
+    assert_eq!(centers, vec!["25 5 35", "2 8 7", "3 3 10"]);
 
-}
\ No newline at end of file
+    // Narrowing is never implicit
+    assert!(Compiler::new().check("int x = 1 + 2.5;").is_err());
+    assert!(Compiler::new().check("int x = max(1, 2.5);").is_err());
+    assert!(Compiler::new().check("circle(1.5, 2, 3);").is_err());
+    assert!(Compiler::new().check("int x = int(2.5);").is_ok());
+}
+
+#[test]
+fn indices_are_any_int_expression() {
+    let (cmds, code) = run_both_backends(r#"
+        array<array<int, 3>, 3> grid = {{0, 1, 2}, {3, 4, 5}, {6, 7, 8}};
+        list<int> xs = {10, 20, 30};
+        int i = 0;
+        grid[random(1, 1)][i + 1] = 40;
+        xs[len(xs) - 1] = grid[1][1] + 2;
+        xs[i + 1] += 5;
+        xs[len(xs) - 1]++;
+        circle(grid[abs(-1)][1], xs[len(xs) - 1], xs[(i + 4) % 3]);
+    "#);
+    assert_eq!(code, 0);
+    assert_eq!(cmds, vec!["circle 40 43 25 fill=#ffffffff stroke=#000000ff width=1"]);
+
+    let error = Compiler::new().check("array<int, 2> a = {1, 2};\nint x = a[1.0];").unwrap_err();
+    assert!(error.message.contains("Array index must be 'int'"), "{}", error.message);
+    assert!(Compiler::new().check("array<int, 2> a = {1, 2};\na[1 > 0] = 3;").is_err());
+}
+
+#[test]
+fn indices_are_evaluated_once() {
+    let src = r#"
+        global {
+            int calls = 0;
+        }
+
+        func next() -> int {
+            calls++;
+            println('call', calls);
+            return calls - 1;
+        }
+
+        func main() {
+            array<list<int>, 3> ls = {{}, {}, {}};
+            push(ls[next()], 7);
+            array<int, 4> a = {0, 0, 0, 0};
+            a[next()] = 5;
+            a[next()] += 4;
+            a[next()]++;
+            println(ls, a);
+        }
+    "#;
+    let (_, code) = run_both_backends(src);
+    assert_eq!(code, 0);
+    assert_eq!(printed_blocks(src).concat(), "call 1\ncall 2\ncall 3\ncall 4\n{{7}, {}, {}} {0, 5, 4, 1}\n");
+}
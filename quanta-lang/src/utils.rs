@@ -8,5 +8,10 @@
 //     #[cfg(feature = "console_error_panic_hook")]
 //     console_error_panic_hook::set_once();
 // }
+pub mod budget;
 pub mod canvas;
-pub mod message;
\ No newline at end of file
+pub mod command;
+pub mod debugger;
+pub mod inspect;
+pub mod message;
+pub mod timer;
\ No newline at end of file
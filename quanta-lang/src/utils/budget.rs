@@ -0,0 +1,70 @@
+use std::sync::{atomic::{AtomicU32, AtomicU64, Ordering}, Arc};
+
+use quanta_parser::{ast::Coords, error::Error};
+
+use crate::utils::canvas::Canvas;
+
+pub const DEFAULT_STEP_BUDGET: u32 = 10_000_000;
+pub const DEFAULT_COMMAND_BUDGET: u32 = 100_000;
+
+#[derive(Debug)]
+struct Limits {
+    steps: AtomicU32,
+    commands: AtomicU32,
+    spent: AtomicU64,
+}
+
+// How much a program may do before the host gets to draw. Both counters start over
+// on `frame()` and `sleep()`, a limit of 0 turns the check off.
+#[derive(Debug, Clone)]
+pub struct Budget {
+    limits: Arc<Limits>,
+}
+
+impl Default for Budget {
+    fn default() -> Budget {
+        Budget::new()
+    }
+}
+
+impl Budget {
+    pub fn new() -> Budget {
+        Budget { limits: Arc::new(Limits {
+            steps: AtomicU32::new(DEFAULT_STEP_BUDGET),
+            commands: AtomicU32::new(DEFAULT_COMMAND_BUDGET),
+            spent: AtomicU64::new(0),
+        }) }
+    }
+
+    pub fn set_step_limit(&self, steps: u32) {
+        self.limits.steps.store(steps, Ordering::Relaxed);
+    }
+
+    pub fn set_command_limit(&self, commands: u32) {
+        self.limits.commands.store(commands, Ordering::Relaxed);
+    }
+
+    pub fn spend(&self, steps: u64) {
+        self.limits.spent.fetch_add(steps, Ordering::Relaxed);
+    }
+
+    pub fn reset(&self) {
+        self.limits.spent.store(0, Ordering::Relaxed);
+    }
+
+    // Called by loops on every iteration, `coords` is the loop itself
+    pub fn check(&self, canvas: &Canvas, coords: Coords) -> Result<(), Error> {
+        let steps = self.limits.steps.load(Ordering::Relaxed) as u64;
+        let spent = self.limits.spent.load(Ordering::Relaxed);
+        if steps != 0 && spent > steps {
+            return Err(Error::limit(format!(
+                "This loop ran {} steps without showing anything, add frame() or sleep() inside it", steps), coords));
+        }
+        let commands = self.limits.commands.load(Ordering::Relaxed) as usize;
+        if commands != 0 && canvas.unshown_commands() > commands {
+            return Err(Error::limit(format!(
+                "This loop drew more than {} shapes without showing them, add frame() or sleep() inside it", commands), coords));
+        }
+        Ok(())
+    }
+}
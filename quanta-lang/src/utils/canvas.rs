@@ -1,30 +1,65 @@
+use std::sync::{atomic::{AtomicUsize, Ordering}, Arc};
+
 use wasm_bindgen::prelude::*;
 use crossbeam_channel::{Receiver, Sender};
 
+use crate::utils::command::DrawCommand;
+
+// Drawing and printing share one channel so printed text stays between the same frames
+#[derive(Debug, Clone, PartialEq)]
+pub enum Output {
+    Draw(DrawCommand),
+    Print(String),
+}
+
 #[wasm_bindgen]
 #[derive(Debug, Clone)]
 pub struct Canvas {
-    commands: Sender<String>,
+    commands: Sender<Output>,
+    // Commands sent since the host last got a chance to draw
+    unshown: Arc<AtomicUsize>,
 }
 
 #[derive(Clone)]
 pub struct CanvasReader {
-    commands: Receiver<String>
+    commands: Receiver<Output>
 }
 
 pub fn construct_canvas() -> (Canvas, CanvasReader) {
     let (tx, rx) = crossbeam_channel::unbounded();
-    (Canvas { commands: tx }, CanvasReader { commands: rx })
+    (Canvas { commands: tx, unshown: Arc::new(AtomicUsize::new(0)) }, CanvasReader { commands: rx })
 }
 
 impl Canvas {
-    pub fn add_command(&mut self, c : String) {
-        self.commands.send(c).expect("Compiler crashed, please try again!");
+    pub fn add_command(&mut self, c : DrawCommand) {
+        match c {
+            DrawCommand::Frame | DrawCommand::Sleep { .. } | DrawCommand::End => self.unshown.store(0, Ordering::Relaxed),
+            _ => { self.unshown.fetch_add(1, Ordering::Relaxed); },
+        }
+        self.send(Output::Draw(c));
+    }
+
+    pub fn print(&mut self, text: String) {
+        self.unshown.fetch_add(1, Ordering::Relaxed);
+        self.send(Output::Print(text));
+    }
+
+    fn send(&self, output: Output) {
+        self.commands.send(output).expect("Compiler crashed, please try again!");
+    }
+
+    pub fn unshown_commands(&self) -> usize {
+        self.unshown.load(Ordering::Relaxed)
+    }
+
+    // The host has shown everything drawn so far
+    pub fn mark_shown(&self) {
+        self.unshown.store(0, Ordering::Relaxed);
     }
 }
 
 impl CanvasReader {
-    pub fn get_commands(&mut self) -> Vec<String> {
+    pub fn get_output(&mut self) -> Vec<Output> {
         self.commands.try_iter().collect()
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,206 @@
+use std::fmt;
+
+use bincode::Options;
+use quanta_parser::error::Error;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+// Bumped whenever a command or a field changes shape, hosts reject streams they don't know.
+pub const PROTOCOL_VERSION: u32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Rgba {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Rgba {
+    pub const WHITE: Rgba = Rgba { r: 255, g: 255, b: 255, a: 255 };
+    pub const BLACK: Rgba = Rgba { r: 0, g: 0, b: 0, a: 255 };
+
+    pub fn new(r: u8, g: u8, b: u8, a: u8) -> Rgba {
+        Rgba { r, g, b, a }
+    }
+
+    pub fn from_hex(hex: &str) -> Option<Rgba> {
+        let digits = hex.strip_prefix('#')?;
+        if !digits.is_ascii() || (digits.len() != 6 && digits.len() != 8) {
+            return None;
+        }
+        let channel = |i: usize| u8::from_str_radix(&digits[i..i + 2], 16).ok();
+        let a = if digits.len() == 8 { channel(6)? } else { 255 };
+        Some(Rgba { r: channel(0)?, g: channel(2)?, b: channel(4)?, a })
+    }
+}
+
+impl fmt::Display for Rgba {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}{:02x}", self.r, self.g, self.b, self.a)
+    }
+}
+
+// `"#rrggbbaa"` in JSON, so the browser can hand it straight to the canvas; four raw bytes in binary.
+impl Serialize for Rgba {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&self.to_string())
+        } else {
+            [self.r, self.g, self.b, self.a].serialize(serializer)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Rgba {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Rgba, D::Error> {
+        if deserializer.is_human_readable() {
+            let hex = String::deserialize(deserializer)?;
+            Rgba::from_hex(&hex).ok_or_else(|| de::Error::custom(format!("invalid color '{}'", hex)))
+        } else {
+            let [r, g, b, a] = <[u8; 4]>::deserialize(deserializer)?;
+            Ok(Rgba { r, g, b, a })
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShapeStyle {
+    pub fill: Rgba,
+    pub stroke: Rgba,
+    pub width: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LineStyle {
+    pub stroke: Rgba,
+    pub width: i32,
+}
+
+// `font` is a CSS font family, hosts without fonts may ignore it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TextStyle {
+    pub fill: Rgba,
+    pub size: i32,
+    pub font: String,
+}
+
+// Everything a program can ask the canvas to do. Angles of `Arc` are in degrees, clockwise,
+// `Text` is placed by the top left corner of its first letter.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DrawCommand {
+    Circle { x: i32, y: i32, r: i32, style: ShapeStyle },
+    Line { x1: i32, y1: i32, x2: i32, y2: i32, style: LineStyle },
+    Rectangle { x1: i32, y1: i32, x2: i32, y2: i32, style: ShapeStyle },
+    Polygon { points: Vec<(i32, i32)>, style: ShapeStyle },
+    Arc { x: i32, y: i32, r: i32, start: i32, end: i32, style: ShapeStyle },
+    Text { x: i32, y: i32, text: String, style: TextStyle },
+    Sleep { ms: i32 },
+    Frame,
+    Animate,
+    Clear,
+    End,
+}
+
+// Text form used in logs and tests, e.g. `circle 1 2 3 fill=#ffffffff stroke=#000000ff width=1`
+impl fmt::Display for DrawCommand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let shape = |f: &mut fmt::Formatter, s: &ShapeStyle| write!(f, " fill={} stroke={} width={}", s.fill, s.stroke, s.width);
+        match self {
+            DrawCommand::Circle { x, y, r, style } => {
+                write!(f, "circle {} {} {}", x, y, r)?;
+                shape(f, style)
+            },
+            DrawCommand::Line { x1, y1, x2, y2, style } => {
+                write!(f, "line {} {} {} {} stroke={} width={}", x1, y1, x2, y2, style.stroke, style.width)
+            },
+            DrawCommand::Rectangle { x1, y1, x2, y2, style } => {
+                write!(f, "rectangle {} {} {} {}", x1, y1, x2, y2)?;
+                shape(f, style)
+            },
+            DrawCommand::Polygon { points, style } => {
+                write!(f, "polygon")?;
+                for (x, y) in points {
+                    write!(f, " {} {}", x, y)?;
+                }
+                shape(f, style)
+            },
+            DrawCommand::Arc { x, y, r, start, end, style } => {
+                write!(f, "arc {} {} {} {} {}", x, y, r, start, end)?;
+                shape(f, style)
+            },
+            DrawCommand::Text { x, y, text, style } => {
+                write!(f, "text {} {} '{}' fill={} size={} font={}", x, y, text.replace('\'', "''"), style.fill, style.size, style.font)
+            },
+            DrawCommand::Sleep { ms } => write!(f, "sleep {}", ms),
+            DrawCommand::Frame => write!(f, "frame"),
+            DrawCommand::Animate => write!(f, "animate"),
+            DrawCommand::Clear => write!(f, "clear"),
+            DrawCommand::End => write!(f, "end"),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct StreamRef<'a> {
+    version: u32,
+    commands: &'a [DrawCommand],
+}
+
+#[derive(Deserialize)]
+struct Stream {
+    commands: Vec<DrawCommand>,
+}
+
+#[derive(Deserialize)]
+struct StreamHeader {
+    version: u32,
+}
+
+fn protocol_error(message: String) -> Error {
+    Error::runtime(message, (0, 0, 0, 0))
+}
+
+fn check_version(version: u32) -> Result<(), Error> {
+    if version != PROTOCOL_VERSION {
+        return Err(protocol_error(format!("Unsupported command stream version {}, expected {}", version, PROTOCOL_VERSION)));
+    }
+    Ok(())
+}
+
+fn binary_options() -> impl Options {
+    bincode::DefaultOptions::new().with_varint_encoding().with_little_endian()
+}
+
+// `{"version":2,"commands":[{"circle":{"x":..}}, "frame", ...]}`
+pub fn to_json(commands: &[DrawCommand]) -> String {
+    serde_json::to_string(&StreamRef { version: PROTOCOL_VERSION, commands })
+        .expect("Draw commands are always representable as JSON")
+}
+
+pub fn from_json(json: &str) -> Result<Vec<DrawCommand>, Error> {
+    let header: StreamHeader = serde_json::from_str(json)
+        .map_err(|e| protocol_error(format!("Malformed command stream: {}", e)))?;
+    check_version(header.version)?;
+    let stream: Stream = serde_json::from_str(json)
+        .map_err(|e| protocol_error(format!("Malformed command stream: {}", e)))?;
+    Ok(stream.commands)
+}
+
+// Version varint followed by the varint-encoded command list.
+pub fn to_binary(commands: &[DrawCommand]) -> Vec<u8> {
+    binary_options()
+        .serialize(&StreamRef { version: PROTOCOL_VERSION, commands })
+        .expect("Draw commands are always representable in binary")
+}
+
+pub fn from_binary(bytes: &[u8]) -> Result<Vec<DrawCommand>, Error> {
+    let mut reader = bytes;
+    let version: u32 = binary_options()
+        .deserialize_from(&mut reader)
+        .map_err(|e| protocol_error(format!("Malformed command stream: {}", e)))?;
+    check_version(version)?;
+    binary_options()
+        .deserialize(reader)
+        .map_err(|e| protocol_error(format!("Malformed command stream: {}", e)))
+}
@@ -0,0 +1,151 @@
+use std::{collections::HashSet, future::Future, pin::Pin, sync::{Arc, Mutex}, task::{Context, Poll, Waker}};
+
+use quanta_parser::ast::Coords;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum StepMode {
+    Run,
+    // Stop at the very next statement
+    Into,
+    // Stop at the next statement that isn't deeper in the call stack than `depth`
+    Over(usize),
+    // Stop at the next statement of the caller
+    Out(usize),
+}
+
+#[derive(Debug)]
+struct DebugState {
+    // Programs only stop once a debugger call was made, until then statements aren't checked at all
+    attached: bool,
+    breakpoints: HashSet<usize>,
+    mode: StepMode,
+    paused_at: Option<(Coords, usize)>,
+    waker: Option<Waker>,
+}
+
+// Shared between the host and every execution of a program. Both backends ask it
+// before each statement whether it should stop, and wait there until the host resumes.
+#[derive(Debug, Clone)]
+pub struct Debugger {
+    state: Arc<Mutex<DebugState>>,
+}
+
+impl Default for Debugger {
+    fn default() -> Debugger {
+        Debugger::new()
+    }
+}
+
+impl Debugger {
+    pub fn new() -> Debugger {
+        Debugger { state: Arc::new(Mutex::new(DebugState {
+            attached: false,
+            breakpoints: HashSet::new(),
+            mode: StepMode::Run,
+            paused_at: None,
+            waker: None,
+        })) }
+    }
+
+    pub fn set_breakpoint(&self, line: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.attached = true;
+        state.breakpoints.insert(line);
+    }
+
+    pub fn remove_breakpoint(&self, line: usize) {
+        self.state.lock().unwrap().breakpoints.remove(&line);
+    }
+
+    pub fn clear_breakpoints(&self) {
+        self.state.lock().unwrap().breakpoints.clear();
+    }
+
+    pub fn pause(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.attached = true;
+        if state.paused_at.is_none() {
+            state.mode = StepMode::Into;
+        }
+    }
+
+    pub fn resume(&self) {
+        self.release(StepMode::Run);
+    }
+
+    pub fn step_into(&self) {
+        self.release(StepMode::Into);
+    }
+
+    // Before the program stops for the first time, stepping over or out of anything means the first statement
+    pub fn step_over(&self) {
+        let depth = self.state.lock().unwrap().paused_at.map(|(_, depth)| depth);
+        self.release(depth.map_or(StepMode::Into, StepMode::Over));
+    }
+
+    pub fn step_out(&self) {
+        let depth = self.state.lock().unwrap().paused_at.map(|(_, depth)| depth);
+        self.release(depth.map_or(StepMode::Into, StepMode::Out));
+    }
+
+    pub fn paused_at(&self) -> Option<Coords> {
+        self.state.lock().unwrap().paused_at.map(|(coords, _)| coords)
+    }
+
+    fn release(&self, mode: StepMode) {
+        let mut state = self.state.lock().unwrap();
+        state.attached = true;
+        state.mode = mode;
+        state.paused_at = None;
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+
+    // Called with the statement about to run and the number of user functions on the call stack
+    pub async fn before_statement(&self, coords: Coords, depth: usize) {
+        if self.stop_at(coords, depth) {
+            self.resumed().await;
+        }
+    }
+
+    // Marks the program as paused if it should stop at this statement
+    pub fn stop_at(&self, coords: Coords, depth: usize) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if !state.attached {
+            return false;
+        }
+        let stop = state.breakpoints.contains(&coords.0) || match state.mode {
+            StepMode::Run => false,
+            StepMode::Into => true,
+            StepMode::Over(d) => depth <= d,
+            StepMode::Out(d) => depth < d,
+        };
+        if stop {
+            state.mode = StepMode::Run;
+            state.paused_at = Some((coords, depth));
+        }
+        stop
+    }
+
+    pub async fn resumed(&self) {
+        Resumed { state: Arc::clone(&self.state) }.await
+    }
+}
+
+struct Resumed {
+    state: Arc<Mutex<DebugState>>,
+}
+
+impl Future for Resumed {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = self.state.lock().unwrap();
+        if state.paused_at.is_none() {
+            return Poll::Ready(());
+        }
+        state.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
@@ -0,0 +1,148 @@
+use std::{collections::{BTreeMap, HashMap}, fmt, sync::{Arc, Mutex}};
+
+use quanta_parser::ast::{BaseValue, BaseValueType, Coords};
+use serde::Serialize;
+
+use crate::{execution::{CallFrame, Scope}, utils::command::Rgba};
+
+// `{"type":"int","value":3}`, arrays hold nested values of the same shape
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum Value {
+    Int(i32),
+    Float(f32),
+    Bool(bool),
+    Color(Rgba),
+    String(String),
+    Array(Vec<Value>),
+    Struct { name: String, fields: Vec<(String, Value)> },
+}
+
+impl Value {
+    // Only evaluated values have a type, variables never hold anything else
+    pub fn from_base(value: &BaseValue) -> Option<Value> {
+        match &value.val {
+            BaseValueType::Int(i) => Some(Value::Int(*i)),
+            BaseValueType::Float(f) => Some(Value::Float(*f)),
+            BaseValueType::Bool(b) => Some(Value::Bool(*b)),
+            BaseValueType::Color(r, g, b, a) => Some(Value::Color(Rgba::new(*r, *g, *b, *a))),
+            BaseValueType::StringVal(s) => Some(Value::String(s.clone())),
+            BaseValueType::Array(elems) => elems.iter().map(Value::from_base).collect::<Option<_>>().map(Value::Array),
+            BaseValueType::Struct(name, fields) => fields.iter()
+                .map(|(field, value)| Some((field.clone(), Value::from_base(value)?)))
+                .collect::<Option<_>>()
+                .map(|fields| Value::Struct { name: name.clone(), fields }),
+            _ => None,
+        }
+    }
+
+    // What print() shows: strings without quotes and colors the way rgb() takes them
+    pub fn printed(&self) -> String {
+        match self {
+            Value::String(s) => s.clone(),
+            Value::Color(c) if c.a == 255 => format!("rgb({}, {}, {})", c.r, c.g, c.b),
+            Value::Array(elems) => {
+                let elems: Vec<String> = elems.iter().map(Value::printed_inside).collect();
+                format!("{{{}}}", elems.join(", "))
+            },
+            Value::Struct { name, fields } => {
+                let fields: Vec<String> = fields.iter().map(|(field, value)| format!("{}: {}", field, value.printed_inside())).collect();
+                format!("{} {{{}}}", name, fields.join(", "))
+            },
+            _ => self.to_string(),
+        }
+    }
+
+    // Strings keep their quotes inside arrays and structs
+    fn printed_inside(&self) -> String {
+        match self {
+            Value::String(_) => self.to_string(),
+            _ => self.printed(),
+        }
+    }
+}
+
+// Source syntax where there is one, floats always show their decimal point
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Int(i) => write!(f, "{}", i),
+            Value::Float(x) if x.is_finite() && x.fract() == 0.0 => write!(f, "{:.1}", x),
+            Value::Float(x) => write!(f, "{}", x),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Color(c) => write!(f, "{}", c),
+            Value::String(s) => write!(f, "'{}'", s.replace('\'', "''")),
+            Value::Array(elems) => {
+                write!(f, "{{")?;
+                for (i, elem) in elems.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", elem)?;
+                }
+                write!(f, "}}")
+            },
+            Value::Struct { name, fields } => {
+                write!(f, "{} {{", name)?;
+                for (i, (field, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", field, value)?;
+                }
+                write!(f, "}}")
+            },
+        }
+    }
+}
+
+pub type Variables = BTreeMap<String, Value>;
+
+fn variables(vars: &HashMap<String, BaseValue>) -> Variables {
+    vars.iter()
+        .filter_map(|(name, value)| Some((name.clone(), Value::from_base(value)?)))
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FrameSnapshot {
+    pub function: String,
+    // Where the function was called from, the header of `main` for the outermost call
+    pub start_row: usize,
+    pub start_column: usize,
+    pub end_row: usize,
+    pub end_column: usize,
+    // Innermost scope first
+    pub scopes: Vec<Variables>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Snapshot {
+    // Outermost call first, the last frame is the one currently running
+    pub calls: Vec<FrameSnapshot>,
+    pub globals: Variables,
+}
+
+fn frame_snapshot(function: &str, coords: Coords, scope: &Arc<Mutex<Scope>>) -> FrameSnapshot {
+    let mut scopes = vec![];
+    let mut next = Some(Arc::clone(scope));
+    while let Some(scope) = next {
+        let scope = scope.lock().unwrap();
+        scopes.push(variables(&scope.variables));
+        next = scope.outer_scope.clone();
+    }
+    FrameSnapshot { function: function.to_string(), start_row: coords.0, start_column: coords.1, end_row: coords.2, end_column: coords.3, scopes }
+}
+
+impl Snapshot {
+    pub fn capture(calls: &[CallFrame], globals: &HashMap<String, BaseValue>) -> Snapshot {
+        Snapshot {
+            calls: calls.iter().map(|frame| frame_snapshot(&frame.function, frame.coords, &frame.scope)).collect(),
+            globals: variables(globals),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Snapshots are always representable as JSON")
+    }
+}
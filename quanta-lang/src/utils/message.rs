@@ -1,8 +1,9 @@
 use std::fmt;
 
 use wasm_bindgen::prelude::*;
-use quanta_parser::error::{Error, ErrorType};
+use quanta_parser::{ast::Coords, error::{Error, ErrorType}};
 use crate::runtime::Runtime;
+use crate::utils::command::{self, DrawCommand};
 //use crate::linear_runtime;
 
 #[derive(Clone, Debug, Copy)]
@@ -17,14 +18,25 @@ enum CommandStatus {
 #[derive(Clone, Debug)]
 pub struct CommandBlock{
     status: CommandStatus,
-    commands: Vec<String>,
+    commands: Vec<DrawCommand>,
+    // Text printed while the commands were drawn, shown together with them
+    output: String,
     pub sleep_for: i32
 }
 
 #[wasm_bindgen]
 impl CommandBlock {
-    pub fn get_commands(&self) -> Vec<String> {
-        self.commands.clone()
+    // `{"version":1,"commands":[...]}`, see utils::command for the format
+    pub fn get_commands_json(&self) -> String {
+        command::to_json(&self.commands)
+    }
+
+    pub fn get_commands_binary(&self) -> Vec<u8> {
+        command::to_binary(&self.commands)
+    }
+
+    pub fn get_output(&self) -> String {
+        self.output.clone()
     }
 
     pub fn get_status(&self) -> u8 {
@@ -44,12 +56,23 @@ impl CommandBlock {
 
 impl CommandBlock {
     pub fn new() -> CommandBlock {
-        CommandBlock { commands: vec![], sleep_for: 0, status: CommandStatus::OkNoDraw }
+        CommandBlock { commands: vec![], output: String::new(), sleep_for: 0, status: CommandStatus::OkNoDraw }
+    }
+
+    pub fn commands(&self) -> &[DrawCommand] {
+        &self.commands
     }
 
-    pub fn push(&mut self, command: String) -> CommandBlock {
+    pub fn output(&self) -> &str {
+        &self.output
+    }
+
+    pub fn push(&mut self, command: DrawCommand) {
         self.commands.push(command);
-        self.clone()
+    }
+
+    pub fn print(&mut self, text: &str) {
+        self.output.push_str(text);
     }
 }
 
@@ -83,6 +106,7 @@ impl RuntimeError {
                 ErrorType::LogicError=> {2},
                 ErrorType::TypeError=> {3},
                 ErrorType::RuntimeError=> {4},
+                ErrorType::LimitError=> {5},
             }, 
             error_message: error.message.to_string(), 
             start_row: error.start.0,
@@ -94,6 +118,23 @@ impl RuntimeError {
 }
 
 
+// Source range of the statement a paused program is about to run
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[wasm_bindgen]
+pub struct StatementSpan {
+    pub start_row: usize,
+    pub start_column: usize,
+    pub end_row: usize,
+    pub end_column: usize
+}
+
+impl StatementSpan {
+    pub fn new(coords: Coords) -> StatementSpan {
+        StatementSpan { start_row: coords.0, start_column: coords.1, end_row: coords.2, end_column: coords.3 }
+    }
+}
+
+
 #[wasm_bindgen]
 pub struct CompilationMessage {
     pub error_code: u32,
@@ -142,6 +183,7 @@ impl CompilationMessage {
             ErrorType::LogicError=> {2},
             ErrorType::TypeError=> {3},
             ErrorType::RuntimeError=> {4},
+            ErrorType::LimitError=> {5},
         };
         CompilationMessage {
             error_code: er_code,
@@ -200,8 +242,8 @@ impl CompilationMessage {
 
 impl fmt::Display for CompilationMessage {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}\n", self.error_code as usize)?;
-        write!(f, "{}\n", self.get_error_message())?;
+        writeln!(f, "{}", self.error_code as usize)?;
+        writeln!(f, "{}", self.get_error_message())?;
         Ok(())
     }
 }
\ No newline at end of file
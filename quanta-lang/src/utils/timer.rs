@@ -0,0 +1,87 @@
+use std::{fmt::Debug, future::Future, pin::Pin, sync::Arc, task::{Context, Poll}};
+
+// Execution awaits a timer tick before every block of commands, so the host
+// gets a chance to draw and handle input while a program is running.
+pub trait Timer: Debug {
+    fn tick(&self) -> Pin<Box<dyn Future<Output = ()>>>;
+}
+
+// Waits for a 1ms `setTimeout`, letting the browser repaint between blocks.
+#[cfg(target_arch = "wasm32")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BrowserTimer;
+
+#[cfg(target_arch = "wasm32")]
+impl Timer for BrowserTimer {
+    fn tick(&self) -> Pin<Box<dyn Future<Output = ()>>> {
+        Box::pin(gloo_timers::future::TimeoutFuture::new(1))
+    }
+}
+
+// Yields once to the executor without sleeping, used on native hosts.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct YieldTimer;
+
+impl Timer for YieldTimer {
+    fn tick(&self) -> Pin<Box<dyn Future<Output = ()>>> {
+        Box::pin(YieldNow { yielded: false })
+    }
+}
+
+struct YieldNow {
+    yielded: bool,
+}
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.yielded {
+            return Poll::Ready(());
+        }
+        self.yielded = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+pub fn default_timer() -> Arc<dyn Timer> {
+    #[cfg(target_arch = "wasm32")]
+    {
+        Arc::new(BrowserTimer)
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        Arc::new(YieldTimer)
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+thread_local! {
+    static POOL: std::cell::RefCell<futures::executor::LocalPool> = std::cell::RefCell::new(futures::executor::LocalPool::new());
+}
+
+// Runs a program future: in the browser it is queued with `spawn_local`,
+// on native hosts it runs on the current thread until it finishes or pauses in the debugger.
+pub fn spawn<F>(future: F)
+    where F: Future<Output = ()> + 'static
+{
+    #[cfg(target_arch = "wasm32")]
+    wasm_bindgen_futures::spawn_local(future);
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        use futures::task::LocalSpawnExt;
+        POOL.with(|pool| pool.borrow().spawner().spawn_local(future).expect("Couldn't start the program"));
+        run_pending();
+    }
+}
+
+// Lets paused programs continue after the host resumed them, a no-op in the browser.
+pub fn run_pending() {
+    #[cfg(not(target_arch = "wasm32"))]
+    POOL.with(|pool| {
+        if let Ok(mut pool) = pool.try_borrow_mut() {
+            pool.run_until_stalled();
+        }
+    });
+}
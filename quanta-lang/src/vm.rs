@@ -0,0 +1,336 @@
+use std::{collections::HashMap, sync::{Arc, Mutex}};
+
+use quanta_parser::{ast::{BaseValue, BaseValueType, Coords}, error::Error};
+
+use crate::{bytecode::{Bytecode, Instr, PathOp, Variable}, execution::{apply_binary, apply_unary, change_list, read_path, update_array, update_path, widen, CallFrame, Execution, ForRange, PathStep, Scope}};
+
+// How many instructions run between two timer ticks. The tree-walker ticks on every
+// block, which caps tight loops at one iteration per browser timeout.
+const TICK_INTERVAL: usize = 4096;
+
+struct Frame {
+    chunk: usize,
+    pc: usize,
+    base: usize,
+    wants_value: bool,
+    // Where the call was made, like `CallFrame::coords`
+    coords: Coords,
+}
+
+// Runs lowered programs. Builtins, operators and globals are shared with `Execution`,
+// so both produce the same canvas commands.
+pub struct Vm {
+    bytecode: Arc<Bytecode>,
+    exec: Execution,
+    stack: Vec<BaseValue>,
+    locals: Vec<BaseValue>,
+    frames: Vec<Frame>,
+}
+
+fn placeholder() -> BaseValue {
+    BaseValue { val: BaseValueType::Int(0), coords: (0, 0, 0, 0) }
+}
+
+fn integer_indices(name: &str, indices: Vec<BaseValue>, coords: Coords) -> Result<Vec<i32>, Error> {
+    let mut result = vec![];
+    for index in indices {
+        match index.val {
+            BaseValueType::Int(i) => {
+                if i < 0 {
+                    return Err(Error::runtime(format!("Negative index for array {}: {}", name, i), coords));
+                }
+                result.push(i);
+            },
+            _ => return Err(Error::runtime(String::from("Array indices must be integers"), coords)),
+        }
+    }
+    Ok(result)
+}
+
+impl Vm {
+    pub fn new(bytecode: Arc<Bytecode>, exec: Execution) -> Vm {
+        Vm { bytecode, exec, stack: vec![], locals: vec![], frames: vec![] }
+    }
+
+    pub async fn run(&mut self) -> Result<(), Error> {
+        let bytecode = Arc::clone(&self.bytecode);
+        let entry = &bytecode.chunks[bytecode.entry];
+        self.locals.resize(entry.slots, placeholder());
+        self.frames.push(Frame { chunk: bytecode.entry, pc: 0, base: 0, wants_value: false, coords: bytecode.header });
+
+        let mut since_tick = 0;
+        loop {
+            if since_tick == TICK_INTERVAL {
+                self.exec.timer.tick().await;
+                since_tick = 0;
+            }
+            since_tick += 1;
+
+            let frame = self.frames.last_mut().unwrap();
+            let chunk = &bytecode.chunks[frame.chunk];
+            let (instr, coords) = (&chunk.code[frame.pc], chunk.coords[frame.pc]);
+            frame.pc += 1;
+            let base = frame.base;
+
+            match instr {
+                Instr::Const(index) => self.stack.push(bytecode.constants[*index].clone()),
+                Instr::Load(var) => {
+                    let value = self.load(*var, base, coords)?;
+                    self.stack.push(value);
+                },
+                Instr::Store(var) => {
+                    let value = self.pop();
+                    self.store(*var, base, value, coords)?;
+                },
+                Instr::LoadIndexed { var, name, count } => {
+                    let name = &bytecode.names[*name];
+                    let indices = integer_indices(name, self.pop_many(*count), coords)?;
+                    let value = self.with_slot(*var, base, coords, |value| {
+                        let mut value = &*value;
+                        for index in indices {
+                            value = match &value.val {
+                                BaseValueType::Array(elems) => elems.get(index as usize)
+                                    .ok_or_else(|| Error::runtime(format!("Index out of bounds for array {}: {}", name, index), coords))?,
+                                _ => return Err(Error::runtime(format!("Variable {} is not an array", name), coords)),
+                            };
+                        }
+                        Ok(value.clone())
+                    })?;
+                    self.stack.push(value);
+                },
+                Instr::StoreIndexed { var, name, count } => {
+                    let name = &bytecode.names[*name];
+                    let indices = self.pop_many(*count);
+                    let value = self.pop();
+                    let indices = integer_indices(name, indices, coords)?;
+                    self.with_slot(*var, base, coords, |array| update_array(name.clone(), array, indices, value))?;
+                },
+                Instr::LoadPath { var, name, path } => {
+                    let name = &bytecode.names[*name];
+                    let steps = self.path_steps(&bytecode, name, path, coords)?;
+                    let value = self.with_slot(*var, base, coords, |root| read_path(name, root, &steps))?;
+                    self.stack.push(value);
+                },
+                Instr::StorePath { var, name, path } => {
+                    let name = &bytecode.names[*name];
+                    let steps = self.path_steps(&bytecode, name, path, coords)?;
+                    let value = self.pop();
+                    self.with_slot(*var, base, coords, |root| update_path(name.clone(), root, &steps, value))?;
+                },
+                Instr::MakeStruct { name, fields } => {
+                    let values = self.pop_many(fields.len());
+                    let fields = fields.iter().map(|field| bytecode.names[*field].clone()).zip(values).collect();
+                    self.stack.push(BaseValue { val: BaseValueType::Struct(bytecode.names[*name].clone(), fields), coords });
+                },
+                Instr::MakeArray(count) => {
+                    let elems = self.pop_many(*count);
+                    self.stack.push(BaseValue { val: BaseValueType::Array(elems), coords });
+                },
+                Instr::Unary(op) => {
+                    let value = self.pop();
+                    self.stack.push(apply_unary(*op, value)?);
+                },
+                Instr::Binary(op) => {
+                    let right = self.pop();
+                    let left = self.pop();
+                    self.stack.push(apply_binary(*op, &left, &right, coords)?);
+                },
+                Instr::Jump(target) => self.frames.last_mut().unwrap().pc = *target,
+                Instr::JumpIfFalse(target) => {
+                    match self.pop().val {
+                        BaseValueType::Bool(true) => {},
+                        BaseValueType::Bool(false) => self.frames.last_mut().unwrap().pc = *target,
+                        v => return Err(Error::runtime(format!("Expected bool value but got: {:?}", v), coords)),
+                    }
+                },
+                Instr::ForPrep { slots, stepped, exclusive, exit } => {
+                    let step = if *stepped { Some(&self.locals[base + slots.step].val) } else { None };
+                    let range = ForRange::new(&self.locals[base + slots.from].val, &self.locals[base + slots.count].val, step, *exclusive, coords)?;
+                    if range.count == 0 {
+                        self.frames.last_mut().unwrap().pc = *exit;
+                    } else {
+                        self.locals[base + slots.var] = BaseValue { val: range.value(0), coords };
+                        self.locals[base + slots.step] = BaseValue { val: range.step, coords };
+                        self.locals[base + slots.count] = BaseValue { val: BaseValueType::Int(range.count), coords };
+                        self.locals[base + slots.index] = BaseValue { val: BaseValueType::Int(0), coords };
+                    }
+                },
+                Instr::ForNext { slots, body } => {
+                    if let (BaseValueType::Int(index), BaseValueType::Int(count)) = (&self.locals[base + slots.index].val, &self.locals[base + slots.count].val) {
+                        let (index, count) = (index + 1, *count);
+                        if index < count {
+                            let range = ForRange { from: self.locals[base + slots.from].val.clone(), step: self.locals[base + slots.step].val.clone(), count };
+                            self.locals[base + slots.var].val = range.value(index);
+                            self.locals[base + slots.index].val = BaseValueType::Int(index);
+                            self.frames.last_mut().unwrap().pc = *body;
+                        }
+                    }
+                },
+                Instr::ForEachPrep { slots, exit } => {
+                    let first = match &self.locals[base + slots.list].val {
+                        BaseValueType::Array(elems) => elems.first().cloned(),
+                        _ => return Err(Error::runtime(String::from("For loop can only go over an array or a list"), coords)),
+                    };
+                    match first {
+                        None => self.frames.last_mut().unwrap().pc = *exit,
+                        Some(first) => {
+                            self.locals[base + slots.var] = first;
+                            self.locals[base + slots.index] = BaseValue { val: BaseValueType::Int(0), coords };
+                            if let Some(position) = slots.position {
+                                self.locals[base + position] = BaseValue { val: BaseValueType::Int(0), coords };
+                            }
+                        },
+                    }
+                },
+                Instr::ForEachNext { slots, body } => {
+                    if let (BaseValueType::Int(index), BaseValueType::Array(elems)) = (&self.locals[base + slots.index].val, &self.locals[base + slots.list].val) {
+                        let index = index + 1;
+                        if let Some(elem) = elems.get(index as usize).cloned() {
+                            self.locals[base + slots.var] = elem;
+                            self.locals[base + slots.index].val = BaseValueType::Int(index);
+                            if let Some(position) = slots.position {
+                                self.locals[base + position].val = BaseValueType::Int(index);
+                            }
+                            self.frames.last_mut().unwrap().pc = *body;
+                        }
+                    }
+                },
+                Instr::ToFloat => {
+                    let value = self.pop();
+                    self.stack.push(widen(value));
+                },
+                Instr::Call { function, argc, wants_value } => {
+                    let callee = &bytecode.chunks[*function];
+                    let args = self.pop_many(*argc);
+                    let base = self.locals.len();
+                    self.locals.extend(args);
+                    self.locals.resize(base + callee.slots.max(callee.params), placeholder());
+                    self.frames.push(Frame { chunk: *function, pc: 0, base, wants_value: *wants_value, coords });
+                },
+                Instr::CallBuiltin { name, argc, wants_value } => {
+                    let name = &bytecode.names[*name];
+                    let args = self.pop_many(*argc);
+                    match (self.exec.call_builtin(name, args, coords)?, wants_value) {
+                        (Some(value), true) => self.stack.push(value),
+                        (None, true) => return Err(Error::runtime(format!("Function {} didn't return a value", name), coords)),
+                        (_, false) => {},
+                    }
+                },
+                Instr::ChangeList { name, list, argc, wants_value } => {
+                    let name = &bytecode.names[*name];
+                    let args = self.pop_many(*argc);
+                    let target = self.pop();
+                    let (target, taken) = change_list(name, &bytecode.names[*list], target, args, coords)?;
+                    match (taken, wants_value) {
+                        (Some(value), true) => self.stack.push(value),
+                        (None, true) => return Err(Error::runtime(format!("Function {} didn't return a value", name), coords)),
+                        (_, false) => {},
+                    }
+                    self.stack.push(target);
+                },
+                Instr::Statement => {
+                    self.exec.budget.spend(1);
+                    // The host can only look at the calls while the program is paused
+                    if self.exec.debugger.stop_at(coords, self.frames.len() - 1) {
+                        *self.exec.calls.lock().unwrap() = self.calls(&bytecode);
+                        self.exec.debugger.resumed().await;
+                        self.exec.calls.lock().unwrap().clear();
+                    }
+                },
+                Instr::Iteration => {
+                    self.exec.budget.spend(1);
+                    self.exec.budget.check(&self.exec.canvas, coords)?;
+                },
+                Instr::Return | Instr::ReturnNone => {
+                    let value = if *instr == Instr::Return { Some(self.pop()) } else { None };
+                    let frame = self.frames.pop().unwrap();
+                    if self.frames.is_empty() {
+                        return Ok(());
+                    }
+                    self.locals.truncate(frame.base);
+                    match (value, frame.wants_value) {
+                        (Some(value), true) => self.stack.push(value),
+                        (None, true) => {
+                            let name = &bytecode.chunks[frame.chunk].name;
+                            return Err(Error::runtime(format!("Function {} didn't return a value", name), coords));
+                        },
+                        (_, false) => {},
+                    }
+                },
+            }
+        }
+    }
+
+    // The frames as the tree-walker keeps them, with a scope for every level of blocks up to the innermost live local
+    fn calls(&self, bytecode: &Bytecode) -> Vec<CallFrame> {
+        self.frames.iter().map(|frame| {
+            let chunk = &bytecode.chunks[frame.chunk];
+            let live: Vec<_> = chunk.locals.iter().filter(|local| local.live.contains(&frame.pc)).collect();
+            let mut scope = None;
+            for depth in 0..=live.iter().map(|local| local.depth).max().unwrap_or(0) {
+                let variables: HashMap<_, _> = live.iter()
+                    .filter(|local| local.depth == depth)
+                    .map(|local| (local.name.clone(), self.locals[frame.base + local.slot].clone()))
+                    .collect();
+                scope = Some(Arc::new(Mutex::new(Scope { variables, outer_scope: scope })));
+            }
+            CallFrame { function: chunk.name.clone(), coords: frame.coords, scope: scope.unwrap() }
+        }).collect()
+    }
+
+    fn pop(&mut self) -> BaseValue {
+        self.stack.pop().expect("Bytecode stack underflow")
+    }
+
+    fn pop_many(&mut self, count: usize) -> Vec<BaseValue> {
+        self.stack.split_off(self.stack.len() - count)
+    }
+
+    fn path_steps(&mut self, bytecode: &Bytecode, name: &str, path: &[PathOp], coords: Coords) -> Result<Vec<PathStep>, Error> {
+        let count = path.iter().filter(|op| **op == PathOp::Index).count();
+        let mut indices = integer_indices(name, self.pop_many(count), coords)?.into_iter();
+        Ok(path.iter().map(|op| match op {
+            PathOp::Index => PathStep::Index(indices.next().unwrap()),
+            PathOp::Field(field) => PathStep::Field(bytecode.names[*field].clone()),
+        }).collect())
+    }
+
+    fn load(&self, var: Variable, base: usize, coords: Coords) -> Result<BaseValue, Error> {
+        match var {
+            Variable::Local(slot) => Ok(self.locals[base + slot].clone()),
+            Variable::Global(name) => {
+                let name = &self.bytecode.names[name];
+                self.exec.global_vars.lock().unwrap().get(name).cloned()
+                    .ok_or_else(|| Error::runtime(format!("Unknown variable: {}", name), coords))
+            },
+        }
+    }
+
+    // Runs `f` on the variable where it lives, so indexing into it copies only the element
+    fn with_slot<R>(&mut self, var: Variable, base: usize, coords: Coords, f: impl FnOnce(&mut BaseValue) -> Result<R, Error>) -> Result<R, Error> {
+        match var {
+            Variable::Local(slot) => f(&mut self.locals[base + slot]),
+            Variable::Global(name) => {
+                let name = &self.bytecode.names[name];
+                match self.exec.global_vars.lock().unwrap().get_mut(name) {
+                    Some(value) => f(value),
+                    None => Err(Error::runtime(format!("Unknown variable: {}", name), coords)),
+                }
+            },
+        }
+    }
+
+    fn store(&mut self, var: Variable, base: usize, value: BaseValue, coords: Coords) -> Result<(), Error> {
+        match var {
+            Variable::Local(slot) => self.locals[base + slot] = value,
+            Variable::Global(name) => {
+                let name = &self.bytecode.names[name];
+                match self.exec.global_vars.lock().unwrap().get_mut(name) {
+                    Some(slot) => *slot = value,
+                    None => return Err(Error::runtime(format!("Unknown variable: {}", name), coords)),
+                }
+            },
+        }
+        Ok(())
+    }
+}
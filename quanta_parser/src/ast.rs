@@ -11,24 +11,48 @@ pub enum BaseType {
     Int,
     Bool,
     Color,
-    Float
+    Float,
+    StringType,
 }
 
-impl BaseType {
-    pub fn to_string(&self) -> String {
+impl fmt::Display for BaseType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            BaseType::Int => "int".to_string(),
-            BaseType::Bool => "bool".to_string(),
-            BaseType::Color => "color".to_string(),
-            BaseType::Float => "float".to_string(),
+            BaseType::Int => write!(f, "int"),
+            BaseType::Bool => write!(f, "bool"),
+            BaseType::Color => write!(f, "color"),
+            BaseType::Float => write!(f, "float"),
+            BaseType::StringType => write!(f, "string"),
         }
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum VariableCall {
     Name(String),
-    ArrayCall(String, Vec<SimpleExpression>)
+    ArrayCall(String, Vec<Expression>),
+    // A path with at least one field in it, `p.x` or `balls[i].pos.x`
+    FieldCall(String, Vec<Accessor>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Accessor {
+    Index(Expression),
+    Field(String),
+}
+
+impl VariableCall {
+    // In the order they are evaluated
+    pub fn indices(&self) -> Vec<&Expression> {
+        match self {
+            VariableCall::Name(_) => vec![],
+            VariableCall::ArrayCall(_, indices) => indices.iter().collect(),
+            VariableCall::FieldCall(_, path) => path.iter().filter_map(|step| match step {
+                Accessor::Index(index) => Some(index),
+                Accessor::Field(_) => None,
+            }).collect(),
+        }
+    }
 }
 
 impl fmt::Display for VariableCall {
@@ -44,53 +68,66 @@ impl fmt::Display for VariableCall {
                     write!(f, "{}", index)?;
                 }
                 write!(f, "]")
+            },
+            VariableCall::FieldCall(name, path) => {
+                write!(f, "{}", name)?;
+                for step in path {
+                    match step {
+                        Accessor::Index(index) => write!(f, "[{}]", index)?,
+                        Accessor::Field(field) => write!(f, ".{}", field)?,
+                    }
+                }
+                Ok(())
             }
         }
     }
 }
 
-impl fmt::Display for SimpleExpression {
+impl fmt::Display for Expression {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match &self.expr {
-            SimpleExpressionType::Value(value) => write!(f, "{}", value),
-            SimpleExpressionType::Unary(op, expr) => write!(f, "{:?}({})", op, expr),
-            SimpleExpressionType::Binary(op, left, right) => write!(f, "({} {:?} {})", left, op, right),
+        match &self.expr_type {
+            ExpressionType::Value(value) => write!(f, "{}", value),
+            ExpressionType::Unary(op, expr) => write!(f, "{:?}({})", op, expr),
+            ExpressionType::Binary(op, left, right) => write!(f, "({} {:?} {})", left, op, right),
         }
     }
 }
 
-impl fmt::Display for SimpleValue {
+impl fmt::Display for BaseValue {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match &self.val {
-            SimpleValueType::Id(var) => write!(f, "{}", var),
-            SimpleValueType::Int(value) => write!(f, "{}", value),
+            BaseValueType::Id(var) => write!(f, "{}", var),
+            BaseValueType::Int(value) => write!(f, "{}", value),
+            BaseValueType::FunctionCall(name, args) => {
+                write!(f, "{}(", name)?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", arg)?;
+                }
+                write!(f, ")")
+            },
+            other => write!(f, "{:?}", other),
         }
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub enum SimpleValueType {
-    Id(VariableCall),
-    Int(i32)
-}
-
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub struct SimpleValue {
-    val: SimpleValueType,
-    coords: (usize, usize, usize, usize)
-}
-
 #[derive(Debug, Clone, PartialEq)]
 pub enum BaseValueType {
     Id(VariableCall),
     Int(i32),
     Bool(bool),
-    //StringVal(String),
+    StringVal(String),
     Color(u8, u8, u8, u8),
     RandomColor(i32),
     Float(f32),
     Array(Vec<BaseValue>), // Array of BaseValues
-    FunctionCall(String, Vec<Expression>, Type), // Function call with name and arguments
+    FunctionCall(String, Vec<Expression>), // Function call with name and arguments
+    // `Point { x: 1, y: 2 }`, fields in the order of the declaration
+    StructLiteral(String, Vec<(String, Expression)>),
+    // What a struct literal evaluates to
+    Struct(String, Vec<(String, BaseValue)>),
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -108,7 +145,10 @@ pub struct Type {
 
 impl fmt::Display for Type {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.to_string())
+        if self.is_const {
+            return write!(f, "const {}", self.type_name);
+        }
+        write!(f, "{}", self.type_name)
     }
 }
 
@@ -116,21 +156,24 @@ impl fmt::Display for Type {
 pub enum TypeName {
     Primitive(BaseType),
     Array(Box<Option<Type>>, usize), // Array of a certain type with a fixed size
+    List(Box<Type>), // Grows and shrinks at runtime, holds an array value
+    Struct(String),
 }
 
 impl Type {
-    pub fn to_string(&self) -> String {
-        if self.is_const {
-            return format!("const {}", self.type_name.to_string());
-        }
-        return self.type_name.to_string();
-    }
-
     pub fn typ(t: BaseType) -> Type {
         Type{type_name: TypeName::Primitive(t), is_const: false}
     }
 
     pub fn can_assign(&self, t: &Type) -> bool {
+        // Lists take any list or array literal of their element type
+        if let TypeName::List(t1) = &self.type_name {
+            return match &t.type_name {
+                TypeName::List(t2) => t1.can_assign(t2),
+                TypeName::Array(t2, _) => t2.as_ref().as_ref().is_none_or(|t2| t1.can_assign(t2)),
+                _ => false,
+            };
+        }
         if let TypeName::Array(t1, size1) = &self.type_name {
             if let TypeName::Array(t2, size2) = &t.type_name {
                 if size1 != size2 { return false; }
@@ -141,21 +184,27 @@ impl Type {
                 return false;
             }
         }  
-        return self.type_name == t.type_name;
+        self.type_name == t.type_name
+    }
+
+    // Arguments also take an int where a float is expected, the int is widened
+    pub fn can_pass(&self, t: &Type) -> bool {
+        self.can_assign(t) || (self.type_name == TypeName::Primitive(BaseType::Float) && t.type_name == TypeName::Primitive(BaseType::Int))
     }
 }
 
-impl TypeName {
-    pub fn to_string(&self) -> String {
+impl fmt::Display for TypeName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match &self {
-            TypeName::Primitive(type_name) => type_name.to_string(),
+            TypeName::Primitive(type_name) => write!(f, "{}", type_name),
             TypeName::Array(inner_type, size) => {
-                let inner = match inner_type.as_ref() {
-                    Some(t) => t.to_string(),
-                    None => "()".to_string(),
-                };
-                format!("array<{},{}>", inner, size)
+                match inner_type.as_ref() {
+                    Some(t) => write!(f, "array<{},{}>", t, size),
+                    None => write!(f, "array<(),{}>", size),
+                }
             },
+            TypeName::List(inner_type) => write!(f, "list<{}>", inner_type),
+            TypeName::Struct(name) => write!(f, "{}", name),
         }
     }
 }
@@ -167,15 +216,15 @@ impl BaseValue {
         use TypeName::*;
         match &self.val {
             BaseValueType::Id(name) => {
-                if let Some(type_) = get_var_type(&name) {
+                if let Some(type_) = get_var_type(name) {
                     Ok(type_.type_name)
                 } else {
-                    Err(Error::type_er(format!("Variable type unknown: {}", name.to_string()), self.coords))
+                    Err(Error::type_er(format!("Variable type unknown: {}", name), self.coords))
                 }
             }, 
             BaseValueType::Int(_) => Ok(Primitive(BaseType::Int)),
             BaseValueType::Bool(_) => Ok(Primitive(BaseType::Bool)),
-            //BaseValueType::StringVal(_) => Ok(Primitive(BaseType::StringType)),
+            BaseValueType::StringVal(_) => Ok(Primitive(BaseType::StringType)),
             BaseValueType::Color(_, _, _, _) => Ok(Primitive(BaseType::Color)),
             BaseValueType::RandomColor(_) => Ok(Primitive(BaseType::Color)),
             BaseValueType::Float(_) => Ok(Primitive(BaseType::Float)),
@@ -186,14 +235,15 @@ impl BaseValue {
                 let type_ = elems.first().unwrap().clone().get_type(get_var_type)?;
                 for elem in elems {
                     if elem.get_type(get_var_type)? != type_ {
-                        return Err(Error::type_er(format!("Array type unknown"), self.coords));
+                        return Err(Error::type_er("Array type unknown".to_string(), self.coords));
                     }
                 }
                 Ok(Array(Box::new(Some(Type{type_name:type_, is_const:false})), elems.len()))
             }
-            BaseValueType::FunctionCall(_, _, t) => {
-                Ok(t.type_name.clone())
-            }
+            BaseValueType::FunctionCall(name, _) => {
+                Err(Error::type_er(format!("The type of a call to {} is only known to the type checker", name), self.coords))
+            },
+            BaseValueType::StructLiteral(name, _) | BaseValueType::Struct(name, _) => Ok(Struct(name.clone())),
         }
     }
 }
@@ -234,57 +284,10 @@ pub fn is_arith(op : Operator) -> bool {
     op == Operator::Mod
 }
 
-fn prec(op : Operator) -> i32 {
-    if op == Operator::OR {
-        return 10;
-    }else if op == Operator::AND {
-        return 9;
-    } else if op == Operator::Mult || op == Operator::Div || op == Operator::Mod {
-        return 4;
-    } else if op == Operator::Plus || op == Operator::Minus {
-        return 6;
-    } else {
-        return 8;
-    }
-}
- 
-pub fn goes_before(op1 : Operator,  op2: Operator) -> bool {
-    return prec(op1) < prec(op2)
-}
-
-
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub enum SimpleExpressionType {
-    Value(SimpleValue),
-    Unary(UnaryOperator, Box<SimpleExpression>),
-    Binary(Operator, Box<SimpleExpression>, Box<SimpleExpression>)
-}
-
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub struct SimpleExpression {
-    expr: SimpleExpressionType,
-    coords: (usize, usize, usize, usize)
+pub fn is_comparison(op : Operator) -> bool {
+    matches!(op, Operator::EQ | Operator::NQ | Operator::GT | Operator::LT | Operator::GQ | Operator::LQ)
 }
 
-impl SimpleExpression {
-    pub fn to_expr(self) -> Expression {
-        match self.expr {
-            SimpleExpressionType::Value(value) => {
-                Expression{expr_type: ExpressionType::Value(match value.val {
-                    SimpleValueType::Id(var) => BaseValue{val: BaseValueType::Id(var), coords: value.coords},
-                    SimpleValueType::Int(i) => BaseValue{val: BaseValueType::Int(i), coords: value.coords},
-                }), coords: self.coords}
-            }
-            SimpleExpressionType::Unary(op, expr) => 
-                Expression{ expr_type: ExpressionType::Unary(op, Box::new(expr.to_expr())), coords: self.coords},
-            SimpleExpressionType::Binary(op, left, right) => 
-                Expression{ expr_type: ExpressionType::Binary(op, Box::new(left.to_expr()), Box::new(right.to_expr()))
-                    , coords: self.coords}
-        }
-    }
-}
-
-
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ExpressionType {
     Value(BaseValue),
@@ -304,10 +307,18 @@ pub enum AstStatement {
     Command { name: String, args: Vec<Expression> },
     Init    { typ: Type, val : String, expr: Expression },
     SetVal { val: VariableCall, expr: Expression },
-    For     { val: String, from: Expression, to: Expression, block: AstBlock },
+    // `x += e`, `expr` is `x + (e)` so it is checked like `SetVal`, but the indices of `val` are worked out only once
+    Update { val: VariableCall, expr: Expression },
+    // `to` is left out when `exclusive`, without a `step` the loop counts towards `to` by 1
+    For     { val: String, from: Expression, to: Expression, step: Option<Box<Expression>>, exclusive: bool, block: AstBlock },
+    // Goes over the elements of an array or a list, `index` counts them from 0
+    ForEach { index: Option<String>, val: String, iterable: Expression, block: AstBlock },
     While   { clause: Expression, block: AstBlock},
     If      { clause: Expression, block: AstBlock, else_block: Option<AstBlock>},
     Return  { expr: Expression },
+    // Leave or skip to the next iteration of the innermost loop
+    Break,
+    Continue,
 }
 
 #[derive(Debug, Clone)]
@@ -320,7 +331,7 @@ impl Expression {
     pub fn get_type<F>(&self, get_var_type: &F) -> Result<TypeName, Error>
         where F: Fn(&VariableCall) -> Option<Type>
     {
-        let type_mismatch = Err(Error::type_er(format!("Type mismatch error"), self.coords));
+        let type_mismatch = Err(Error::type_er("Type mismatch error".to_string(), self.coords));
         match &self.expr_type {
             ExpressionType::Value(base_value) => base_value.get_type(get_var_type),
             ExpressionType::Unary(_, expr) => expr.get_type(get_var_type),
@@ -348,7 +359,11 @@ impl Expression {
                     }
                     return type_mismatch;
                 }
-                return if t1 == t2 { Ok(t1) } else { type_mismatch }
+                let (int, float) = (TypeName::Primitive(BaseType::Int), TypeName::Primitive(BaseType::Float));
+                if (t1 == int && t2 == float) || (t1 == float && t2 == int) {
+                    return Ok(float);
+                }
+                if t1 == t2 { Ok(t1) } else { type_mismatch }
             },
         }
     }
@@ -372,6 +387,13 @@ pub struct HalfParsedAstFunction<'a> {
     pub coords: Coords
 }
 
+#[derive(Debug, Clone)]
+pub struct AstStruct {
+    pub name: String,
+    pub fields: Vec<(String, Type)>,
+    pub coords: Coords,
+}
+
 #[derive(Debug, Clone)]
 pub struct AstFunction {
     pub name: String,
@@ -381,7 +403,11 @@ pub struct AstFunction {
     pub header: Coords,
 }
 
-pub type FunctionsAndGlobals = (Vec<AstFunction>, HashMap<String, (Coords, Type, Expression)>);
+pub type FunctionSignature = (Vec<(String, Type)>, Option<Type>);
+pub type FunctionBody = (Vec<(String, Type)>, Option<Type>, AstBlock);
+
+// Structs are kept in the order they are declared in
+pub type FunctionsAndGlobals = (Vec<AstFunction>, HashMap<String, (Coords, Type, Expression)>, Vec<AstStruct>);
 
 #[derive(Debug, Clone)]
 pub enum AstProgram {
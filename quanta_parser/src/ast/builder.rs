@@ -1,10 +1,10 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::OnceLock};
 
-use pest::iterators::{Pairs, Pair};
-use crate::{ast::{keys::key_to_number, AstFunction, AstProgram, AstStatement, BaseValueType, Coords, ExpressionType, FunctionsAndGlobals, HalfParsedAstFunction, SimpleExpression, SimpleExpressionType, SimpleValue, SimpleValueType, Type, TypeName, VariableCall}, error::Error, Rule};
+use pest::{iterators::{Pairs, Pair}, pratt_parser::{Assoc, Op, PrattParser}};
+use crate::{ast::{keys::key_to_number, Accessor, AstFunction, AstStruct, AstProgram, AstStatement, BaseValueType, Coords, ExpressionType, FunctionsAndGlobals, HalfParsedAstFunction, Type, TypeName, VariableCall}, error::Error, Rule};
 
 
-use super::{AstBlock, AstNode, Expression, Operator,  BaseType, BaseValue, goes_before, UnaryOperator };
+use super::{AstBlock, AstNode, Expression, Operator,  BaseType, BaseValue, is_comparison, UnaryOperator };
 
 macro_rules! coords {
     ($val:expr) => {
@@ -16,31 +16,28 @@ macro_rules! coords {
 }
 
 pub struct AstBuilder {
-    pub function_signatures : HashMap<String, (Vec<Type>, Option<Type>)>
+    pub structs : HashMap<String, Vec<(String, Type)>>,
+}
+
+impl Default for AstBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl AstBuilder {
 
 pub fn new() -> AstBuilder
 {
-    AstBuilder{ function_signatures: HashMap::new() }
+    AstBuilder{ structs: HashMap::new() }
 }
 
 pub fn build_ast_from_doc(&mut self, docs: Pairs<Rule>) -> Result<AstProgram, Error> {
-    self.function_signatures.insert(String::from("rgb"), (vec![Type::typ(BaseType::Int), Type::typ(BaseType::Int), Type::typ(BaseType::Int)], Some(Type::typ(BaseType::Color))));
-    self.function_signatures.insert(String::from("round"), (vec![Type::typ(BaseType::Float)], Some(Type::typ(BaseType::Int))));
-    self.function_signatures.insert(String::from("decimal"), (vec![Type::typ(BaseType::Int)], Some(Type::typ(BaseType::Float))));
-    self.function_signatures.insert(String::from("ceil"), (vec![Type::typ(BaseType::Float)], Some(Type::typ(BaseType::Int))));
-    self.function_signatures.insert(String::from("floor"), (vec![Type::typ(BaseType::Float)], Some(Type::typ(BaseType::Int))));
-    self.function_signatures.insert(String::from("abs"), (vec![Type::typ(BaseType::Int)], Some(Type::typ(BaseType::Int))));
-    //self.function_signatures.insert(String::from("abs"), (vec![Type::typ(BaseType::Float)], Some(Type::typ(BaseType::Float))));
-    self.function_signatures.insert(String::from("sqrt"), (vec![Type::typ(BaseType::Float)], Some(Type::typ(BaseType::Float))));
-    self.function_signatures.insert(String::from("random"), (vec![Type::typ(BaseType::Int), Type::typ(BaseType::Int)], Some(Type::typ(BaseType::Int))));
     assert!(docs.len() == 1);
     let doc = docs.into_iter().next().unwrap();
     assert!(doc.as_rule() == Rule::document);
 
-    let mut doc_iter = doc.into_inner().into_iter();
+    let mut doc_iter = doc.into_inner();
     assert!(doc_iter.len() == 2);
     let block_rule = doc_iter.next().unwrap();
     let eof_rule = doc_iter.next().unwrap();
@@ -53,29 +50,43 @@ pub fn build_ast_from_doc(&mut self, docs: Pairs<Rule>) -> Result<AstProgram, Er
     }    
 }
 
+// A lone expression, as typed into the REPL
+pub fn build_ast_from_expression_input(&mut self, input: Pairs<Rule>) -> Result<Expression, Error> {
+    let input = input.into_iter().next().unwrap();
+    assert!(input.as_rule() == Rule::expression_input);
+    self.build_ast_from_expression(input.into_inner().next().unwrap())
+}
+
 fn build_ast_from_forest(&mut self, statements: Pairs<Rule>) -> Result<FunctionsAndGlobals, Error> {
     let mut half_functions = vec![];
-    let mut init_statements :HashMap<String, ((usize, usize, usize, usize), Type, Expression)> = HashMap::new();
+    let mut init_statements :HashMap<String, (Coords, Type, Expression)> = HashMap::new();
     let mut blocks : Vec<AstFunction> = vec![];
+    // Struct literals are checked against the declaration, so structs come first
+    let mut structs = vec![];
+    for pair in statements.clone().filter(|pair| pair.as_rule() == Rule::struct_def) {
+        let declaration = self.build_ast_from_struct(pair)?;
+        self.structs.insert(declaration.name.clone(), declaration.fields.clone());
+        structs.push(declaration);
+    }
     for pair in statements.clone() {
         match pair.as_rule() {
+            Rule::struct_def => {},
             Rule::function => {
                 let res = self.get_function_signature(pair.into_inner())?;
-                self.function_signatures.insert(res.name.clone(), (res.args.iter().map(|(_, t)| t.clone()).collect(), res.return_type.clone()));
                 half_functions.push(res);
             }
             Rule::global_block => {
-                let mut iter = pair.into_inner().into_iter();
+                let iter = pair.into_inner();
                 
-                while let Some(init) = iter.next() {
+                for init in iter {
                     if init.as_rule() == Rule::strong_init {
                         let coords = coords!(init);
-                        let mut init_iter = init.into_inner().into_iter();
+                        let mut init_iter = init.into_inner();
                         let type_name = self.build_ast_from_type(init_iter.next().unwrap())?;
-                        let mut init_iter2 = init_iter.next().unwrap().into_inner().into_iter();
+                        let mut init_iter2 = init_iter.next().unwrap().into_inner();
                         let name = self.build_ast_from_noun(init_iter2.next().unwrap())?;
                         match name {
-                            VariableCall::ArrayCall(_, _) => return Err(Error::parse(String::from("Array call not allowed in an init statement"), coords)),
+                            VariableCall::ArrayCall(_, _) | VariableCall::FieldCall(_, _) => return Err(Error::parse(String::from("Array call not allowed in an init statement"), coords)),
                             VariableCall::Name(n) => {
                                 if init_statements.contains_key(&n) {
                                     return Err(Error::parse(format!("Global variable '{}' is already defined", &n), coords));
@@ -95,7 +106,28 @@ fn build_ast_from_forest(&mut self, statements: Pairs<Rule>) -> Result<Functions
     for func in half_functions {
         blocks.push(self.build_ast_from_function(func)?);
     }
-    Ok((blocks, init_statements))
+    Ok((blocks, init_statements, structs))
+}
+
+fn build_ast_from_struct(&self, declaration: Pair<Rule>) -> Result<AstStruct, Error> {
+    let coords = coords!(declaration);
+    let mut iter = declaration.into_inner();
+    let name = self.build_ast_from_ident(iter.next().unwrap())?;
+    if self.structs.contains_key(&name) {
+        return Err(Error::parse(format!("Struct '{}' is already defined", name), coords));
+    }
+    let mut fields: Vec<(String, Type)> = vec![];
+    for field in iter {
+        let field_coords = coords!(field);
+        let mut field_iter = field.into_inner();
+        let typ = self.build_ast_from_type(field_iter.next().unwrap())?;
+        let field_name = self.build_ast_from_ident(field_iter.next().unwrap())?;
+        if fields.iter().any(|(existing, _)| *existing == field_name) {
+            return Err(Error::parse(format!("Struct '{}' already has a field '{}'", name, field_name), field_coords));
+        }
+        fields.push((field_name, typ));
+    }
+    Ok(AstStruct { name, fields, coords })
 }
 
 fn build_ast_from_function(&self, function: HalfParsedAstFunction) -> Result<AstFunction, Error> {
@@ -112,12 +144,12 @@ fn get_function_signature<'a>(&self, statement: Pairs<'a, Rule>) -> Result<HalfP
     let body = iter.next().unwrap();
     assert!(body.as_rule() == Rule::block);
 
-    let mut header_iter = header.into_inner().into_iter();
+    let mut header_iter = header.into_inner();
     let name = self.build_ast_from_ident(header_iter.next().unwrap())?;
     let mut args = vec![];
-    let args_iter = header_iter.next().unwrap().into_inner().into_iter();
+    let args_iter = header_iter.next().unwrap().into_inner();
     for arg in args_iter {
-        let mut arg_iter = arg.into_inner().into_iter();
+        let mut arg_iter = arg.into_inner();
         let arg_type = self.build_ast_from_type(arg_iter.next().unwrap())?;
         let arg_name = self.build_ast_from_ident(arg_iter.next().unwrap())?;
         args.push((arg_name, arg_type));
@@ -171,23 +203,26 @@ fn build_ast_from_statement(&self, statement: Pairs<Rule>) -> Result<AstNode, Er
         Rule::init_statement => self.build_ast_from_init(state.into_inner(), coords),
         Rule::if_statement => self.build_ast_from_if(state.into_inner(), coords),
         Rule::for_statement => self.build_ast_from_for(state.into_inner(), coords),
+        Rule::for_each_statement => self.build_ast_from_for_each(state.into_inner(), coords),
         Rule::while_statement => self.build_ast_from_while(state.into_inner(), coords),
         Rule::return_statement => {
-            let expr = self.build_ast_from_expression(state.into_inner().into_iter().next().unwrap())?;
-            Ok(AstNode{statement: AstStatement::Return { expr: expr }, coords: coords})
+            let expr = self.build_ast_from_expression(state.into_inner().next().unwrap())?;
+            Ok(AstNode{statement: AstStatement::Return { expr }, coords})
         }
-        _ => return Err(Error::parse(String::from("Expected a statement!"), coords!(state)))
+        Rule::break_statement => Ok(AstNode{statement: AstStatement::Break, coords}),
+        Rule::continue_statement => Ok(AstNode{statement: AstStatement::Continue, coords}),
+        _ => Err(Error::parse(String::from("Expected a statement!"), coords!(state)))
     }
 }
 
 fn build_ast_from_command(&self, command: Pairs<Rule>, coords: Coords) -> Result<AstNode, Error> {
-    let mut iter = command.into_iter().next().unwrap().into_inner().into_iter();
+    let mut iter = command.into_iter().next().unwrap().into_inner();
     let name = self.build_ast_from_ident(iter.next().unwrap())?;
     let args = self.build_ast_from_arglist(iter)?;
-    return Ok(AstNode{statement: AstStatement::Command { 
-        name: name,
-        args: args
-    }, coords});
+    Ok(AstNode{statement: AstStatement::Command { 
+        name,
+        args
+    }, coords})
 }
 
 fn build_ast_from_ident(&self, ident: Pair<Rule>) -> Result<String, Error> {
@@ -196,12 +231,22 @@ fn build_ast_from_ident(&self, ident: Pair<Rule>) -> Result<String, Error> {
 
 fn build_ast_from_noun(&self, ident: Pair<Rule>) -> Result<VariableCall, Error> {
     if ident.as_rule() == Rule::noun {
-        let mut ident = ident.into_inner().into_iter();
+        let mut ident = ident.into_inner();
         if let Some(name) = ident.next() {
+            if ident.clone().any(|step| step.as_rule() == Rule::field) {
+                let mut path = vec![];
+                for step in ident {
+                    path.push(match step.as_rule() {
+                        Rule::field => Accessor::Field(self.build_ast_from_ident(step.into_inner().next().unwrap())?),
+                        _ => Accessor::Index(self.build_ast_from_expression(step.into_inner().next().unwrap())?),
+                    });
+                }
+                return Ok(VariableCall::FieldCall(String::from(name.as_str()), path));
+            }
             if ident.clone().count() > 0 {
                 let mut args = vec![];
                 for arg in ident {
-                    args.push(self.build_ast_for_simple_expression(arg.into_inner().into_iter().next().unwrap())?);
+                    args.push(self.build_ast_from_expression(arg.into_inner().next().unwrap())?);
                 }
                 return Ok(VariableCall::ArrayCall(String::from(name.as_str()), args));
             }
@@ -220,231 +265,145 @@ fn build_ast_from_arglist(&self, args: Pairs<Rule>) -> Result<Vec<Expression>, E
     Ok(expressions)
 }
 
-fn improve_expr(&self, expr : Expression) -> (Expression, bool) {
-    let bin = |op, l, r, c| {
-        Expression{expr_type: ExpressionType::Binary(op, l, r), coords: c}
-    };
-    match expr.expr_type {
-        ExpressionType::Value(_) => (Expression{expr_type: expr.expr_type, coords: expr.coords}, false),
-        ExpressionType::Unary(op, inner) => {
-            let (new_inner, did) = self.improve_expr(*inner);
-            (Expression{expr_type: ExpressionType::Unary(op, new_inner.into()), coords: expr.coords}, did)
-        },
-        ExpressionType::Binary(op, left, right) => {
-            let mut new_left : Expression = *left;
-            if let Expression{expr_type: ExpressionType::Unary(UnaryOperator::Parentheses, _), coords: _} = new_left {
-                (new_left, _) = self.improve_expr(new_left);
-            }
-            if let Expression{expr_type: ExpressionType::Binary(r_op, r_left, r_right), coords} = *right.clone() {
-                if goes_before(op, r_op) {
-                    return self.improve_expr(bin(r_op, 
-                            bin(op, new_left.into(), r_left, coords).into(), r_right, expr.coords))
-                } else {
-                    let (new_right, redo) = self.improve_expr(*right);
-                    if redo {
-                        return self.improve_expr(bin(op, new_left.into(), new_right.into(), expr.coords));
-                    }
-                    return (bin(op, new_left.into(), new_right.into(), expr.coords), false)
-                }
-            }
-            (bin(op, new_left.into(), right, expr.coords), false)
-        },
-    }
+// Operators further down bind tighter, every binary operator is left-associative
+fn pratt() -> &'static PrattParser<Rule> {
+    static PRATT: OnceLock<PrattParser<Rule>> = OnceLock::new();
+    PRATT.get_or_init(|| PrattParser::new()
+        .op(Op::infix(Rule::or, Assoc::Left))
+        .op(Op::infix(Rule::and, Assoc::Left))
+        .op(Op::infix(Rule::eq, Assoc::Left) | Op::infix(Rule::ne, Assoc::Left)
+            | Op::infix(Rule::lt, Assoc::Left) | Op::infix(Rule::gt, Assoc::Left)
+            | Op::infix(Rule::le, Assoc::Left) | Op::infix(Rule::ge, Assoc::Left))
+        .op(Op::infix(Rule::add, Assoc::Left) | Op::infix(Rule::sub, Assoc::Left))
+        .op(Op::infix(Rule::mul, Assoc::Left) | Op::infix(Rule::div, Assoc::Left) | Op::infix(Rule::rem, Assoc::Left))
+        .op(Op::prefix(Rule::neg) | Op::prefix(Rule::not)))
 }
 
-// todo remove separation for simple expressions
-fn improve_simple_expr(&self, expr : SimpleExpression) -> (SimpleExpression, bool) {
-    let sim = |op, l, r, c| {
-        SimpleExpression{expr: SimpleExpressionType::Binary(op, l, r), coords: c}
-    };
-    match expr.expr {
-        SimpleExpressionType::Value(_) => (expr, false),
-        SimpleExpressionType::Unary(_, _) => (expr, false),
-        SimpleExpressionType::Binary(op, left, right) => {
-            let mut new_left : SimpleExpression = *left;
-            if let SimpleExpression{ expr: SimpleExpressionType::Unary(UnaryOperator::Parentheses, _), coords: _} = new_left {
-                (new_left, _) = self.improve_simple_expr(new_left);
-            }
-            if let SimpleExpression{expr: SimpleExpressionType::Binary(r_op, r_left, r_right), coords} = *right.clone() {
-                if goes_before(op, r_op) {
-                    return self.improve_simple_expr(
-                        sim(r_op, 
-                            sim(op, new_left.into(), r_left, coords).into(), r_right, expr.coords))
-                } else {
-                    let (new_right, redo) = self.improve_simple_expr(*right);
-                    if redo {
-                        return self.improve_simple_expr(SimpleExpression{expr: SimpleExpressionType::Binary(op, new_left.into(), new_right.into()), coords: expr.coords});
-                    }
-                    return (sim(op, new_left.into(), new_right.into(), expr.coords), false)
-                }
-            }
-            (sim(op, new_left.into(), right, expr.coords), false)
-        },
+fn binary_operator(rule: Rule) -> Operator {
+    match rule {
+        Rule::add => Operator::Plus,
+        Rule::sub => Operator::Minus,
+        Rule::mul => Operator::Mult,
+        Rule::div => Operator::Div,
+        Rule::rem => Operator::Mod,
+        Rule::eq  => Operator::EQ,
+        Rule::ne  => Operator::NQ,
+        Rule::lt  => Operator::LT,
+        Rule::gt  => Operator::GT,
+        Rule::le  => Operator::LQ,
+        Rule::ge  => Operator::GQ,
+        Rule::and => Operator::AND,
+        Rule::or  => Operator::OR,
+        rule => unreachable!("{:?} is not a binary operator", rule),
     }
 }
 
-fn build_ast_for_simple_expression(&self, expression : Pair<Rule>) -> Result<SimpleExpression, Error> {
-    let expr = self.build_ast_from_simple_expression_inner(expression)?;
-    let (res, _) = self.improve_simple_expr(expr);
-    Ok(res)
-}
-
-fn build_ast_from_simple_expression_inner(&self, expression: Pair<Rule>) -> Result<SimpleExpression, Error> {
-    let coords = coords!(expression);
-    match expression.as_rule() {
-        Rule::monadicExpr => {
-            let coords = coords!(expression);
-            let mut iter = expression.into_inner().into_iter();
-            let operator = iter.next().unwrap();
-            let right = self.build_ast_from_simple_expression_inner(iter.next().unwrap())?;
-            if operator.as_str().trim() == "-" {
-                Ok(SimpleExpression{expr: SimpleExpressionType::Unary(super::UnaryOperator::UnaryMinus, right.into()), coords: coords})
-            } else if operator.as_str().trim() == "!" {
-                Ok(SimpleExpression{expr: SimpleExpressionType::Unary(super::UnaryOperator::NOT, right.into()), coords: coords})
-            } else {
-                Err(Error::parse(format!("Unknown unary operator '{}'", operator.as_str()), coords))
-            }
-        },
-        Rule::dyadicExpr => {
-            let coords = coords!(expression);
-            let mut iter = expression.into_inner().into_iter();
-            let left = self.build_ast_from_simple_expression_inner(iter.next().unwrap())?;
-            let operator = iter.next().unwrap();
-            let right = self.build_ast_from_simple_expression_inner(iter.next().unwrap())?;
-            let v = match operator.as_str() {
-                "+" => Ok(SimpleExpressionType::Binary(Operator::Plus, left.into(), right.into())),
-                "-" => Ok(SimpleExpressionType::Binary(Operator::Minus, left.into(), right.into())),
-                "*" => Ok(SimpleExpressionType::Binary(Operator::Mult, left.into(), right.into())),
-                "/" => Ok(SimpleExpressionType::Binary(Operator::Div, left.into(), right.into())),
-                "%" => Ok(SimpleExpressionType::Binary(Operator::Mod, left.into(), right.into())),
-
-                ">"   => Ok(SimpleExpressionType::Binary(Operator::GT, left.into(), right.into())),
-                "<"   => Ok(SimpleExpressionType::Binary(Operator::LT, left.into(), right.into())),
-                ">="  => Ok(SimpleExpressionType::Binary(Operator::GQ, left.into(), right.into())),
-                "<="  => Ok(SimpleExpressionType::Binary(Operator::LQ, left.into(), right.into())),
-                "=="  => Ok(SimpleExpressionType::Binary(Operator::EQ, left.into(), right.into())),
-                "!="  => Ok(SimpleExpressionType::Binary(Operator::NQ, left.into(), right.into())),
-
-                "&&"  => Ok(SimpleExpressionType::Binary(Operator::AND, left.into(), right.into())),
-                "||"  => Ok(SimpleExpressionType::Binary(Operator::OR, left.into(), right.into())),
-
-                op => Err(Error::parse(format!("Unknown operator {}", op), coords))
-            }?;
-            Ok(SimpleExpression { expr: v, coords: coords })
-        },
-        Rule::expression => {
-            return self.build_ast_from_simple_expression_inner(expression.into_inner().into_iter().next().unwrap())
-        },
-        Rule::parenth_expr => {
-            let inner_expr = self.build_ast_from_simple_expression_inner(expression.into_inner().into_iter().next().unwrap().into_inner().into_iter().next().unwrap())?;
-            Ok(SimpleExpression{expr: SimpleExpressionType::Unary(super::UnaryOperator::Parentheses, inner_expr.into()), coords:coords})
-        },
-        _ => {
-            return Ok(SimpleExpression{expr: SimpleExpressionType::Value(self.build_ast_from_simple_value(expression)?), coords: coords})
-        }
+fn build_ast_from_expression(&self, expression: Pair<Rule>) -> Result<Expression, Error> {
+    if expression.as_rule() != Rule::expression {
+        return self.build_ast_from_operand(expression);
     }
-
-
+    Self::pratt()
+        .map_primary(|operand| self.build_ast_from_operand(operand))
+        .map_prefix(|op, inner| {
+            let inner = inner?;
+            let (line, col) = op.as_span().start_pos().line_col();
+            let coords = (line, col, inner.coords.2, inner.coords.3);
+            let op = if op.as_rule() == Rule::neg { UnaryOperator::UnaryMinus } else { UnaryOperator::NOT };
+            Ok(Expression{expr_type: ExpressionType::Unary(op, inner.into()), coords})
+        })
+        .map_infix(|left, op, right| {
+            let (left, right) = (left?, right?);
+            let coords = (left.coords.0, left.coords.1, right.coords.2, right.coords.3);
+            let operator = Self::binary_operator(op.as_rule());
+            // `a < b < c` would compare a bool with c
+            if let ExpressionType::Binary(left_op, _, _) = &left.expr_type {
+                if is_comparison(operator) && is_comparison(*left_op) {
+                    return Err(Error::parse(String::from("Comparisons cannot be chained, join them with &&"), coords));
+                }
+            }
+            Ok(Expression{expr_type: ExpressionType::Binary(operator, left.into(), right.into()), coords})
+        })
+        .parse(expression.into_inner())
 }
 
-fn build_ast_from_expression(&self, expression: Pair<Rule>) -> Result<Expression, Error> {
-    let expr = self.build_ast_from_expression_inner(expression)?;
-    let (res, _) = self.improve_expr(expr);
-    Ok(res)
-}
-
-fn build_ast_from_expression_inner(&self, expression: Pair<Rule>) -> Result<Expression, Error> {
-    let coords = coords!(expression);
-    match expression.as_rule() {
-        Rule::monadicExpr => {
-            let mut iter = expression.into_inner().into_iter();
-            let operator = iter.next().unwrap();
-            let right = self.build_ast_from_expression_inner(iter.next().unwrap())?;
-            if operator.as_str() == "-" {
-                Ok(Expression{expr_type: ExpressionType::Unary(super::UnaryOperator::UnaryMinus, right.into()), coords})
-            } else if operator.as_str() == "!" {
-                Ok(Expression{expr_type: ExpressionType::Unary(super::UnaryOperator::NOT, right.into()), coords: coords})
-            } else {
-                Err(Error::parse(format!("Unknown unary operator {}", operator.as_str()), coords))
-            }
-        },
-        Rule::dyadicExpr => {
-            let coords = coords!(expression);
-            let mut iter = expression.into_inner().into_iter();
-            let left = self.build_ast_from_expression_inner(iter.next().unwrap())?;
-            let operator = iter.next().unwrap();
-            let right = self.build_ast_from_expression_inner(iter.next().unwrap())?;
-            let expr = match operator.as_str() {
-                "+" => Ok(ExpressionType::Binary(Operator::Plus, left.into(), right.into())),
-                "-" => Ok(ExpressionType::Binary(Operator::Minus, left.into(), right.into())),
-                "*" => Ok(ExpressionType::Binary(Operator::Mult, left.into(), right.into())),
-                "/" => Ok(ExpressionType::Binary(Operator::Div, left.into(), right.into())),
-                "%" => Ok(ExpressionType::Binary(Operator::Mod, left.into(), right.into())),
-
-                ">"   => Ok(ExpressionType::Binary(Operator::GT, left.into(), right.into())),
-                "<"   => Ok(ExpressionType::Binary(Operator::LT, left.into(), right.into())),
-                ">="  => Ok(ExpressionType::Binary(Operator::GQ, left.into(), right.into())),
-                "<="  => Ok(ExpressionType::Binary(Operator::LQ, left.into(), right.into())),
-                "=="  => Ok(ExpressionType::Binary(Operator::EQ, left.into(), right.into())),
-                "!="  => Ok(ExpressionType::Binary(Operator::NQ, left.into(), right.into())),
-
-                "&&"  => Ok(ExpressionType::Binary(Operator::AND, left.into(), right.into())),
-                "||"  => Ok(ExpressionType::Binary(Operator::OR, left.into(), right.into())),
-
-                op => Err(Error::parse(format!("Unknown operator {}", op), coords))
-            }?;
-            Ok(Expression{expr_type: expr, coords: coords})
-        },
-        Rule::expression => {
-            return self.build_ast_from_expression_inner(expression.into_inner().into_iter().next().unwrap())
-        },
-        Rule::parenth_expr => {
-            let inner_expr = self.build_ast_from_expression_inner(expression.into_inner().into_iter().next().unwrap().into_inner().into_iter().next().unwrap())?;
-            Ok(Expression{expr_type: ExpressionType::Unary(super::UnaryOperator::Parentheses, inner_expr.into()), coords: coords})
-        },
-        _ => {
-            return Ok(Expression{expr_type: ExpressionType::Value(self.build_ast_from_value(expression)?), coords: coords});
-        }
+fn build_ast_from_operand(&self, operand: Pair<Rule>) -> Result<Expression, Error> {
+    let coords = coords!(operand);
+    if operand.as_rule() == Rule::parenth_expr {
+        let inner_expr = self.build_ast_from_expression(operand.into_inner().next().unwrap())?;
+        return Ok(Expression{expr_type: ExpressionType::Unary(UnaryOperator::Parentheses, inner_expr.into()), coords});
     }
-
-
+    Ok(Expression{expr_type: ExpressionType::Value(self.build_ast_from_value(operand)?), coords})
 }
 
 fn build_ast_from_init(&self, command: Pairs<Rule>, coords: Coords) -> Result<AstNode, Error> {
     let mut iter = command.into_iter();
     let mut first = iter.next().unwrap();
+    if let Rule::compound_assignment | Rule::increment = first.as_rule() {
+        return self.build_ast_from_compound(first, coords);
+    }
     if let Rule::type_name = first.as_rule() {
         let type_val = self.build_ast_from_type(first)?;
         first = iter.next().unwrap();
-        let mut assign = first.into_inner().into_iter();
+        let mut assign = first.into_inner();
         let name = self.build_ast_from_noun(assign.next().unwrap())?;
         return match name {
-            VariableCall::ArrayCall(_, _) => Err(Error::parse(String::from("Array call not allowed in an init statement"), coords)),
+            VariableCall::ArrayCall(_, _) | VariableCall::FieldCall(_, _) => Err(Error::parse(String::from("Array call not allowed in an init statement"), coords)),
             VariableCall::Name(n) => {
                 let expr = self.build_ast_from_expression(assign.next().unwrap())?;
                 Ok(AstNode{statement: AstStatement::Init { typ: type_val, val: n, expr }, coords})
             }
         }
     } 
-    let mut assign = first.into_inner().into_iter();
+    let mut assign = first.into_inner();
     let name = self.build_ast_from_noun(assign.next().unwrap())?;
     let expr = self.build_ast_from_expression(assign.next().unwrap())?;
 
     Ok(AstNode{statement: AstStatement::SetVal { val: name, expr },coords})
 }
 
+// `x += e` is `x = x + (e)` and `x++` is `x = x + 1`, so they are checked like any other assignment
+fn build_ast_from_compound(&self, assign: Pair<Rule>, coords: Coords) -> Result<AstNode, Error> {
+    let is_increment = assign.as_rule() == Rule::increment;
+    let mut inner = assign.into_inner();
+    let target = inner.next().unwrap();
+    let target_coords = coords!(target);
+    let name = self.build_ast_from_noun(target)?;
+    let op = inner.next().unwrap();
+    let op_coords = coords!(op);
+    let operator = match op.as_str() {
+        "+=" | "++" => Operator::Plus,
+        "-=" | "--" => Operator::Minus,
+        "*=" => Operator::Mult,
+        "/=" => Operator::Div,
+        "%=" => Operator::Mod,
+        op => return Err(Error::parse(format!("Unknown operator {}", op), op_coords)),
+    };
+    let rhs = if is_increment {
+        Expression{expr_type: ExpressionType::Value(BaseValue{val: BaseValueType::Int(1), coords: op_coords}), coords: op_coords}
+    } else {
+        let rhs = self.build_ast_from_expression(inner.next().unwrap())?;
+        let rhs_coords = rhs.coords;
+        Expression{expr_type: ExpressionType::Unary(super::UnaryOperator::Parentheses, rhs.into()), coords: rhs_coords}
+    };
+    let current = Expression{expr_type: ExpressionType::Value(BaseValue{val: BaseValueType::Id(name.clone()), coords: target_coords}), coords: target_coords};
+    let expr = Expression{expr_type: ExpressionType::Binary(operator, current.into(), rhs.into()), coords};
+    Ok(AstNode{statement: AstStatement::Update { val: name, expr }, coords})
+}
+
 fn build_ast_from_if(&self, command: Pairs<Rule>, coords: Coords) -> Result<AstNode, Error> {
     let mut iter = command.into_iter();
-    return Ok(AstNode{statement: AstStatement::If { 
+    Ok(AstNode{statement: AstStatement::If { 
         clause: self.build_ast_from_expression(iter.next().unwrap())?, 
-        block: self.build_ast_from_block(iter.next().unwrap().into_inner().into_iter().next().unwrap().into_inner())?,
-        else_block: { 
-            if let Some(rule) = iter.next() {
-                let block = self.build_ast_from_block(rule.into_inner().into_iter().next().unwrap().into_inner())?;
-                    Some(block)
-            } else { 
-                None 
-            }
+        block: self.build_ast_from_block(iter.next().unwrap().into_inner().next().unwrap().into_inner())?,
+        else_block: match iter.next() {
+            // `else if` is an else block holding just the next if of the chain
+            Some(rule) if rule.as_rule() == Rule::if_statement => {
+                let coords = coords!(rule);
+                let chained = self.build_ast_from_if(rule.into_inner(), coords)?;
+                Some(AstBlock { nodes: vec![chained], coords })
+            },
+            Some(rule) => Some(self.build_ast_from_block(rule.into_inner().next().unwrap().into_inner())?),
+            None => None,
         }
     }, coords})
 }
@@ -452,12 +411,31 @@ fn build_ast_from_if(&self, command: Pairs<Rule>, coords: Coords) -> Result<AstN
 fn build_ast_from_for(&self, command: Pairs<Rule>, coords: Coords) -> Result<AstNode, Error> {
     let mut iter = command.into_iter();
     let name = iter.next().unwrap();
-    let mut range = iter.next().unwrap().into_inner().into_iter();
+    let mut range = iter.next().unwrap().into_inner().peekable();
+    let from = self.build_ast_from_expression(range.next().unwrap())?;
+    let exclusive = range.next_if(|pair| pair.as_rule() == Rule::exclusive).is_some();
     Ok(AstNode{statement: AstStatement::For { 
         val:  self.build_ast_from_ident(name).unwrap(), 
-        from: self.build_ast_from_expression(range.next().unwrap())?, 
+        from,
         to: self.build_ast_from_expression(range.next().unwrap())?,
-        block: self.build_ast_from_block(iter.next().unwrap().into_inner().into_iter().next().unwrap().into_inner())?
+        step: range.next().map(|step| self.build_ast_from_expression(step).map(Box::new)).transpose()?,
+        exclusive,
+        block: self.build_ast_from_block(iter.next().unwrap().into_inner().next().unwrap().into_inner())?
+    }, coords})
+}
+
+fn build_ast_from_for_each(&self, command: Pairs<Rule>, coords: Coords) -> Result<AstNode, Error> {
+    let mut names = vec![];
+    let mut iter = command.into_iter().peekable();
+    while let Some(name) = iter.next_if(|pair| pair.as_rule() == Rule::ident) {
+        names.push(self.build_ast_from_ident(name).unwrap());
+    }
+    let val = names.pop().unwrap();
+    Ok(AstNode{statement: AstStatement::ForEach {
+        index: names.pop(),
+        val,
+        iterable: self.build_ast_from_expression(iter.next().unwrap())?,
+        block: self.build_ast_from_block(iter.next().unwrap().into_inner().next().unwrap().into_inner())?
     }, coords})
 }
 
@@ -467,8 +445,14 @@ fn build_ast_from_value(&self, val: Pair<Rule>) -> Result<BaseValue, Error> {
         Rule::integer => Ok(BaseValueType::Int(val.as_str().parse::<i32>().unwrap())),
         Rule::decimal => Ok(BaseValueType::Float(val.as_str().parse::<f32>().unwrap())),
         Rule::boolean => Ok(BaseValueType::Bool(val.as_str() == "true")),
+        // 'it''s' is the text it's
+        Rule::string => {
+            let quoted = val.as_str();
+            Ok(BaseValueType::StringVal(quoted[1..quoted.len() - 1].replace("''", "'")))
+        },
         Rule::color   => {return self.build_ast_from_color(val);},
         Rule::key     => {return self.build_ast_from_key(val);},
+        Rule::math_const => Ok(BaseValueType::Float(std::f32::consts::PI)),
         Rule::noun   => Ok(BaseValueType::Id(self.build_ast_from_noun(val)?)),
         Rule::array_literal => {
             let mut elements = vec![];
@@ -477,33 +461,48 @@ fn build_ast_from_value(&self, val: Pair<Rule>) -> Result<BaseValue, Error> {
             }
             Ok(BaseValueType::Array(elements))
         },
+        Rule::struct_literal => self.build_ast_from_struct_literal(val),
+        // Return types are looked up by the type checker, which knows the builtins
         Rule::function_call => {
-            let coords = coords!(val);
-            let mut iter = val.into_inner().into_iter();
+            let mut iter = val.into_inner();
             let name = self.build_ast_from_ident(iter.next().unwrap())?;
             let args = self.build_ast_from_arglist(iter)?;
-            if let Some((_, return_type)) = self.function_signatures.get(&name) {
-                if let Some(typ) = return_type {
-                    Ok(BaseValueType::FunctionCall(name, args, typ.clone()))
-                } else {
-                    Err(Error::type_er(format!("Function {} has no return type", name), coords))
-                }
-            } else {
-                Err(Error::type_er(format!("Unknown function {}", name), coords))
-            }
+            Ok(BaseValueType::FunctionCall(name, args))
         }
         _ => return Err(Error::parse(String::from("Expected a value!"), coords!(val)))
     }?;
-    Ok(BaseValue{val: v, coords: coords})
+    Ok(BaseValue{val: v, coords})
 }
 
-fn build_ast_from_simple_value(&self, val: Pair<Rule>) -> Result<SimpleValue, Error> {
-    let coords = coords!(val);
-    match val.as_rule() {
-        Rule::integer => Ok(SimpleValue{val:SimpleValueType::Int(val.as_str().parse::<i32>().unwrap()), coords: coords}),
-        Rule::noun   => Ok(SimpleValue{val:SimpleValueType::Id(self.build_ast_from_noun(val)?), coords: coords}),
-        _ => return Err(Error::parse(String::from("Expected a simple value!"), coords!(val)))
+// Every field has to be given exactly once, they may come in any order
+fn build_ast_from_struct_literal(&self, literal: Pair<Rule>) -> Result<BaseValueType, Error> {
+    let coords = coords!(literal);
+    let mut iter = literal.into_inner();
+    let name = self.build_ast_from_ident(iter.next().unwrap())?;
+    let Some(declared) = self.structs.get(&name) else {
+        return Err(Error::type_er(format!("Unknown struct {}", name), coords));
+    };
+    let mut given: Vec<(String, Expression)> = vec![];
+    for field in iter {
+        let field_coords = coords!(field);
+        let mut field_iter = field.into_inner();
+        let field_name = self.build_ast_from_ident(field_iter.next().unwrap())?;
+        if !declared.iter().any(|(declared_name, _)| *declared_name == field_name) {
+            return Err(Error::type_er(format!("Struct {} has no field '{}'", name, field_name), field_coords));
+        }
+        if given.iter().any(|(given_name, _)| *given_name == field_name) {
+            return Err(Error::type_er(format!("Field '{}' is given twice", field_name), field_coords));
+        }
+        given.push((field_name, self.build_ast_from_expression(field_iter.next().unwrap())?));
     }
+    let mut fields = vec![];
+    for (field_name, _) in declared {
+        match given.iter().position(|(given_name, _)| given_name == field_name) {
+            Some(position) => fields.push(given.remove(position)),
+            None => return Err(Error::type_er(format!("Struct {} literal is missing the field '{}'", name, field_name), coords)),
+        }
+    }
+    Ok(BaseValueType::StructLiteral(name, fields))
 }
 
 fn build_ast_from_color(&self, val: Pair<Rule>) -> Result<BaseValue, Error> {
@@ -562,7 +561,7 @@ fn build_ast_from_color(&self, val: Pair<Rule>) -> Result<BaseValue, Error> {
         "Color::White"      => Ok(BaseValueType::Color(255, 255, 255, 255)),
         "Color::Background" => Ok(BaseValueType::Color( 10,  15,  31, 255)),
         "Color::Transparent" => Ok(BaseValueType::Color(  0,   0,   0,   0)),
-        "Color::Random" => Ok(BaseValueType::FunctionCall(String::from("Color::Random"), vec![], Type::typ(BaseType::Color))),
+        "Color::Random" => Ok(BaseValueType::FunctionCall(String::from("Color::Random"), vec![])),
         col => Err(Error::parse(format!("Unknown color: {}", col), coords!(val)))
     }?;
     Ok(BaseValue { val: v, coords: coords!(val) })
@@ -575,20 +574,20 @@ fn build_ast_from_key(&self, val: Pair<Rule>) -> Result<BaseValue, Error> {
             return Ok(BaseValue{val: BaseValueType::Int(num), coords: coords!(val)});
         }
     }
-    return Err(Error::parse(format!("Unknown key: {}", val.as_str()), coords!(val)));
+    Err(Error::parse(format!("Unknown key: {}", val.as_str()), coords!(val)))
 }
 
 fn build_ast_from_while(&self, command: Pairs<Rule>, coords: Coords) -> Result<AstNode, Error> {
     let mut iter = command.into_iter();
     Ok(AstNode{statement: AstStatement::While { 
         clause: self.build_ast_from_expression(iter.next().unwrap())?, 
-        block: self.build_ast_from_block(iter.next().unwrap().into_inner().into_iter().next().unwrap().into_inner())?,
+        block: self.build_ast_from_block(iter.next().unwrap().into_inner().next().unwrap().into_inner())?,
     }, coords})
 }
 
 fn build_ast_from_array_type(&self, type_val: Pairs<Rule>) -> Result<TypeName, Error> {
     
-    let mut iter = type_val.into_iter().next().unwrap().into_inner().into_iter();
+    let mut iter = type_val.into_iter().next().unwrap().into_inner();
     let inner_type = self.build_ast_from_type(iter.next().unwrap())?;
     let val = iter.next().unwrap();
     let coords = coords!(val);
@@ -596,9 +595,9 @@ fn build_ast_from_array_type(&self, type_val: Pairs<Rule>) -> Result<TypeName, E
         if array_size <= 0 {
             return Err(Error::parse(String::from("Array size must be greater than 0"), c));
         }
-        return Ok(TypeName::Array(Box::new(Some(inner_type)), array_size as usize));
+        Ok(TypeName::Array(Box::new(Some(inner_type)), array_size as usize))
     } else {
-        return Err(Error::parse(String::from("Expected an integer for array size"), coords));
+        Err(Error::parse(String::from("Expected an integer for array size"), coords))
     }
 }
 
@@ -608,24 +607,32 @@ fn build_ast_from_inner_type(&self, type_val: Pairs<Rule>) -> Result<TypeName, E
         if i.as_rule() == Rule::array_type {
             return self.build_ast_from_array_type(type_val);
         }
+        if i.as_rule() == Rule::list_type {
+            let inner = self.build_ast_from_type(i.into_inner().next().unwrap())?;
+            return Ok(TypeName::List(Box::new(inner)));
+        }
+        if i.as_rule() == Rule::struct_type {
+            return Ok(TypeName::Struct(i.as_str().to_string()));
+        }
     }
     match type_val.as_str() {
         "int" => Ok(TypeName::Primitive(Int)),
         "bool" => Ok(TypeName::Primitive(Bool)),
         "color" => Ok(TypeName::Primitive(Color)),
         "float" => Ok(TypeName::Primitive(Float)),
+        "string" => Ok(TypeName::Primitive(StringType)),
         t => Err(Error::parse(format!("Unknown type: {}", t), coords!(type_val.clone().next().unwrap())))
     }
 }
 
 fn build_ast_from_type(&self, type_val: Pair<Rule>) -> Result<Type, Error> {
-    let mut whole = type_val.clone().into_inner().into_iter();
+    let mut whole = type_val.clone().into_inner();
     if let Some(first) = whole.next(){
         if first.as_rule() == Rule::const_key {
             return Ok(Type{type_name: self.build_ast_from_inner_type(whole)?, is_const: true});
         }
     }
-    Ok(Type{type_name: self.build_ast_from_inner_type(type_val.into_inner().into_iter())?, is_const: false})
+    Ok(Type{type_name: self.build_ast_from_inner_type(type_val.into_inner())?, is_const: false})
 
 }
 
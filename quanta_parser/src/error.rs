@@ -8,7 +8,9 @@ pub enum ErrorType {
     ParseError,
     LogicError,
     TypeError,
-    RuntimeError
+    RuntimeError,
+    // A program ran out of its step or command budget
+    LimitError
 }
 
 #[derive(Debug, Clone)]
@@ -55,6 +57,15 @@ impl Error {
             finish: (pos.2, pos.3)
         }
     }
+
+    pub fn limit(mes: String, pos: (usize, usize, usize, usize)) -> Error {
+        Error {
+            error_type: ErrorType::LimitError,
+            message: mes,
+            start: (pos.0, pos.1),
+            finish: (pos.2, pos.3)
+        }
+    }
 }
 
 impl Error {
@@ -66,19 +77,19 @@ impl Error {
         };
         if message.starts_with("expected operator") || message.starts_with("expected box") {
             if c2 >= err.line().len() && !err.line().trim().ends_with(";") {
-                return Error::parse(format!("Probably missing ';'"), (l1, c1, l2, c2));
+                return Error::parse("Probably missing ';'".to_string(), (l1, c1, l2, c2));
             }
             if c2 == 1 {
-                return Error::parse(format!("Probably missing ';' at line"), (l1-1, 1, l2, c2));
+                return Error::parse("Probably missing ';' at line".to_string(), (l1-1, 1, l2, c2));
             }
         }
         if message.starts_with("expected bracket_block") {
-            return Error::parse(format!("Commands for 'if', 'for', 'while' and 'func' should be put inside {{}}."), (l1, c1, l2, c2));
+            return Error::parse("Commands for 'if', 'for', 'while' and 'func' should be put inside {}.".to_string(), (l1, c1, l2, c2));
         }
         if message.starts_with("expected statement") {
-            return Error::parse(format!("Probably missing a ')' or a '}}'"), (l1, c1, l2, c2));
+            return Error::parse("Probably missing a ')' or a '}'".to_string(), (l1, c1, l2, c2));
         }
-        Error::parse(format!("ERROR {} on line '{}'", message.to_string(), err.line()), (l1, c1, l2, c2))
+        Error::parse(format!("ERROR {} on line '{}'", message, err.line()), (l1, c1, l2, c2))
     }
 }
 
@@ -87,16 +98,19 @@ impl fmt::Display for Error {
         let coords = format!("{}:{} - {}:{}", self.start.0, self.start.1, self.finish.0, self.finish.1);
         match &self.error_type {
             ErrorType::ParseError => {
-                write!(f, "{}", format!("Got error during parsing: {} at {}", self.message, coords))
+                write!(f, "Got error during parsing: {} at {}", self.message, coords)
             },
             ErrorType::LogicError => {
-                write!(f, "{}", format!("Got logical error: {} at {}", self.message, coords))
+                write!(f, "Got logical error: {} at {}", self.message, coords)
             },
             ErrorType::TypeError => {
-                write!(f, "{}", format!("Got type checker error: {} at {}", self.message, coords))
+                write!(f, "Got type checker error: {} at {}", self.message, coords)
             }
             ErrorType::RuntimeError=> {
-                write!(f, "{}", format!("Got runtime error: {} at {}", self.message, coords))
+                write!(f, "Got runtime error: {} at {}", self.message, coords)
+            }
+            ErrorType::LimitError => {
+                write!(f, "Program stopped: {} at {}", self.message, coords)
             }
         }
         
@@ -0,0 +1,318 @@
+use pest::{iterators::Pair, Parser};
+
+use crate::{error::Error, QuantaParser, Rule};
+
+const INDENT: &str = "    ";
+
+// Prints programs in one canonical layout. Works on the parse tree rather than the AST,
+// so colors and keys keep their names and comments between statements survive.
+pub fn format_source(source: &str) -> Result<String, Error> {
+    let doc = QuantaParser::parse(Rule::document, source).map_err(Error::from_pest_error)?.next().unwrap();
+    let program = doc.into_inner().next().unwrap();
+    let mut formatter = Formatter { source, lines: vec![], depth: 0, comments: 0 };
+    if program.as_rule() == Rule::forest {
+        formatter.items(program.into_inner().collect(), 0, source.len(), true, Formatter::top_level);
+    } else {
+        formatter.items(program.into_inner().collect(), 0, source.len(), false, Formatter::statement);
+    }
+
+    let expected = source.lines().filter(|line| has_comment(line)).count();
+    if formatter.comments != expected {
+        return Err(Error::parse(String::from("Comments inside a statement can't be formatted, move them to a line of their own"), (0, 0, 0, 0)));
+    }
+    let mut result = formatter.lines.join("\n");
+    result.push('\n');
+    Ok(result)
+}
+
+// `//` inside a string literal doesn't start a comment
+fn has_comment(line: &str) -> bool {
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => quoted = !quoted,
+            '/' if !quoted && chars.peek() == Some(&'/') => return true,
+            _ => {},
+        }
+    }
+    false
+}
+
+struct Formatter<'a> {
+    source: &'a str,
+    lines: Vec<String>,
+    depth: usize,
+    comments: usize,
+}
+
+impl<'a> Formatter<'a> {
+    fn line(&mut self, text: String) {
+        self.lines.push(format!("{}{}", INDENT.repeat(self.depth), text));
+    }
+
+    fn blank_line(&mut self) {
+        if self.lines.last().is_some_and(|line| !line.is_empty()) {
+            self.lines.push(String::new());
+        }
+    }
+
+    // Prints `items` found between `from` and `to` of the source, together with the comments around them.
+    // Single blank lines are kept, `separate` puts one between every two items.
+    fn items<F>(&mut self, items: Vec<Pair<'a, Rule>>, from: usize, to: usize, separate: bool, mut print: F)
+        where F: FnMut(&mut Self, Pair<'a, Rule>)
+    {
+        let mut position = from;
+        let mut printed = false;
+        for item in items {
+            self.gap(position, item.as_span().start(), printed, true, separate && printed);
+            position = item.as_span().end();
+            print(self, item);
+            printed = true;
+        }
+        self.gap(position, to, printed, false, false);
+    }
+
+    fn gap(&mut self, from: usize, to: usize, mut printed: bool, before_item: bool, mut blank: bool) {
+        let mut rest = &self.source[from..to];
+        let mut same_line = true;
+        while let Some(start) = rest.find("//") {
+            let newlines = rest[..start].matches('\n').count();
+            let end = rest[start..].find('\n').map_or(rest.len(), |e| start + e);
+            let comment = rest[start..end].trim_end().to_string();
+            self.comments += 1;
+            if same_line && newlines == 0 && !self.lines.is_empty() {
+                let last = self.lines.last_mut().unwrap();
+                last.push(' ');
+                last.push_str(&comment);
+            } else {
+                if printed && (blank || newlines >= 2) {
+                    self.blank_line();
+                }
+                self.line(comment);
+                blank = false;
+                printed = true;
+            }
+            same_line = false;
+            rest = &rest[end..];
+        }
+        if printed && before_item && (blank || rest.matches('\n').count() >= 2) {
+            self.blank_line();
+        }
+    }
+
+    // `head {`, the statements of `block` and the closing brace. Empty blocks stay on one line.
+    fn braced(&mut self, head: String, attach: bool, block: Pair<'a, Rule>) {
+        let span = block.as_span();
+        if attach {
+            let last = self.lines.last_mut().unwrap();
+            last.push(' ');
+            last.push_str(&head);
+            last.push_str(" {");
+        } else {
+            self.line(format!("{} {{", head));
+        }
+        let (from, to) = (span.start() + self.source[span.start()..].find('{').unwrap() + 1, span.end() - 1);
+        let statements = block.into_inner().filter(|p| p.as_rule() == Rule::block).flat_map(|p| p.into_inner()).collect();
+        self.close(statements, from, to, Formatter::statement);
+    }
+
+    fn close<F>(&mut self, items: Vec<Pair<'a, Rule>>, from: usize, to: usize, print: F)
+        where F: FnMut(&mut Self, Pair<'a, Rule>)
+    {
+        let opened = self.lines.len();
+        self.depth += 1;
+        self.items(items, from, to, false, print);
+        self.depth -= 1;
+        if self.lines.len() == opened {
+            self.lines.last_mut().unwrap().push('}');
+        } else {
+            self.line(String::from("}"));
+        }
+    }
+
+    fn top_level(&mut self, item: Pair<'a, Rule>) {
+        let span = item.as_span();
+        let body_start = span.start() + self.source[span.start()..].find('{').unwrap() + 1;
+        match item.as_rule() {
+            Rule::function => {
+                let mut inner = item.into_inner();
+                let header = self.header(inner.next().unwrap());
+                self.line(format!("{} {{", header));
+                let statements = inner.next().unwrap().into_inner().collect();
+                self.close(statements, body_start, span.end() - 1, Formatter::statement);
+            },
+            Rule::struct_def => {
+                let mut inner = item.into_inner();
+                self.line(format!("struct {} {{", inner.next().unwrap().as_str()));
+                self.close(inner.collect(), body_start, span.end() - 1, Formatter::field);
+            },
+            _ => {
+                self.line(String::from("global {"));
+                self.close(item.into_inner().collect(), body_start, span.end() - 1, Formatter::statement);
+            },
+        }
+    }
+
+    fn field(&mut self, field: Pair<'a, Rule>) {
+        let mut inner = field.into_inner();
+        let typ = self.typ(inner.next().unwrap());
+        self.line(format!("{} {};", typ, inner.next().unwrap().as_str()));
+    }
+
+    fn header(&self, header: Pair<'a, Rule>) -> String {
+        let mut inner = header.into_inner();
+        let name = inner.next().unwrap().as_str();
+        let args: Vec<String> = inner.next().unwrap().into_inner()
+            .map(|arg| {
+                let mut arg = arg.into_inner();
+                format!("{} {}", self.typ(arg.next().unwrap()), arg.next().unwrap().as_str())
+            })
+            .collect();
+        match inner.next() {
+            Some(ret) => format!("func {}({}) -> {}", name, args.join(", "), self.typ(ret)),
+            None => format!("func {}({})", name, args.join(", ")),
+        }
+    }
+
+    fn statement(&mut self, statement: Pair<'a, Rule>) {
+        let statement = if statement.as_rule() == Rule::statement { statement.into_inner().next().unwrap() } else { statement };
+        match statement.as_rule() {
+            Rule::command => {
+                let call = self.expr(statement.into_inner().next().unwrap());
+                self.line(format!("{};", call));
+            },
+            Rule::init_statement | Rule::strong_init => {
+                let mut typ = String::new();
+                let mut assignment = String::new();
+                for part in statement.into_inner() {
+                    if part.as_rule() == Rule::type_name {
+                        typ = format!("{} ", self.typ(part));
+                    } else if part.as_rule() == Rule::compound_assignment {
+                        let mut part = part.into_inner();
+                        let target = self.expr(part.next().unwrap());
+                        assignment = format!("{} {} {}", target, part.next().unwrap().as_str(), self.expr(part.next().unwrap()));
+                    } else if part.as_rule() == Rule::increment {
+                        let mut part = part.into_inner();
+                        assignment = format!("{}{}", self.expr(part.next().unwrap()), part.next().unwrap().as_str());
+                    } else {
+                        let mut part = part.into_inner();
+                        assignment = format!("{} = {}", self.expr(part.next().unwrap()), self.expr(part.next().unwrap()));
+                    }
+                }
+                self.line(format!("{}{};", typ, assignment));
+            },
+            Rule::if_statement => self.if_chain(statement, false),
+            Rule::for_statement => {
+                let mut inner = statement.into_inner();
+                let var = inner.next().unwrap().as_str();
+                let mut range = inner.next().unwrap().into_inner().peekable();
+                let from = self.expr(range.next().unwrap());
+                let dots = if range.next_if(|pair| pair.as_rule() == Rule::exclusive).is_some() { "..<" } else { ".." };
+                let to = self.expr(range.next().unwrap());
+                let step = range.next().map(|step| format!(" step {}", self.expr(step))).unwrap_or_default();
+                self.braced(format!("for {} in ({}{}{}{})", var, from, dots, to, step), false, inner.next().unwrap());
+            },
+            Rule::for_each_statement => {
+                let mut inner = statement.into_inner().peekable();
+                let mut names = vec![];
+                while let Some(name) = inner.next_if(|pair| pair.as_rule() == Rule::ident) {
+                    names.push(name.as_str());
+                }
+                let iterable = self.expr(inner.next().unwrap());
+                self.braced(format!("for {} in {}", names.join(", "), iterable), false, inner.next().unwrap());
+            },
+            Rule::while_statement => {
+                let mut inner = statement.into_inner();
+                let clause = self.expr(inner.next().unwrap());
+                self.braced(format!("while ({})", clause), false, inner.next().unwrap());
+            },
+            Rule::break_statement => self.line(String::from("break;")),
+            Rule::continue_statement => self.line(String::from("continue;")),
+            _ => {
+                let value = self.expr(statement.into_inner().next().unwrap());
+                self.line(format!("return {};", value));
+            },
+        }
+    }
+
+    fn if_chain(&mut self, statement: Pair<'a, Rule>, attach: bool) {
+        let mut inner = statement.into_inner();
+        let clause = self.expr(inner.next().unwrap());
+        let head = if attach { format!("else if ({})", clause) } else { format!("if ({})", clause) };
+        self.braced(head, attach, inner.next().unwrap());
+        match inner.next() {
+            Some(chained) if chained.as_rule() == Rule::if_statement => self.if_chain(chained, true),
+            Some(else_block) => self.braced(String::from("else"), true, else_block),
+            None => {},
+        }
+    }
+
+    fn typ(&self, typ: Pair<'a, Rule>) -> String {
+        let mut result = String::new();
+        for part in typ.into_inner() {
+            match part.as_rule() {
+                Rule::const_key => result.push_str("const "),
+                Rule::array_type => {
+                    let mut inner = part.into_inner();
+                    let elem = self.typ(inner.next().unwrap());
+                    result.push_str(&format!("array<{}, {}>", elem, inner.next().unwrap().as_str()));
+                },
+                Rule::list_type => result.push_str(&format!("list<{}>", self.typ(part.into_inner().next().unwrap()))),
+                _ => result.push_str(part.as_str()),
+            }
+        }
+        result
+    }
+
+    fn expr(&self, expr: Pair<'a, Rule>) -> String {
+        match expr.as_rule() {
+            Rule::expression => {
+                let mut result = String::new();
+                for part in expr.into_inner() {
+                    match part.as_rule() {
+                        Rule::neg | Rule::not => result.push_str(part.as_str()),
+                        Rule::add | Rule::sub | Rule::mul | Rule::div | Rule::rem | Rule::eq | Rule::ne
+                        | Rule::lt | Rule::gt | Rule::le | Rule::ge | Rule::and | Rule::or => result.push_str(&format!(" {} ", part.as_str())),
+                        _ => result.push_str(&self.expr(part)),
+                    }
+                }
+                result
+            },
+            Rule::parenth_expr => format!("({})", self.expr(expr.into_inner().next().unwrap())),
+            Rule::noun => {
+                let mut inner = expr.into_inner();
+                let mut result = inner.next().unwrap().as_str().to_string();
+                for step in inner {
+                    match step.as_rule() {
+                        Rule::field => result.push_str(&format!(".{}", step.into_inner().next().unwrap().as_str())),
+                        _ => result.push_str(&format!("[{}]", self.expr(step.into_inner().next().unwrap()))),
+                    }
+                }
+                result
+            },
+            Rule::function_call => {
+                let mut inner = expr.into_inner();
+                let name = inner.next().unwrap().as_str();
+                let args: Vec<String> = inner.map(|arg| self.expr(arg)).collect();
+                format!("{}({})", name, args.join(", "))
+            },
+            Rule::struct_literal => {
+                let mut inner = expr.into_inner();
+                let name = inner.next().unwrap().as_str();
+                let fields: Vec<String> = inner
+                    .map(|field| {
+                        let mut field = field.into_inner();
+                        format!("{}: {}", field.next().unwrap().as_str(), self.expr(field.next().unwrap()))
+                    })
+                    .collect();
+                format!("{} {{{}}}", name, fields.join(", "))
+            },
+            Rule::array_literal => {
+                let elems: Vec<String> = expr.into_inner().map(|elem| self.expr(elem)).collect();
+                format!("{{{}}}", elems.join(", "))
+            },
+            _ => expr.as_str().to_string(),
+        }
+    }
+}
@@ -3,9 +3,10 @@ use error::Error;
 use pest::Parser;
 use pest_derive::Parser;
 
-use crate::ast::{builder::AstBuilder, AstProgram};
+use crate::ast::{builder::AstBuilder, AstProgram, Expression};
 pub mod ast;
 pub mod error;
+pub mod format;
 
 #[derive(Parser)]
 #[grammar = "../grammar/grammar.pest"]
@@ -20,11 +21,23 @@ pub fn parse_ast(source : &str) -> Result<AstProgram, Error> {
     }
 }
 
+pub fn parse_expression(source : &str) -> Result<Expression, Error> {
+    let mut builder = AstBuilder::new();
+    match QuantaParser::parse(Rule::expression_input, source) {
+        Ok(input) => builder.build_ast_from_expression_input(input),
+        Err(err) => Err(Error::from_pest_error(err))
+    }
+}
+
 
 #[cfg(test)]
 mod tests {
     use std::{fs};
 
+    use proptest::prelude::*;
+
+    use crate::ast::{AstStatement, BaseValue, BaseValueType, ExpressionType, Operator, UnaryOperator};
+
     use super::*;
 
     #[test]
@@ -37,12 +50,10 @@ mod tests {
 func keyboard(int key) {
     if (key == Key::Space) {
         setFigureColor(Color::Blue);
+    } else if (key == Key::A) {
+        setFigureColor(Color::Black);
     } else {
-      if (key == Key::As) {
-          setFigureColor(Color::Black);
-      } else {
-          setFigureColor(Color::Yellow);
-      }
+        setFigureColor(Color::Yellow);
     }
 }
 
@@ -59,21 +70,274 @@ func main() {
         assert!(parse_ast(wrong_text).is_err());
     }
 
+    #[test]
+    fn format_keeps_names_and_comments() {
+        let text = "// waves\nglobal { int  n=3; }\nfunc wave(int x,int y)->int{ return x*y+ -1; }\n\n\n\nfunc main() {\n  setFigureColor(Color::Red); // red\n\n\n  array<int,2> a={1,2};\n  for i in (0..n) { if (a[0]>=i&&true) { circle(i,i,(i+1)*2); } else {} }\n  // done\n}\n";
+        let expected = "// waves\nglobal {\n    int n = 3;\n}\n\nfunc wave(int x, int y) -> int {\n    return x * y + -1;\n}\n\nfunc main() {\n    setFigureColor(Color::Red); // red\n\n    array<int, 2> a = {1, 2};\n    for i in (0..n) {\n        if (a[0] >= i && true) {\n            circle(i, i, (i + 1) * 2);\n        } else {}\n    }\n    // done\n}\n";
+        let formatted = format::format_source(text).unwrap();
+        assert_eq!(formatted, expected);
+        assert_eq!(format::format_source(&formatted).unwrap(), formatted);
+        assert!(format::format_source("circle(1, // radius\n 2, 3);").is_err());
+    }
+
+    #[test]
+    fn else_if_nests_into_the_else_block() {
+        let text = "if (true) {} else  if(false){ circle(1,1,1); }\nelse {}\n";
+        let AstProgram::Block(block) = parse_ast(text).unwrap() else { panic!("Expected a block") };
+        let AstStatement::If { else_block: Some(else_block), .. } = &block.nodes[0].statement else { panic!("Expected an else block") };
+        assert_eq!(else_block.nodes.len(), 1);
+        assert_eq!(else_block.coords, (1, 20, 2, 8));
+        assert!(matches!(else_block.nodes[0].statement, AstStatement::If { else_block: Some(_), .. }));
+        let formatted = format::format_source(text).unwrap();
+        assert_eq!(formatted, "if (true) {} else if (false) {\n    circle(1, 1, 1);\n} else {}\n");
+    }
+
+    #[test]
+    fn loops_can_break_and_continue() {
+        let text = "while (true) { if (false) { continue; } break; }";
+        let AstProgram::Block(block) = parse_ast(text).unwrap() else { panic!("Expected a block") };
+        let AstStatement::While { block, .. } = &block.nodes[0].statement else { panic!("Expected a loop") };
+        assert!(matches!(block.nodes[1].statement, AstStatement::Break));
+        let formatted = format::format_source(text).unwrap();
+        assert_eq!(formatted, "while (true) {\n    if (false) {\n        continue;\n    }\n    break;\n}\n");
+    }
+
+    #[test]
+    fn ranges_keep_their_step_and_end() {
+        let text = "for i in (0 ..< n step 2) {}\nfor x in (1.0..0.0 step -0.5) {}\n";
+        let AstProgram::Block(block) = parse_ast(text).unwrap() else { panic!("Expected a block") };
+        let AstStatement::For { step: Some(_), exclusive: true, .. } = &block.nodes[0].statement else { panic!("Expected a stepped exclusive range") };
+        let AstStatement::For { step: Some(_), exclusive: false, .. } = &block.nodes[1].statement else { panic!("Expected a stepped inclusive range") };
+        let formatted = format::format_source(text).unwrap();
+        assert_eq!(formatted, "for i in (0..<n step 2) {}\nfor x in (1.0..0.0 step -0.5) {}\n");
+    }
+
+    #[test]
+    fn for_in_goes_over_arrays() {
+        let text = "for x in xs {}\nfor i,row in grid[1] { circle(i, row[0], 1); }\nfor x in (0..2) {}\n";
+        let AstProgram::Block(block) = parse_ast(text).unwrap() else { panic!("Expected a block") };
+        let AstStatement::ForEach { index: None, val, .. } = &block.nodes[0].statement else { panic!("Expected a for-in loop") };
+        assert_eq!(val, "x");
+        let AstStatement::ForEach { index: Some(index), .. } = &block.nodes[1].statement else { panic!("Expected an indexed for-in loop") };
+        assert_eq!(index, "i");
+        assert!(matches!(block.nodes[2].statement, AstStatement::For { .. }));
+        let formatted = format::format_source(text).unwrap();
+        assert_eq!(formatted, "for x in xs {}\nfor i, row in grid[1] {\n    circle(i, row[0], 1);\n}\nfor x in (0..2) {}\n");
+        assert!(parse_ast("for i, x in (0..2) {}").is_err());
+    }
+
+    #[test]
+    fn compound_assignments_read_their_target() {
+        let text = "x+=2*y;\ngrid[i][0] --;\n";
+        let AstProgram::Block(block) = parse_ast(text).unwrap() else { panic!("Expected a block") };
+        let AstStatement::Update { val, expr } = &block.nodes[0].statement else { panic!("Expected a compound assignment") };
+        assert_eq!(val.to_string(), "x");
+        assert!(matches!(&expr.expr_type, ExpressionType::Binary(Operator::Plus, current, _) if matches!(&current.expr_type, ExpressionType::Value(BaseValue { val: BaseValueType::Id(_), .. }))));
+        let AstStatement::Update { val, expr } = &block.nodes[1].statement else { panic!("Expected a compound assignment") };
+        assert_eq!(val.to_string(), "grid[i, 0]");
+        assert!(matches!(&expr.expr_type, ExpressionType::Binary(Operator::Minus, _, _)));
+        let formatted = format::format_source(text).unwrap();
+        assert_eq!(formatted, "x += 2 * y;\ngrid[i][0]--;\n");
+        assert!(parse_ast("int x += 1;").is_err());
+    }
+
+    #[test]
+    fn strings_unescape_quotes() {
+        let expr = parse_expression("'it''s // not a comment'").unwrap();
+        assert_eq!(format!("{:?}", expr).matches("StringVal(\"it's // not a comment\")").count(), 1);
+        let text = "string s='a//b'+string(1); // joined\ntext(1,2,s);\n";
+        let formatted = format::format_source(text).unwrap();
+        assert_eq!(formatted, "string s = 'a//b' + string(1); // joined\ntext(1, 2, s);\n");
+    }
+
+    #[test]
+    fn structs_declare_fields_and_literals_follow_them() {
+        let text = "struct Point{int x;  int y;}\nfunc main(){ Point p=Point{y:2,x:1}; ps[0].pos.x=p.x; }\n";
+        let AstProgram::Forest((funcs, _, structs)) = parse_ast(text).unwrap() else { panic!("Expected a forest") };
+        assert_eq!(structs[0].fields.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>(), vec!["x", "y"]);
+        let AstStatement::Init { expr, .. } = &funcs[0].block.nodes[0].statement else { panic!("Expected an init") };
+        let ExpressionType::Value(BaseValue { val: BaseValueType::StructLiteral(_, fields), .. }) = &expr.expr_type else { panic!("Expected a struct literal") };
+        assert_eq!(fields.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>(), vec!["x", "y"]);
+        let AstStatement::SetVal { val, .. } = &funcs[0].block.nodes[1].statement else { panic!("Expected an assignment") };
+        assert_eq!(val.to_string(), "ps[0].pos.x");
+        let formatted = format::format_source(text).unwrap();
+        assert_eq!(formatted, "struct Point {\n    int x;\n    int y;\n}\n\nfunc main() {\n    Point p = Point {y: 2, x: 1};\n    ps[0].pos.x = p.x;\n}\n");
+        assert!(parse_ast("struct A {}\nstruct A {}\n").is_err());
+        assert!(parse_ast("func main() { Point p = Point {x: 1}; }").is_err());
+    }
+
+    #[test]
+    fn operators_bind_by_precedence() {
+        let shape = |source: &str| format!("{:?}", parse_expression(source).unwrap().expr_type);
+        assert!(shape("!a && b").starts_with("Binary(AND"));
+        assert!(shape("-x * y").starts_with("Binary(Mult"));
+        assert!(shape("10 - 4 - 3").starts_with("Binary(Minus, Expression { expr_type: Binary(Minus"));
+        assert!(shape("a || b && c").starts_with("Binary(OR"));
+        assert!(parse_expression("a < b < c").is_err());
+        assert!(parse_expression("a == b != c").is_err());
+        assert!(parse_expression("(a < b) == c").is_ok());
+        let formatted = format::format_source("bool b=!(x<1)&&-y>=2%z;\n").unwrap();
+        assert_eq!(formatted, "bool b = !(x < 1) && -y >= 2 % z;\n");
+    }
+
+    // Expressions built without the parser, printed with only the brackets precedence needs
+    #[derive(Debug, Clone)]
+    enum Reference {
+        Int(i64),
+        Bool(bool),
+        Unary(UnaryOperator, Box<Reference>),
+        Binary(Operator, Box<Reference>, Box<Reference>),
+        Brackets(Box<Reference>),
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum Value {
+        Int(i64),
+        Bool(bool),
+    }
+
+    // Binds tighter the higher it is, the same order the grammar should follow
+    fn level(expr: &Reference) -> u8 {
+        match expr {
+            Reference::Binary(Operator::OR, _, _) => 1,
+            Reference::Binary(Operator::AND, _, _) => 2,
+            Reference::Binary(Operator::Plus | Operator::Minus, _, _) => 4,
+            Reference::Binary(Operator::Mult | Operator::Div | Operator::Mod, _, _) => 5,
+            Reference::Binary(_, _, _) => 3,
+            Reference::Unary(_, _) => 6,
+            _ => 7,
+        }
+    }
+
+    fn print(expr: &Reference) -> String {
+        let operand = |inner: &Reference, needs_brackets: bool| if needs_brackets { format!("({})", print(inner)) } else { print(inner) };
+        match expr {
+            Reference::Int(i) => i.to_string(),
+            Reference::Bool(b) => b.to_string(),
+            Reference::Brackets(inner) => format!("({})", print(inner)),
+            Reference::Unary(op, inner) => {
+                let sign = if *op == UnaryOperator::NOT { "!" } else { "-" };
+                format!("{}{}", sign, operand(inner, level(inner) < 6))
+            },
+            Reference::Binary(op, left, right) => {
+                let sign = match op {
+                    Operator::Plus => "+", Operator::Minus => "-", Operator::Mult => "*", Operator::Div => "/", Operator::Mod => "%",
+                    Operator::EQ => "==", Operator::NQ => "!=", Operator::LT => "<", Operator::GT => ">", Operator::LQ => "<=", Operator::GQ => ">=",
+                    Operator::AND => "&&", Operator::OR => "||",
+                };
+                // Left-associative, so a right operand on the same level keeps its brackets
+                format!("{} {} {}", operand(left, level(left) < level(expr)), sign, operand(right, level(right) <= level(expr)))
+            },
+        }
+    }
+
+    fn apply(op: Operator, left: Value, right: Value) -> Option<Value> {
+        match (left, right) {
+            (Value::Int(a), Value::Int(b)) => match op {
+                Operator::Plus => a.checked_add(b).map(Value::Int),
+                Operator::Minus => a.checked_sub(b).map(Value::Int),
+                Operator::Mult => a.checked_mul(b).map(Value::Int),
+                Operator::Div => a.checked_div(b).map(Value::Int),
+                Operator::Mod => a.checked_rem(b).map(Value::Int),
+                Operator::EQ => Some(Value::Bool(a == b)),
+                Operator::NQ => Some(Value::Bool(a != b)),
+                Operator::LT => Some(Value::Bool(a < b)),
+                Operator::GT => Some(Value::Bool(a > b)),
+                Operator::LQ => Some(Value::Bool(a <= b)),
+                Operator::GQ => Some(Value::Bool(a >= b)),
+                _ => None,
+            },
+            (Value::Bool(a), Value::Bool(b)) => match op {
+                Operator::AND => Some(Value::Bool(a && b)),
+                Operator::OR => Some(Value::Bool(a || b)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn unary(op: UnaryOperator, value: Value) -> Option<Value> {
+        match (op, value) {
+            (UnaryOperator::UnaryMinus, Value::Int(i)) => i.checked_neg().map(Value::Int),
+            (UnaryOperator::NOT, Value::Bool(b)) => Some(Value::Bool(!b)),
+            _ => None,
+        }
+    }
+
+    fn evaluate_reference(expr: &Reference) -> Option<Value> {
+        match expr {
+            Reference::Int(i) => Some(Value::Int(*i)),
+            Reference::Bool(b) => Some(Value::Bool(*b)),
+            Reference::Brackets(inner) => evaluate_reference(inner),
+            Reference::Unary(op, inner) => unary(*op, evaluate_reference(inner)?),
+            Reference::Binary(op, left, right) => apply(*op, evaluate_reference(left)?, evaluate_reference(right)?),
+        }
+    }
+
+    fn evaluate(expr: &Expression) -> Option<Value> {
+        match &expr.expr_type {
+            ExpressionType::Value(BaseValue { val: BaseValueType::Int(i), .. }) => Some(Value::Int(*i as i64)),
+            ExpressionType::Value(BaseValue { val: BaseValueType::Bool(b), .. }) => Some(Value::Bool(*b)),
+            ExpressionType::Value(_) => None,
+            ExpressionType::Unary(UnaryOperator::Parentheses, inner) => evaluate(inner),
+            ExpressionType::Unary(op, inner) => unary(*op, evaluate(inner)?),
+            ExpressionType::Binary(op, left, right) => apply(*op, evaluate(left)?, evaluate(right)?),
+        }
+    }
+
+    fn bracketed(inner: Reference) -> Reference {
+        Reference::Brackets(inner.into())
+    }
+
+    fn int_expression() -> impl Strategy<Value = Reference> {
+        (0..20i64).prop_map(Reference::Int).prop_recursive(5, 32, 2, |inner| prop_oneof![
+            inner.clone().prop_map(|e| Reference::Unary(UnaryOperator::UnaryMinus, e.into())),
+            inner.clone().prop_map(bracketed),
+            (prop::sample::select(vec![Operator::Plus, Operator::Minus, Operator::Mult, Operator::Div, Operator::Mod]), inner.clone(), inner)
+                .prop_map(|(op, l, r)| Reference::Binary(op, l.into(), r.into())),
+        ])
+    }
+
+    fn bool_expression() -> impl Strategy<Value = Reference> {
+        let comparison = (prop::sample::select(vec![Operator::EQ, Operator::NQ, Operator::LT, Operator::GT, Operator::LQ, Operator::GQ]), int_expression(), int_expression())
+            .prop_map(|(op, l, r)| Reference::Binary(op, l.into(), r.into()));
+        prop_oneof![any::<bool>().prop_map(Reference::Bool), comparison].prop_recursive(4, 24, 2, |inner| prop_oneof![
+            inner.clone().prop_map(|e| Reference::Unary(UnaryOperator::NOT, e.into())),
+            inner.clone().prop_map(bracketed),
+            (prop::sample::select(vec![Operator::AND, Operator::OR]), inner.clone(), inner)
+                .prop_map(|(op, l, r)| Reference::Binary(op, l.into(), r.into())),
+        ])
+    }
+
+    proptest! {
+        #[test]
+        fn ints_parse_like_the_reference(expr in int_expression()) {
+            let source = print(&expr);
+            let parsed = parse_expression(&source).unwrap();
+            prop_assert_eq!(evaluate(&parsed), evaluate_reference(&expr), "{}", source);
+        }
+
+        #[test]
+        fn bools_parse_like_the_reference(expr in bool_expression()) {
+            let source = print(&expr);
+            let parsed = parse_expression(&source).unwrap();
+            prop_assert_eq!(evaluate(&parsed), evaluate_reference(&expr), "{}", source);
+        }
+    }
+
     #[test]
     fn test_file() {
         let file_path = "../grammar/test.txt";
 
         let contents = fs::read_to_string(file_path)
             .expect("Should have been able to read the file");
-        assert!(contents.len() > 0);
+        assert!(!contents.is_empty());
         let res = parse_ast(contents.as_str());
-        match &res {
-            Ok(_ast) => {},
-            Err(Error::ParseError{message}) => {println!("{}", message.to_string())}
-            Err(Error::LogicError{message}) => {println!("{}", message.to_string())}
-            Err(Error::TypeError{message}) => {println!("{}", message.to_string())}
-            Err(Error::RuntimeError{message}) => {println!("{}", message.to_string())}
+        if let Err(err) = &res {
+            println!("{}", err);
         }
         assert!(res.is_ok());
+        let formatted = format::format_source(contents.as_str()).unwrap();
+        assert_eq!(format::format_source(&formatted).unwrap(), formatted);
     }
 }
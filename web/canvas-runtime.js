@@ -12,8 +12,6 @@ let isCancelled = false;
 const CANVAS_W = 1000, CANVAS_H = 1000;
 const DPR = Math.max(1, Math.min(3, window.devicePixelRatio || 1));
 
-let randomColors = [];
-
 drawCanvas.width  = Math.floor(CANVAS_W * DPR);
 drawCanvas.height = Math.floor(CANVAS_H * DPR);
 bufferCanvas.width = drawCanvas.width;
@@ -25,9 +23,14 @@ export function log(text) {
   logEl.textContent = text;
 }
 
+// Text from print()/println(), kept until the next run
+export function printOutput(text) {
+  if (text && logEl) logEl.textContent += text;
+}
+
 export function setup() {
-  randomColors = [];
   clearCanvas();
+  if (logEl) logEl.textContent = '';
 }
 
 export function checkIsCancelled() {
@@ -50,80 +53,43 @@ function clearCanvas(color = '#0a0f1f') {
   ctx.restore();
 }
 
-function toPx(val, axis) {
-  if (typeof val === 'string' && val.endsWith('%')) {
-    const p = parseFloat(val) / 100;
-    return (axis === 'x' ? CANVAS_W : CANVAS_H) * p;
-  }
-  return +val;
-}
-
-function randomColorString() {
-  const r = Math.floor(255 * Math.random());
-  const g = Math.floor(255 * Math.random());
-  const b = Math.floor(255 * Math.random());
-  return `rgb(${r},${g},${b})`;
-}
+// Must match PROTOCOL_VERSION in quanta-lang/src/utils/command.rs
+const PROTOCOL_VERSION = 2;
 
 const deg2rad = d => (d * Math.PI) / 180;
-function applyStyle(opts){ ctx.lineWidth = opts.width ?? 1; if (opts.stroke) ctx.strokeStyle = opts.stroke; if (opts.fill) ctx.fillStyle = opts.fill; }
-function parseOptions(tokens, startIdx){ 
-    const o={}; for(let i=startIdx;i<tokens.length;i++){ 
-        const t=tokens[i], eq=t.indexOf('='); 
-          if(eq>0){ 
-            let k=t.slice(0,eq), v=t.slice(eq+1); 
-            if (v.startsWith('RandomColor')) {
-              let idx = parseInt(v.slice(11));
-              if (isNaN(idx)) {
-                randomColors.push(randomColorString());
-                idx = randomColors.length - 1;
-              }
-              while (idx >= randomColors.length) {
-                randomColors.push(randomColorString());
-              }
-              v = randomColors[idx];
-            }
-            if(k==='width') o.width=Number(v); 
-            else if(k==='stroke') o.stroke=v; 
-            else if(k==='fill') o.fill=v; 
-            else if(k==='ccw') o.ccw=/^(1|true|yes)$/i.test(v);
-          } 
-    }
-    return o; 
-}
-function tokenize(line){ return line.trim().split(/\s+/).filter(Boolean); }
-
-function drawCircle(cx, cy, r, o){ ctx.beginPath(); ctx.arc(toPx(cx,'x'), toPx(cy,'y'), toPx(r,'x'), 0, Math.PI*2); if(o.fill) ctx.fill(); if(o.stroke||!o.fill) ctx.stroke(); }
-function drawRect(x,y,w,h,o){ const X=toPx(x,'x'),Y=toPx(y,'y'),W=toPx(w,'x'),H=toPx(h,'y'); if(o.fill) ctx.fillRect(X,Y,W-X,H-Y); if(o.stroke||!o.fill) ctx.strokeRect(X,Y,W-X,H-Y); }
-function drawLine(x1,y1,x2,y2,o){ ctx.beginPath(); ctx.moveTo(toPx(x1,'x'), toPx(y1,'y')); ctx.lineTo(toPx(x2,'x'), toPx(y2,'y')); ctx.stroke(); }
-function drawPolygon(nums,o){ if(nums.length<4) return; ctx.beginPath(); ctx.moveTo(toPx(nums[0],'x'), toPx(nums[1],'y')); for(let i=2;i<nums.length;i+=2) ctx.lineTo(toPx(nums[i],'x'), toPx(nums[i+1],'y')); ctx.closePath(); if(o.fill) {ctx.fill()}; if(o.stroke||!o.fill) ctx.stroke(); }
-function drawArc(cx,cy,r,a0,a1,ccw,o){ ctx.beginPath(); ctx.arc(toPx(cx,'x'), toPx(cy,'y'), toPx(r,'x'), deg2rad(a0), deg2rad(a1), !!ccw); if(o.fill) ctx.fill(); if(o.stroke||!o.fill) ctx.stroke(); }
-
-export function drawScript(script, should_draw_frame=false){
+function applyStyle(style){ ctx.lineWidth = style.width ?? 1; if (style.stroke) ctx.strokeStyle = style.stroke; if (style.fill) ctx.fillStyle = style.fill; }
+function paint(style){ if(style.fill) ctx.fill(); if(style.width > 0) ctx.stroke(); }
+
+function drawCircle(c){ ctx.beginPath(); ctx.arc(c.x, c.y, Math.max(0, c.r), 0, Math.PI*2); paint(c.style); }
+function drawRect(c){ const w=c.x2-c.x1, h=c.y2-c.y1; ctx.fillRect(c.x1,c.y1,w,h); if(c.style.width > 0) ctx.strokeRect(c.x1,c.y1,w,h); }
+function drawLine(c){ ctx.beginPath(); ctx.moveTo(c.x1, c.y1); ctx.lineTo(c.x2, c.y2); if(c.style.width > 0) ctx.stroke(); }
+function drawPolygon(c){ const pts=c.points; if(pts.length<2) return; ctx.beginPath(); ctx.moveTo(pts[0][0], pts[0][1]); for(let i=1;i<pts.length;i++) ctx.lineTo(pts[i][0], pts[i][1]); ctx.closePath(); paint(c.style); }
+function drawText(c){ ctx.font = `${c.style.size}px ${c.style.font}`; ctx.textBaseline = 'top'; ctx.fillStyle = c.style.fill; ctx.fillText(c.text, c.x, c.y); }
+function drawArc(c){ ctx.beginPath(); ctx.arc(c.x, c.y, Math.max(0, c.r), deg2rad(c.start), deg2rad(c.end), false); paint(c.style); }
+
+// `json` is CommandBlock.get_commands_json(): {"version":2,"commands":["frame", {"circle":{...}}, ...]}
+export function drawScript(json, should_draw_frame=false){
+  const stream = typeof json === 'string' ? JSON.parse(json) : { version: PROTOCOL_VERSION, commands: [] };
+  if (stream.version !== PROTOCOL_VERSION) {
+    throw new Error(`Unsupported command stream version ${stream.version}, expected ${PROTOCOL_VERSION}`);
+  }
   ctx.save(); ctx.lineJoin='round'; ctx.lineCap='round';
-  const lines = String(script||'').split(/,/);
-  //bufferCanvas.width = drawCanvas.width;
-  //bufferCanvas.height = drawCanvas.height;
-  for (const raw of lines) {
+  for (const command of stream.commands) {
     if (isCancelled) { return; }
-    const line = raw.trim();
-    if (!line || line.startsWith('//')) continue;
-    const tok = tokenize(line); if (!tok.length) continue;
-    const cmd = tok[0].toLowerCase();
+    const [kind, c] = typeof command === 'string' ? [command, {}] : Object.entries(command)[0];
     try {
-      switch (cmd) {
-        case 'circle': { const [_, cx, cy, r] = tok; const o=parseOptions(tok,4); applyStyle(o); drawCircle(cx,cy,r,o); console.log("Drawing line: " + raw); break; }
-        case 'rectangle': { const [_, x, y, w, h] = tok; const o=parseOptions(tok,5); applyStyle(o); drawRect(x,y,w,h,o); console.log("Drawing line: " + raw); break; }
-        case 'line': { const [_, x1, y1, x2, y2] = tok; const o=parseOptions(tok,5); applyStyle(o); drawLine(x1,y1,x2,y2,o); console.log("Drawing line: " + raw); break; }
-        case 'polygon': { const nums=[]; let i=1; for(;i<tok.length;i++){ if(tok[i].includes('=')) break; nums.push(Number(tok[i])); } const o=parseOptions(tok,i); applyStyle(o); drawPolygon(nums,o); console.log("Drawing line: " + raw); break; }
-        case 'arc': { const [_, cx, cy, r, a0, a1] = tok; const o=parseOptions(tok,6); applyStyle(o); drawArc(cx,cy,r,Number(a0),Number(a1),!!o.ccw,o); console.log("Drawing line: " + raw); break; }
-        case 'bg': case 'background': { const color = tok[1] || '#0a0f1f'; clearCanvas(color); console.log("Drawing line: " + raw); break; }
-        case 'animate': {isAnimation = true; console.log("GOT ANIMATION HERE! " + isAnimation); console.log("Drawing line: " + raw); break;}
-        case 'clear': {clearCanvas(); console.log("Drawing line: " + raw); break; }
-        case 'error': {alert("Error: " + raw); break; }
-        default: /* ignore unknown */ break;
+      switch (kind) {
+        case 'circle': { applyStyle(c.style); drawCircle(c); break; }
+        case 'rectangle': { applyStyle(c.style); drawRect(c); break; }
+        case 'line': { applyStyle(c.style); drawLine(c); break; }
+        case 'polygon': { applyStyle(c.style); drawPolygon(c); break; }
+        case 'arc': { applyStyle(c.style); drawArc(c); break; }
+        case 'text': { drawText(c); break; }
+        case 'animate': { isAnimation = true; break; }
+        case 'clear': { clearCanvas(); break; }
+        default: /* sleep/frame/end are handled by the run loop */ break;
       }
-    } catch (e) { console.warn('Error:', line, e); }
+    } catch (e) { console.warn('Error:', command, e); }
   }
   ctx.restore();
   if (!isAnimation || should_draw_frame) {
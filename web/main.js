@@ -28,15 +28,15 @@ import {
 } from "@codemirror/autocomplete"
 import { linter, setDiagnostics } from "@codemirror/lint";
 // Language support (your Lezer parser compiled to quanta.js)
-import { quanta, quantaSyntax, quantaLanguageSupport } from "./quanta-support.ts";
+import { quanta, quantaSyntax, quantaLanguageSupport, builtinCompletion } from "./quanta-support.ts";
 
 import { quantaTheme } from "./custom-theme";
 
 // Canvas runtime (drawScript + utilities)
-import { drawScript, setup, checkIsCancelled, cancelNow } from "./canvas-runtime.js";
+import { drawScript, printOutput, setup, checkIsCancelled, cancelNow } from "./canvas-runtime.js";
 
 // WASM glue (wasm-pack output); adjust crate name/path
-import initWasm, { Compiler } from "../quanta-lang/pkg/quanta_lang.js"; 
+import initWasm, { Compiler, builtins_json } from "../quanta-lang/pkg/quanta_lang.js"; 
 //import { rustHighlighting } from "../grammar/highlight.js";
 
 const runBtn = document.getElementById("runBtn");
@@ -57,6 +57,7 @@ const insertFourSpaces = keymap.of([{
 }]);
 
 const fontSizeCompartment = new Compartment();
+const builtinsCompartment = new Compartment();
 
 const newlineSameIndent = keymap.of([{
   key: "Enter",
@@ -275,6 +276,7 @@ const editor = new EditorView({
     fourSpaceIndent,
     newlineSameIndent,
     fontSizeCompartment.of(fontSizeTheme(currentFontSize)),
+    builtinsCompartment.of([]),
     fontSizeKeys,
     keymap.of([
       // Closed-brackets aware backspace
@@ -305,6 +307,13 @@ const editor = new EditorView({
   parent: document.getElementById("editor")
 });
 
+// The builtins are known once the compiler is loaded
+initWasm().then(() => {
+  editor.dispatch({
+    effects: builtinsCompartment.reconfigure(builtinCompletion(JSON.parse(builtins_json())))
+  });
+});
+
 function clearErrors() {
   editor.dispatch(setDiagnostics(editor.state, []));
 }
@@ -377,8 +386,8 @@ function doRun() {
         for (let i = 0; i < blocks.length; i++) {
           if (checkIsCancelled()) { return; }
           const block = blocks[i];
-          let commands = block.get_commands();
-          drawScript(commands, block.should_draw_frame);
+          drawScript(block.get_commands_json(), block.should_draw_frame);
+          printOutput(block.get_output());
           let blockStatus = block.get_status();
           if (blockStatus == 3) { // Error
             const err = runtime.get_runtime_error();
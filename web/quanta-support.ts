@@ -145,8 +145,7 @@ export const QuantaLanguage = LRLanguage.define({
 
 
 
-export const quantaCompletion = QuantaLanguage.data.of({
-  autocomplete: completeFromList([
+const keywordCompletions = [
     {label: "bool", type: "keyword"},
     {label: "int", type: "keyword"},
     {label: "float", type: "keyword"},
@@ -196,15 +195,21 @@ export const quantaCompletion = QuantaLanguage.data.of({
   {label: "Background", type: "keyword"},
   {label: "Transparent", type: "keyword"},
   {label: "Random", type: "keyword"},
+]
 
-    {label: "circle", type: "function"},
-    {label: "rectangle", type: "function"},
-    {label: "line", type: "function"},
-    {label: "setLineColor", type: "function"},
-    {label: "setFigureColor", type: "function"},
-  ])
+export const quantaCompletion = QuantaLanguage.data.of({
+  autocomplete: completeFromList(keywordCompletions)
 })
 
+// Builtins as given by `builtins_json()` of the compiler, so the list never goes out of date
+export function builtinCompletion(builtins: {name: string, signature: string, doc: string}[]) {
+  return QuantaLanguage.data.of({
+    autocomplete: completeFromList(builtins
+      .filter(builtin => !builtin.name.includes("::"))
+      .map(builtin => ({label: builtin.name, type: "function", detail: builtin.signature, info: builtin.doc})))
+  })
+}
+
 export function quanta() {
   return new LanguageSupport(QuantaLanguage, [quantaSyntax, quantaCompletion])
 }